@@ -0,0 +1,289 @@
+//! Generates and lights a region of a world ahead of time, saves it in the world save
+//! format and prints statistics about the generated terrain.
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Result};
+use clap::Parser;
+use mycraft_world::{
+    blocks::{block_names, load_texture_names, BlockRegistry},
+    changed_faces,
+    generation::{Biome, Generator, StructureRegistry},
+    recalculate_light, BlockCoords, Chunk, ChunkCoords, ChunkNeighborhood, WorldBounds,
+    WorldMetadata, WorldStorage,
+};
+
+/// Pre-generates the chunk columns from `--from` to `--to`, both included, between the
+/// bounds of the world. The chunks around the range are not generated, so no light comes
+/// from them. The chunks above the terrain are neither generated nor saved, the world
+/// shares one chunk of air between them when loading them.
+#[derive(Parser)]
+struct Args {
+    /// Seed of the terrain generator, an existing world keeps its own seed. Random if
+    /// not given.
+    #[arg(long)]
+    seed: Option<u32>,
+    /// Height of the lowest blocks of a new world, a multiple of 16
+    #[arg(long, allow_hyphen_values = true)]
+    min_height: Option<i32>,
+    /// Height above the highest blocks of a new world, a multiple of 16
+    #[arg(long, allow_hyphen_values = true)]
+    max_height: Option<i32>,
+    /// Chunk column where the range starts, as "x,z"
+    #[arg(long, value_parser = parse_column, allow_hyphen_values = true)]
+    from: (i32, i32),
+    /// Chunk column where the range ends, as "x,z"
+    #[arg(long, value_parser = parse_column, allow_hyphen_values = true)]
+    to: (i32, i32),
+    /// Directory the world is saved to
+    #[arg(long)]
+    output: PathBuf,
+    /// Directory containing the block and structure definitions
+    #[arg(long, default_value = "./res")]
+    res: PathBuf,
+}
+
+fn parse_column(value: &str) -> Result<(i32, i32)> {
+    let (x, z) = value
+        .split_once(',')
+        .ok_or_else(|| anyhow!("Expected a chunk column as \"x,z\""))?;
+    Ok((x.trim().parse()?, z.trim().parse()?))
+}
+
+/// Time spent in each stage.
+#[derive(Default)]
+struct Timings {
+    generation: Duration,
+    lighting: Duration,
+    saving: Duration,
+}
+
+fn main() -> Result<()> {
+    env_logger::init();
+
+    let args = Args::parse();
+    let texture_names = load_texture_names(&args.res.join("textures").join("blocks"))?;
+    BlockRegistry::load(&args.res.join("blocks.ron"), &texture_names)?.install()?;
+    StructureRegistry::load(&args.res.join("structures.ron"))?.install()?;
+
+    let bounds = WorldBounds::from_heights(args.min_height, args.max_height)?;
+
+    let metadata = WorldMetadata::load_or_create(&args.output, args.seed, bounds)?;
+    if args.seed.is_some_and(|seed| seed != metadata.seed) {
+        bail!(
+            "{} was created with the seed {}, the chunks would not match",
+            args.output.display(),
+            metadata.seed
+        );
+    }
+    if bounds.is_some_and(|bounds| bounds != metadata.bounds) {
+        bail!(
+            "{} was created with the bounds {}..{}",
+            args.output.display(),
+            metadata.bounds.min_y,
+            metadata.bounds.max_y
+        );
+    }
+    println!(
+        "Seed {}, heights {}..{}",
+        metadata.seed, metadata.bounds.min_y, metadata.bounds.max_y
+    );
+
+    let generator = Generator::new(metadata.seed, metadata.bounds)?;
+
+    let (min_x, max_x) = (args.from.0.min(args.to.0), args.from.0.max(args.to.0));
+    let (min_z, max_z) = (args.from.1.min(args.to.1), args.from.1.max(args.to.1));
+    let mut coords_in_range = vec![];
+    for x in min_x..=max_x {
+        for z in min_z..=max_z {
+            for y in metadata.bounds.chunk_layers() {
+                let coords = ChunkCoords { x, y, z };
+                if !generator.is_above_terrain(coords) {
+                    coords_in_range.push(coords);
+                }
+            }
+        }
+    }
+    let mut timings = Timings::default();
+
+    println!("Generating {} chunks", coords_in_range.len());
+    let stage_start = Instant::now();
+    let mut chunks: HashMap<ChunkCoords, Arc<Chunk>> = HashMap::new();
+    for &coords in &coords_in_range {
+        let mut chunk = Chunk::new();
+        generator.generate_chunk(&mut chunk, coords);
+        chunks.insert(coords, Arc::new(chunk));
+    }
+    timings.generation = stage_start.elapsed();
+
+    println!("Lighting");
+    let stage_start = Instant::now();
+    let light_passes = light_chunks(&mut chunks, &coords_in_range);
+    timings.lighting = stage_start.elapsed();
+
+    println!("Saving to {}", args.output.display());
+    let stage_start = Instant::now();
+    let mut storage = WorldStorage::new(&args.output)?;
+    for &coords in &coords_in_range {
+        storage.save_chunk(coords, &chunks[&coords])?;
+    }
+    timings.saving = stage_start.elapsed();
+
+    println!();
+    print_timings(&timings, coords_in_range.len(), light_passes);
+    print_block_counts(&chunks);
+    print_heights(&generator, (min_x, min_z), (max_x, max_z));
+    print_biomes(&generator, (min_x, min_z), (max_x, max_z));
+
+    Ok(())
+}
+
+/// Lights the chunks from the top to the bottom, relighting the neighbors whose borders
+/// received light until nothing changes. Returns the number of lighting passes.
+fn light_chunks(chunks: &mut HashMap<ChunkCoords, Arc<Chunk>>, coords: &[ChunkCoords]) -> usize {
+    let mut sorted_coords = coords.to_vec();
+    sorted_coords.sort_by_key(|coords| -coords.y);
+
+    let mut queued: HashSet<ChunkCoords> = sorted_coords.iter().copied().collect();
+    let mut queue: VecDeque<ChunkCoords> = sorted_coords.into();
+    let mut passes = 0;
+
+    while let Some(coords) = queue.pop_front() {
+        queued.remove(&coords);
+        passes += 1;
+
+        let old_chunk = chunks[&coords].clone();
+        let neighbors = ChunkNeighborhood::from_fn(old_chunk.clone(), coords, |coords| {
+            chunks.get(&coords).cloned()
+        });
+        let mut chunk = old_chunk.as_ref().clone();
+        recalculate_light(&mut chunk, &neighbors);
+
+        for direction in changed_faces(&old_chunk, &chunk) {
+            let neighbor_coords = coords + direction;
+            if chunks.contains_key(&neighbor_coords) && queued.insert(neighbor_coords) {
+                queue.push_back(neighbor_coords);
+            }
+        }
+        chunks.insert(coords, Arc::new(chunk));
+    }
+
+    passes
+}
+
+fn print_timings(timings: &Timings, chunk_count: usize, light_passes: usize) {
+    let per_chunk = |duration: Duration| duration / chunk_count.max(1) as u32;
+
+    println!("Stage       Total       Per chunk");
+    for (name, duration) in [
+        ("Generation", timings.generation),
+        ("Lighting", timings.lighting),
+        ("Saving", timings.saving),
+    ] {
+        println!(
+            "{:<11} {:<11.2?} {:.2?}",
+            name,
+            duration,
+            per_chunk(duration)
+        );
+    }
+    println!(
+        "{} lighting passes over {} chunks",
+        light_passes, chunk_count
+    );
+    println!();
+}
+
+fn print_block_counts(chunks: &HashMap<ChunkCoords, Arc<Chunk>>) {
+    let names: Vec<&str> = block_names().collect();
+    let mut counts = vec![0u64; names.len()];
+    for chunk in chunks.values() {
+        for x in 0..Chunk::SIZE {
+            for y in 0..Chunk::SIZE {
+                for z in 0..Chunk::SIZE {
+                    let id: u16 = chunk[BlockCoords { x, y, z }].block_id.into();
+                    counts[id as usize] += 1;
+                }
+            }
+        }
+    }
+
+    let total: u64 = counts.iter().sum();
+    let mut sorted: Vec<(&str, u64)> = names.into_iter().zip(counts).collect();
+    sorted.sort_by_key(|x| std::cmp::Reverse(x.1));
+
+    println!("Block           Count       Share");
+    for (name, count) in sorted.into_iter().filter(|x| x.1 > 0) {
+        println!(
+            "{:<15} {:<11} {:.2}%",
+            name,
+            count,
+            count as f64 / total.max(1) as f64 * 100.
+        );
+    }
+    println!();
+}
+
+fn print_heights(generator: &Generator, min: (i32, i32), max: (i32, i32)) {
+    const BUCKET_SIZE: i32 = 4;
+    const BAR_WIDTH: usize = 40;
+
+    let mut heights = vec![];
+    for x in min.0 * Chunk::SIZE..(max.0 + 1) * Chunk::SIZE {
+        for z in min.1 * Chunk::SIZE..(max.1 + 1) * Chunk::SIZE {
+            heights.push(generator.get_height(x, z));
+        }
+    }
+
+    let lowest = *heights.iter().min().unwrap();
+    let highest = *heights.iter().max().unwrap();
+    let mean = heights.iter().map(|&x| x as f64).sum::<f64>() / heights.len() as f64;
+    println!(
+        "Terrain height: min {}, max {}, mean {:.1}",
+        lowest, highest, mean
+    );
+
+    let mut buckets: HashMap<i32, usize> = HashMap::new();
+    for height in heights {
+        *buckets.entry(height.div_euclid(BUCKET_SIZE)).or_default() += 1;
+    }
+
+    let largest = buckets.values().copied().max().unwrap();
+    for bucket in lowest.div_euclid(BUCKET_SIZE)..=highest.div_euclid(BUCKET_SIZE) {
+        let count = buckets.get(&bucket).copied().unwrap_or(0);
+        let start = bucket * BUCKET_SIZE;
+        println!(
+            "{:>4}..{:<4} {:<8} {}",
+            start,
+            start + BUCKET_SIZE,
+            count,
+            "#".repeat(count * BAR_WIDTH / largest)
+        );
+    }
+}
+
+fn print_biomes(generator: &Generator, min: (i32, i32), max: (i32, i32)) {
+    let mut counts = [0usize; Biome::ALL.len()];
+    for x in min.0 * Chunk::SIZE..(max.0 + 1) * Chunk::SIZE {
+        for z in min.1 * Chunk::SIZE..(max.1 + 1) * Chunk::SIZE {
+            counts[generator.get_biome(x, z) as usize] += 1;
+        }
+    }
+
+    let total: usize = counts.iter().sum();
+    println!();
+    println!("Biome           Columns     Share");
+    for (biome, count) in Biome::ALL.iter().zip(counts) {
+        println!(
+            "{:<15} {:<11} {:.2}%",
+            format!("{:?}", biome),
+            count,
+            count as f64 / total as f64 * 100.
+        );
+    }
+}
@@ -14,4 +14,18 @@ impl Aabb {
                 z: if direction.z > 0. { self.size.z } else { 0. },
             }
     }
+
+    pub fn end(&self) -> Vector3<f32> {
+        self.start + self.size
+    }
+
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        let (end, other_end) = (self.end(), other.end());
+        self.start.x < other_end.x
+            && other.start.x < end.x
+            && self.start.y < other_end.y
+            && other.start.y < end.y
+            && self.start.z < other_end.z
+            && other.start.z < end.z
+    }
 }
@@ -0,0 +1,273 @@
+use std::{collections::HashMap, path::Path, sync::OnceLock};
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Deserialize;
+
+use super::LightLevel;
+
+pub enum BlockShape {
+    Empty,
+    Solid { texture_ids: [u16; 6] },
+    Fluid { texture_id: u16 },
+    Flower { texture_id: u16 },
+    Torch { texture_id: u16 },
+}
+
+pub struct Block {
+    pub name: String,
+    pub shape: BlockShape,
+    transparent: bool,
+    light_level: LightLevel,
+    collidable: bool,
+}
+
+impl Block {
+    pub fn is_transparent(&self) -> bool {
+        self.transparent
+    }
+
+    pub fn light_level(&self) -> LightLevel {
+        self.light_level
+    }
+
+    pub fn is_collidable(&self) -> bool {
+        self.collidable
+    }
+
+    #[inline]
+    pub fn by_id(id: BlockId) -> &'static Self {
+        &registry().blocks[id.0 as usize]
+    }
+}
+
+/// Index of a block in the registry, assigned in the order of the definitions file.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct BlockId(u16);
+
+impl BlockId {
+    /// Air is not listed in the definitions file, it always comes first.
+    pub const AIR: BlockId = BlockId(0);
+
+    pub fn by_name(name: &str) -> Option<BlockId> {
+        registry().ids_by_name.get(name).copied()
+    }
+}
+
+impl From<BlockId> for u16 {
+    #[inline]
+    fn from(id: BlockId) -> Self {
+        id.0
+    }
+}
+
+pub struct BlockRegistry {
+    blocks: Vec<Block>,
+    ids_by_name: HashMap<String, BlockId>,
+}
+
+static REGISTRY: OnceLock<BlockRegistry> = OnceLock::new();
+
+#[inline]
+fn registry() -> &'static BlockRegistry {
+    REGISTRY.get().expect("The block registry is not loaded")
+}
+
+#[derive(Deserialize)]
+enum ShapeDef {
+    Solid,
+    Fluid,
+    Flower,
+    Torch,
+}
+
+#[derive(Deserialize)]
+enum TexturesDef {
+    All(String),
+    Column {
+        top: String,
+        bottom: String,
+        sides: String,
+    },
+    Faces {
+        neg_z: String,
+        pos_z: String,
+        neg_y: String,
+        pos_y: String,
+        neg_x: String,
+        pos_x: String,
+    },
+}
+
+#[derive(Deserialize)]
+struct BlockDef {
+    name: String,
+    shape: ShapeDef,
+    textures: TexturesDef,
+    #[serde(default)]
+    transparent: Option<bool>,
+    #[serde(default)]
+    light: LightLevel,
+    #[serde(default)]
+    collision: Option<bool>,
+}
+
+#[derive(Deserialize)]
+struct BlockDefinitions {
+    blocks: Vec<BlockDef>,
+}
+
+/// Returns the names of the block textures, the PNG files of the directory sorted by
+/// name. The id of a texture is its index in the list.
+pub fn load_texture_names(dir: &Path) -> Result<Vec<String>> {
+    let entries = std::fs::read_dir(dir)
+        .with_context(|| format!("Failed to list block textures {}", dir.display()))?;
+
+    let mut names = vec![];
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().is_some_and(|x| x == "png") {
+            let name = path.file_stem().and_then(|x| x.to_str());
+            let name = name.ok_or_else(|| anyhow!("Invalid texture name {}", path.display()))?;
+            names.push(name.to_string());
+        }
+    }
+
+    if names.len() > u16::MAX as usize {
+        bail!("Too many block textures: {}", names.len());
+    }
+    names.sort();
+    Ok(names)
+}
+
+impl TexturesDef {
+    /// Returns texture ids in the order of the block faces: -Z, +Z, -Y, +Y, -X, +X.
+    fn resolve(&self, texture_ids: &HashMap<&str, u16>) -> Result<[u16; 6]> {
+        let get = |name: &String| {
+            texture_ids
+                .get(name.as_str())
+                .copied()
+                .ok_or_else(|| anyhow!("Unknown texture {}", name))
+        };
+
+        Ok(match self {
+            TexturesDef::All(name) => [get(name)?; 6],
+            TexturesDef::Column { top, bottom, sides } => {
+                let sides = get(sides)?;
+                [sides, sides, get(bottom)?, get(top)?, sides, sides]
+            }
+            TexturesDef::Faces {
+                neg_z,
+                pos_z,
+                neg_y,
+                pos_y,
+                neg_x,
+                pos_x,
+            } => [
+                get(neg_z)?,
+                get(pos_z)?,
+                get(neg_y)?,
+                get(pos_y)?,
+                get(neg_x)?,
+                get(pos_x)?,
+            ],
+        })
+    }
+}
+
+impl BlockDef {
+    fn into_block(self, texture_ids: &HashMap<&str, u16>) -> Result<Block> {
+        let face_texture_ids = self.textures.resolve(texture_ids)?;
+        let single_texture_id = || {
+            if face_texture_ids.iter().all(|&x| x == face_texture_ids[0]) {
+                Ok(face_texture_ids[0])
+            } else {
+                Err(anyhow!("The block shape allows only a single texture"))
+            }
+        };
+
+        let shape = match self.shape {
+            ShapeDef::Solid => BlockShape::Solid {
+                texture_ids: face_texture_ids,
+            },
+            ShapeDef::Fluid => BlockShape::Fluid {
+                texture_id: single_texture_id()?,
+            },
+            ShapeDef::Flower => BlockShape::Flower {
+                texture_id: single_texture_id()?,
+            },
+            ShapeDef::Torch => BlockShape::Torch {
+                texture_id: single_texture_id()?,
+            },
+        };
+
+        if self.light > 15 {
+            bail!("Light level {} is higher than 15", self.light);
+        }
+
+        let is_solid = matches!(shape, BlockShape::Solid { .. });
+        Ok(Block {
+            name: self.name,
+            shape,
+            transparent: self.transparent.unwrap_or(!is_solid),
+            light_level: self.light,
+            collidable: self.collision.unwrap_or(is_solid),
+        })
+    }
+}
+
+impl BlockRegistry {
+    /// Loads the block definitions, their textures are resolved by name among
+    /// `texture_names`.
+    pub fn load(path: &Path, texture_names: &[String]) -> Result<Self> {
+        let source = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to load block definitions {}", path.display()))?;
+        let definitions: BlockDefinitions = ron::from_str(&source)
+            .with_context(|| format!("Failed to parse block definitions {}", path.display()))?;
+
+        let texture_ids: HashMap<&str, u16> = texture_names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| (name.as_str(), i as u16))
+            .collect();
+
+        let mut blocks = vec![Block {
+            name: "air".to_string(),
+            shape: BlockShape::Empty,
+            transparent: true,
+            light_level: 0,
+            collidable: false,
+        }];
+        let mut ids_by_name = HashMap::from([("air".to_string(), BlockId::AIR)]);
+
+        for def in definitions.blocks {
+            let name = def.name.clone();
+            if ids_by_name.contains_key(&name) {
+                bail!("Block {} is defined twice", name);
+            }
+
+            let id = BlockId(blocks.len() as u16);
+            let block = def
+                .into_block(&texture_ids)
+                .with_context(|| format!("Invalid definition of block {}", name))?;
+            blocks.push(block);
+            ids_by_name.insert(name, id);
+        }
+
+        Ok(BlockRegistry {
+            blocks,
+            ids_by_name,
+        })
+    }
+
+    /// Makes the registry available through [`Block::by_id`] and [`BlockId::by_name`].
+    pub fn install(self) -> Result<()> {
+        REGISTRY
+            .set(self)
+            .map_err(|_| anyhow!("The block registry is already loaded"))
+    }
+}
+
+/// Returns the names of all the registered blocks, indexed by their ids.
+pub fn block_names() -> impl Iterator<Item = &'static str> {
+    registry().blocks.iter().map(|block| block.name.as_str())
+}
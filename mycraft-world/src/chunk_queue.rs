@@ -0,0 +1,69 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use cgmath::MetricSpace;
+
+use super::{ChunkCoords, LoadedChunk};
+
+struct ChunkQueueItem {
+    coords: ChunkCoords,
+    chunk: Rc<RefCell<LoadedChunk>>,
+}
+
+pub struct ChunkQueue {
+    queue: Vec<ChunkQueueItem>,
+    /// Position of the chunks in the queue, so that inserting does not search it
+    indices: HashMap<ChunkCoords, usize>,
+    needs_sort: bool,
+}
+
+impl ChunkQueue {
+    pub fn new() -> Self {
+        ChunkQueue {
+            queue: vec![],
+            indices: HashMap::new(),
+            needs_sort: false,
+        }
+    }
+
+    pub fn insert(&mut self, coords: ChunkCoords, chunk: Rc<RefCell<LoadedChunk>>) {
+        if let Some(&index) = self.indices.get(&coords) {
+            self.queue[index].chunk = chunk;
+        } else {
+            self.indices.insert(coords, self.queue.len());
+            self.queue.push(ChunkQueueItem { coords, chunk });
+            self.needs_sort = true;
+        }
+    }
+
+    /// Removes the chunks for which `f` returns false.
+    pub fn retain(&mut self, f: impl Fn(ChunkCoords) -> bool) {
+        self.queue.retain(|x| f(x.coords));
+        self.update_indices();
+    }
+
+    pub fn mark_unsorted(&mut self) {
+        self.needs_sort = true;
+    }
+
+    pub fn needs_to_be_sorted(&self) -> bool {
+        self.needs_sort
+    }
+
+    /// Sorts the chunks from the closest to the farthest from the viewer.
+    pub fn sort(&mut self, viewer_chunk_coords: ChunkCoords) {
+        self.queue
+            .sort_unstable_by_key(|x| viewer_chunk_coords.distance2(x.coords));
+        self.update_indices();
+        self.needs_sort = false;
+    }
+
+    fn update_indices(&mut self) {
+        self.indices.clear();
+        self.indices
+            .extend(self.queue.iter().enumerate().map(|(i, x)| (x.coords, i)));
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (ChunkCoords, &RefCell<LoadedChunk>)> {
+        self.queue.iter().map(|x| (x.coords, x.chunk.as_ref()))
+    }
+}
@@ -0,0 +1,12 @@
+use std::time::Duration;
+
+pub const MAX_UPDATE_TIME: Duration = Duration::from_millis(15);
+
+pub const RENDER_DISTANCE: i32 = 32;
+pub const UNLOAD_DISTANCE_MARGIN: i32 = 2;
+/// Bounds of the new worlds, see [`crate::WorldBounds`]
+pub const DEFAULT_MIN_HEIGHT: i32 = -64;
+pub const DEFAULT_MAX_HEIGHT: i32 = 256;
+
+pub const FLUID_TICK_INTERVAL: Duration = Duration::from_millis(250);
+pub const MAX_FLUID_UPDATES_PER_TICK: usize = 4096;
@@ -0,0 +1,181 @@
+use std::{collections::HashSet, time::Instant};
+
+use cgmath::Vector3;
+
+use super::{
+    blocks::{BlockId, BlockShape},
+    to_local_chunk_coords, BlockCoords, Cell, FluidLevel, World,
+};
+use crate::consts::{FLUID_TICK_INTERVAL, MAX_FLUID_UPDATES_PER_TICK};
+
+/// A still block of fluid, fluid around it flows out of it.
+pub const SOURCE: FluidLevel = 0;
+/// Fluid flowing sideways has levels from 1 to this, by the distance from the closest
+/// source or falling fluid, it does not spread further than that.
+pub const MAX_FLOW_DISTANCE: FluidLevel = 7;
+/// Fluid falling down from the block above.
+pub const FALLING: FluidLevel = 8;
+
+#[rustfmt::skip]
+const HORIZONTAL_OFFSETS: [Vector3<i32>; 4] = [
+    Vector3 { x:  0, y: 0, z: -1 },
+    Vector3 { x:  0, y: 0, z:  1 },
+    Vector3 { x: -1, y: 0, z:  0 },
+    Vector3 { x:  1, y: 0, z:  0 },
+];
+
+#[rustfmt::skip]
+const NEIGHBOR_OFFSETS: [Vector3<i32>; 6] = [
+    Vector3 { x:  0, y:  0, z: -1 },
+    Vector3 { x:  0, y:  0, z:  1 },
+    Vector3 { x:  0, y: -1, z:  0 },
+    Vector3 { x:  0, y:  1, z:  0 },
+    Vector3 { x: -1, y:  0, z:  0 },
+    Vector3 { x:  1, y:  0, z:  0 },
+];
+
+#[inline]
+pub fn is_fluid(cell: &Cell) -> bool {
+    matches!(cell.get_block().shape, BlockShape::Fluid { .. })
+}
+
+/// Fluid can flow into and wash away blocks without collision.
+#[inline]
+fn is_replaceable(cell: &Cell) -> bool {
+    !is_fluid(cell) && !cell.get_block().is_collidable()
+}
+
+/// Keeps track of the cells whose fluid may have to change on the next tick.
+pub struct FluidSimulation {
+    scheduled: HashSet<BlockCoords>,
+    last_tick: Instant,
+}
+
+impl FluidSimulation {
+    pub fn new() -> Self {
+        FluidSimulation {
+            scheduled: HashSet::new(),
+            last_tick: Instant::now(),
+        }
+    }
+
+    /// Schedules an update of the cell and its neighbors.
+    pub fn schedule_around(&mut self, coords: BlockCoords) {
+        self.scheduled.insert(coords);
+        for offset in NEIGHBOR_OFFSETS {
+            self.scheduled.insert(coords + offset);
+        }
+    }
+
+    /// Returns the cells to update if it is time for a tick.
+    pub fn take_due_updates(&mut self) -> Vec<BlockCoords> {
+        if self.last_tick.elapsed() < FLUID_TICK_INTERVAL || self.scheduled.is_empty() {
+            return vec![];
+        }
+        self.last_tick = Instant::now();
+
+        if self.scheduled.len() <= MAX_FLUID_UPDATES_PER_TICK {
+            return self.scheduled.drain().collect();
+        }
+
+        let updates: Vec<_> = self
+            .scheduled
+            .iter()
+            .take(MAX_FLUID_UPDATES_PER_TICK)
+            .copied()
+            .collect();
+        for coords in &updates {
+            self.scheduled.remove(coords);
+        }
+        updates
+    }
+}
+
+/// Returns the cell if its chunk is generated, fluid stops at the chunks that are not
+/// generated yet.
+fn get_cell(world: &World, coords: BlockCoords) -> Option<Cell> {
+    let (chunk_coords, block_coords) = to_local_chunk_coords(coords);
+    if !world.is_chunk_generated(chunk_coords) {
+        return None;
+    }
+
+    world.borrow_chunk(chunk_coords).map(|x| x[block_coords])
+}
+
+/// Returns the block and the fluid level the cell should have after the tick,
+/// or `None` if it stays the same.
+pub fn next_state(world: &World, coords: BlockCoords) -> Option<(BlockId, FluidLevel)> {
+    let cell = get_cell(world, coords)?;
+    let cell_is_fluid = is_fluid(&cell);
+    if cell_is_fluid && cell.fluid_level == SOURCE {
+        return None;
+    }
+    if !cell_is_fluid && !is_replaceable(&cell) {
+        return None;
+    }
+
+    let desired = match get_cell(world, coords + Vector3::unit_y()) {
+        Some(above) if is_fluid(&above) => Some((above.block_id, FALLING)),
+        _ => flow_from_sides(world, coords),
+    };
+
+    match desired {
+        Some((block_id, level)) if cell_is_fluid && cell.block_id == block_id => {
+            (cell.fluid_level != level).then_some((block_id, level))
+        }
+        Some(desired) => Some(desired),
+        // Nothing feeds the fluid anymore, it dries up
+        None if cell_is_fluid => Some((BlockId::AIR, SOURCE)),
+        None => None,
+    }
+}
+
+fn flow_from_sides(world: &World, coords: BlockCoords) -> Option<(BlockId, FluidLevel)> {
+    let mut source_count = 0;
+    let mut best: Option<(BlockId, FluidLevel)> = None;
+
+    for offset in HORIZONTAL_OFFSETS {
+        let neighbor_coords = coords + offset;
+        let Some(neighbor) = get_cell(world, neighbor_coords) else {
+            continue;
+        };
+        if !is_fluid(&neighbor) {
+            continue;
+        }
+
+        if neighbor.fluid_level == SOURCE {
+            source_count += 1;
+        }
+
+        // Fluid spreads sideways only when it cannot fall down
+        let below_neighbor = get_cell(world, neighbor_coords - Vector3::unit_y());
+        if below_neighbor.is_some_and(|x| is_replaceable(&x)) {
+            continue;
+        }
+
+        let distance = match neighbor.fluid_level {
+            SOURCE | FALLING => 1,
+            level => level + 1,
+        };
+        if distance <= MAX_FLOW_DISTANCE && best.is_none_or(|(_, x)| distance < x) {
+            best = Some((neighbor.block_id, distance));
+        }
+    }
+
+    // Fluid between two sources becomes a source itself, if it has something to rest on
+    let below = get_cell(world, coords - Vector3::unit_y());
+    let is_supported = below.is_some_and(|x| {
+        if is_fluid(&x) {
+            x.fluid_level == SOURCE
+        } else {
+            !is_replaceable(&x)
+        }
+    });
+    if source_count >= 2 && is_supported {
+        if let Some((block_id, _)) = best {
+            return Some((block_id, SOURCE));
+        }
+    }
+
+    best
+}
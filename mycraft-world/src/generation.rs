@@ -0,0 +1,420 @@
+mod biome;
+mod caves;
+mod ores;
+mod structures;
+
+use anyhow::{anyhow, Result};
+use cgmath::{ElementWise, Matrix2, Rad, Vector2, Vector3};
+use noise::{NoiseFn, Perlin};
+
+pub use self::{
+    biome::Biome,
+    structures::{StructureId, StructureRegistry, StructureTemplate},
+};
+use self::{
+    biome::{BiomeRules, ColumnBiomes},
+    caves::WormRules,
+    ores::OreRules,
+};
+use super::{blocks::BlockId, BlockCoords, Chunk, ChunkCoords, WorldBounds};
+
+/// Blocks placed by the generator, looked up in the block registry once.
+struct TerrainBlocks {
+    bedrock: BlockId,
+    stone: BlockId,
+    dirt: BlockId,
+    grass: BlockId,
+    sand: BlockId,
+    snowy_grass: BlockId,
+    water: BlockId,
+    red_flower: BlockId,
+    yellow_flower: BlockId,
+    coal_ore: BlockId,
+    iron_ore: BlockId,
+    gold_ore: BlockId,
+    diamond_ore: BlockId,
+}
+
+impl TerrainBlocks {
+    fn resolve() -> Result<Self> {
+        let get = |name: &str| {
+            BlockId::by_name(name)
+                .ok_or_else(|| anyhow!("Block {} used by the generator is not defined", name))
+        };
+
+        Ok(TerrainBlocks {
+            bedrock: get("bedrock")?,
+            stone: get("stone")?,
+            dirt: get("dirt")?,
+            grass: get("grass")?,
+            sand: get("sand")?,
+            snowy_grass: get("snowy_grass")?,
+            water: get("water")?,
+            red_flower: get("red_flower")?,
+            yellow_flower: get("yellow_flower")?,
+            coal_ore: get("coal_ore")?,
+            iron_ore: get("iron_ore")?,
+            gold_ore: get("gold_ore")?,
+            diamond_ore: get("diamond_ore")?,
+        })
+    }
+}
+
+/// Generates the terrain of the chunks. Every block only depends on the seed and its
+/// coords, so the chunks are the same whatever order they are generated in.
+pub struct Generator {
+    seed: u32,
+    bounds: WorldBounds,
+    /// No block is generated above this height
+    max_terrain_height: i32,
+    height_noise: Perlin,
+    cave_noise: Perlin,
+    shore_noise: Perlin,
+    temperature_noise: Perlin,
+    humidity_noise: Perlin,
+    blocks: TerrainBlocks,
+    /// Indexed by biome
+    biome_rules: [BiomeRules; Biome::ALL.len()],
+    ore_rules: Vec<OreRules>,
+    worm_rules: [WormRules; 2],
+}
+
+/// Terrain of a column, computed before the passes that need it.
+#[derive(Clone, Copy, Default)]
+struct Column {
+    height: i32,
+    /// Whether the column is above the water and the shore
+    is_land: bool,
+}
+
+impl Column {
+    /// Whether the water of a water body fills the column at the height.
+    fn has_water_at(&self, y: i32) -> bool {
+        !self.is_land && y > self.height && y <= Generator::WATER_HEIGHT
+    }
+}
+
+/// Columns of a chunk, indexed by their x then z local coords.
+type Columns = [[Column; Chunk::SIZE as usize]; Chunk::SIZE as usize];
+
+/// Mixes the bits of the seed and the values, the same inputs always give the same result.
+fn hash(seed: u32, values: &[i32]) -> u32 {
+    let mix = |mut x: u64| {
+        x ^= x >> 33;
+        x = x.wrapping_mul(0xff51_afd7_ed55_8ccd);
+        x ^= x >> 33;
+        x = x.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+        x ^ (x >> 33)
+    };
+
+    let mut result = mix(seed as u64);
+    for &value in values {
+        result = mix(result ^ value as u32 as u64);
+    }
+    result as u32
+}
+
+/// Random value within 0..1 for the seed and the values.
+fn random(seed: u32, values: &[i32]) -> f64 {
+    hash(seed, values) as f64 / (u32::MAX as f64 + 1.)
+}
+
+/// What a noise is used for, each one gets its own seed derived from the world seed.
+#[derive(Clone, Copy)]
+enum NoiseKind {
+    Height,
+    Caves,
+    Shore,
+    Temperature,
+    Humidity,
+}
+
+fn create_noise(seed: u32, kind: NoiseKind) -> Perlin {
+    Perlin::new(hash(seed, &[kind as i32]))
+}
+
+fn set_block(chunk: &mut Chunk, coords: BlockCoords, id: BlockId) {
+    if (0..Chunk::SIZE).contains(&coords.x)
+        && (0..Chunk::SIZE).contains(&coords.y)
+        && (0..Chunk::SIZE).contains(&coords.z)
+    {
+        chunk[coords].block_id = id;
+    }
+}
+
+impl Generator {
+    const WATER_HEIGHT: i32 = 27;
+    /// Size of the temperature and humidity features, biomes are about half as large
+    const CLIMATE_SCALE: f64 = 600.;
+    /// Frequency and scale of the octaves of the terrain height
+    const HEIGHT_OCTAVES: [(f64, f64); 4] = [(80., 24.), (30., 12.), (15., 4.), (10., 3.)];
+    /// Layers at the bottom of the world where bedrock is generated, the lowest one is
+    /// full of it and it gets sparser going up
+    const BEDROCK_LAYERS: i32 = 5;
+
+    /// Structures are loaded from the structure registry, which must be installed.
+    pub fn new(seed: u32, bounds: WorldBounds) -> Result<Self> {
+        let blocks = TerrainBlocks::resolve()?;
+        let biome_rules = Biome::ALL.map(|biome| BiomeRules::of(biome, &blocks));
+        let ore_rules = OreRules::all(&blocks, bounds);
+
+        let max_octaves_sum: f64 = Self::HEIGHT_OCTAVES.iter().map(|(_, scale)| scale).sum();
+        let max_ground_height = biome_rules
+            .iter()
+            .map(|rules| (rules.base_height + rules.height_scale * max_octaves_sum) as i32)
+            .max()
+            .unwrap_or(0);
+        // Plants stand one block above the ground
+        let max_terrain_height =
+            Self::WATER_HEIGHT.max(max_ground_height + structures::max_structure_height().max(1));
+
+        Ok(Generator {
+            seed,
+            bounds,
+            max_terrain_height,
+            height_noise: create_noise(seed, NoiseKind::Height),
+            cave_noise: create_noise(seed, NoiseKind::Caves),
+            shore_noise: create_noise(seed, NoiseKind::Shore),
+            temperature_noise: create_noise(seed, NoiseKind::Temperature),
+            humidity_noise: create_noise(seed, NoiseKind::Humidity),
+            blocks,
+            biome_rules,
+            ore_rules,
+            worm_rules: WormRules::all(bounds),
+        })
+    }
+
+    #[inline]
+    fn rules(&self, biome: Biome) -> &BiomeRules {
+        &self.biome_rules[biome as usize]
+    }
+
+    fn column_biomes(&self, offset: Vector2<f64>) -> ColumnBiomes {
+        // The octaves are turned so that the lattices of the noises do not line up,
+        // which would repeat the same biomes along the lines
+        let climate = |noise: &Perlin, angle: f64| {
+            let position = Matrix2::from_angle(Rad(angle)) * offset / Self::CLIMATE_SCALE;
+            let detail_position = Matrix2::from_angle(Rad(angle + 1.)) * position * 4.;
+            let value = noise.get(position.into()) + noise.get(detail_position.into()) * 0.1;
+            (value / 2. + 0.5).clamp(0., 1.)
+        };
+
+        ColumnBiomes::new(
+            climate(&self.temperature_noise, 0.3),
+            climate(&self.humidity_noise, 1.1),
+        )
+    }
+
+    /// Biome that sets the blocks of the column.
+    pub fn get_biome(&self, x: i32, z: i32) -> Biome {
+        self.column_biomes(Vector2::new(x as f64, z as f64)).main()
+    }
+
+    #[inline]
+    fn get_noise(noise: &Perlin, offset: Vector2<f64>, freq: f64, scale: f64) -> f64 {
+        (noise.get((offset / freq).into()) / 2. + 0.5) * scale
+    }
+
+    #[inline]
+    fn get_noise_3d(noise: &Perlin, offset: Vector3<f64>, freq: f64, scale: f64) -> f64 {
+        (noise.get((offset / freq).into()) / 2. + 0.5) * scale
+    }
+
+    /// Height of the terrain surface at the column.
+    pub fn get_height(&self, x: i32, z: i32) -> i32 {
+        let offset = Vector2::new(x as f64, z as f64);
+        self.height_in_biomes(offset, &self.column_biomes(offset))
+    }
+
+    /// Height of the terrain surface at the column, None if it is under the water or on
+    /// the shore.
+    pub fn get_land_height(&self, x: i32, z: i32) -> Option<i32> {
+        let column = self.get_column(x, z);
+        column.is_land.then_some(column.height)
+    }
+
+    fn get_column(&self, x: i32, z: i32) -> Column {
+        let offset = Vector2::new(x as f64, z as f64);
+        let height = self.height_in_biomes(offset, &self.column_biomes(offset));
+        Column {
+            height,
+            is_land: self.is_land(offset, height),
+        }
+    }
+
+    fn is_land(&self, offset: Vector2<f64>, height: i32) -> bool {
+        let shore_height =
+            Self::WATER_HEIGHT + Self::get_noise(&self.shore_noise, offset, 30., 3.) as i32;
+        height > shore_height
+    }
+
+    /// Blends the heights the biomes would give to the column, so that the terrain
+    /// changes smoothly at the borders of the biomes.
+    fn height_in_biomes(&self, offset: Vector2<f64>, biomes: &ColumnBiomes) -> i32 {
+        let octaves_sum: f64 = Self::HEIGHT_OCTAVES
+            .iter()
+            .map(|&(freq, scale)| Self::get_noise(&self.height_noise, offset, freq, scale))
+            .sum();
+
+        let height = biomes.blend(|biome| {
+            let rules = self.rules(biome);
+            rules.base_height + rules.height_scale * octaves_sum
+        });
+        height as i32
+    }
+
+    fn is_bedrock(&self, coords: BlockCoords) -> bool {
+        let layer = coords.y - self.bounds.min_y;
+        layer < Self::BEDROCK_LAYERS
+            && random(self.seed, &[coords.x, coords.y, coords.z])
+                < (Self::BEDROCK_LAYERS - layer) as f64 / Self::BEDROCK_LAYERS as f64
+    }
+
+    /// Whether the chunk is above the highest possible terrain, so it is only air.
+    pub fn is_above_terrain(&self, chunk_coords: ChunkCoords) -> bool {
+        chunk_coords.y * Chunk::SIZE > self.max_terrain_height
+    }
+
+    /// Leaves the chunks outside of the world bounds and the ones above the highest
+    /// possible terrain empty, without computing anything for their columns.
+    pub fn generate_chunk(&self, chunk: &mut Chunk, chunk_coords: ChunkCoords) {
+        puffin::profile_function!();
+
+        if !self.bounds.chunk_layers().contains(&chunk_coords.y)
+            || self.is_above_terrain(chunk_coords)
+        {
+            return;
+        }
+
+        let chunk_offset = chunk_coords.mul_element_wise(Chunk::SIZE);
+
+        let blocks = &self.blocks;
+        let mut columns = Columns::default();
+        for chunk_x in 0..Chunk::SIZE {
+            for chunk_z in 0..Chunk::SIZE {
+                let world_coords = Vector2 {
+                    x: chunk_x + chunk_offset.x,
+                    y: chunk_z + chunk_offset.z,
+                };
+                let offset = world_coords.map(|x| x as f64);
+
+                let biomes = self.column_biomes(offset);
+                let rules = self.rules(biomes.main());
+
+                let height = self.height_in_biomes(offset, &biomes);
+                let plant_random = random(self.seed, &[world_coords.x, world_coords.y]);
+
+                let is_land = self.is_land(offset, height);
+                columns[chunk_x as usize][chunk_z as usize] = Column { height, is_land };
+                let has_flower = is_land && plant_random > 1. - rules.flower_density;
+
+                for chunk_y in 0..Chunk::SIZE {
+                    let world_coords = BlockCoords {
+                        x: world_coords.x,
+                        y: chunk_y + chunk_offset.y,
+                        z: world_coords.y,
+                    };
+
+                    let chunk_coords = BlockCoords {
+                        x: chunk_x,
+                        y: chunk_y,
+                        z: chunk_z,
+                    };
+                    chunk[chunk_coords].block_id = if self.is_bedrock(world_coords) {
+                        blocks.bedrock
+                    } else if world_coords.y < height - 3 {
+                        blocks.stone
+                    } else if world_coords.y < height {
+                        rules.subsurface
+                    } else if world_coords.y == height {
+                        if is_land {
+                            rules.surface
+                        } else {
+                            rules.shore
+                        }
+                    } else if world_coords.y <= Self::WATER_HEIGHT {
+                        blocks.water
+                    } else {
+                        break;
+                    };
+                }
+
+                if has_flower {
+                    let coords = BlockCoords::new(chunk_x, height + 1 - chunk_offset.y, chunk_z);
+                    if plant_random > 1. - rules.flower_density / 2. {
+                        set_block(chunk, coords, blocks.red_flower);
+                    } else {
+                        set_block(chunk, coords, blocks.yellow_flower);
+                    }
+                }
+            }
+        }
+
+        self.carve_caves(chunk, chunk_coords, &columns);
+        self.place_ores(chunk, chunk_coords);
+        self.stamp_structures(chunk, chunk_coords);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::test_utils::install_registries;
+
+    const SEED: u32 = 1234;
+
+    pub(super) fn generator() -> Generator {
+        install_registries();
+        Generator::new(SEED, WorldBounds::default()).unwrap()
+    }
+
+    pub(super) fn generate(generator: &Generator, coords: ChunkCoords) -> Chunk {
+        let mut chunk = Chunk::new();
+        generator.generate_chunk(&mut chunk, coords);
+        chunk
+    }
+
+    #[test]
+    fn chunks_do_not_depend_on_the_generation_order() {
+        // Around the surface and the caves, with structures and ores
+        let mut coords = vec![];
+        for x in -1..=1 {
+            for y in 0..=2 {
+                for z in -1..=1 {
+                    coords.push(ChunkCoords::new(x, y, z));
+                }
+            }
+        }
+
+        let first = generator();
+        let forward: HashMap<_, _> = coords
+            .iter()
+            .map(|&coords| (coords, generate(&first, coords)))
+            .collect();
+
+        let second = generator();
+        let backward: HashMap<_, _> = coords
+            .iter()
+            .rev()
+            .map(|&coords| (coords, generate(&second, coords)))
+            .collect();
+
+        assert!(forward.values().any(|chunk| !chunk.is_empty()));
+        for (chunk_coords, chunk) in &forward {
+            for x in 0..Chunk::SIZE {
+                for y in 0..Chunk::SIZE {
+                    for z in 0..Chunk::SIZE {
+                        let coords = BlockCoords { x, y, z };
+                        assert_eq!(
+                            chunk[coords].block_id, backward[chunk_coords][coords].block_id,
+                            "{:?} in chunk {:?}",
+                            coords, chunk_coords
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,155 @@
+use cgmath::{MetricSpace, Vector2};
+use serde::Deserialize;
+
+use crate::blocks::BlockId;
+
+use super::TerrainBlocks;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize)]
+pub enum Biome {
+    Ocean,
+    Plains,
+    Forest,
+    Desert,
+    Tundra,
+    Mountains,
+}
+
+impl Biome {
+    pub const ALL: [Biome; 6] = [
+        Biome::Ocean,
+        Biome::Plains,
+        Biome::Forest,
+        Biome::Desert,
+        Biome::Tundra,
+        Biome::Mountains,
+    ];
+
+    /// Temperature and humidity at which the biome is the most likely, both within 0..1
+    fn climate(self) -> Vector2<f64> {
+        match self {
+            Biome::Ocean => Vector2::new(0.7, 0.75),
+            Biome::Plains => Vector2::new(0.5, 0.4),
+            Biome::Forest => Vector2::new(0.45, 0.6),
+            Biome::Desert => Vector2::new(0.75, 0.3),
+            Biome::Tundra => Vector2::new(0.25, 0.4),
+            Biome::Mountains => Vector2::new(0.3, 0.65),
+        }
+    }
+}
+
+/// How a biome shapes the terrain of its columns.
+pub struct BiomeRules {
+    /// Height of the terrain when all the height octaves are at their lowest
+    pub base_height: f64,
+    /// Factor applied to the sum of the height octaves
+    pub height_scale: f64,
+    pub surface: BlockId,
+    pub subsurface: BlockId,
+    /// Surface under or right above the water level
+    pub shore: BlockId,
+    /// Chances for a column to get a flower, trees are structures
+    pub flower_density: f64,
+}
+
+impl BiomeRules {
+    pub fn of(biome: Biome, blocks: &TerrainBlocks) -> Self {
+        match biome {
+            Biome::Ocean => BiomeRules {
+                base_height: 4.,
+                height_scale: 0.4,
+                surface: blocks.sand,
+                subsurface: blocks.sand,
+                shore: blocks.sand,
+                flower_density: 0.,
+            },
+            Biome::Plains => BiomeRules {
+                base_height: 22.,
+                height_scale: 0.4,
+                surface: blocks.grass,
+                subsurface: blocks.dirt,
+                shore: blocks.sand,
+                flower_density: 0.12,
+            },
+            Biome::Forest => BiomeRules {
+                base_height: 20.,
+                height_scale: 0.55,
+                surface: blocks.grass,
+                subsurface: blocks.dirt,
+                shore: blocks.dirt,
+                flower_density: 0.04,
+            },
+            Biome::Desert => BiomeRules {
+                base_height: 24.,
+                height_scale: 0.3,
+                surface: blocks.sand,
+                subsurface: blocks.sand,
+                shore: blocks.sand,
+                flower_density: 0.,
+            },
+            Biome::Tundra => BiomeRules {
+                base_height: 22.,
+                height_scale: 0.45,
+                surface: blocks.snowy_grass,
+                subsurface: blocks.dirt,
+                shore: blocks.snowy_grass,
+                flower_density: 0.,
+            },
+            Biome::Mountains => BiomeRules {
+                base_height: 18.,
+                height_scale: 1.8,
+                surface: blocks.stone,
+                subsurface: blocks.stone,
+                shore: blocks.stone,
+                flower_density: 0.,
+            },
+        }
+    }
+}
+
+/// Biomes of a column, weighted by how close the climate of the column is to theirs.
+pub struct ColumnBiomes {
+    weights: [f64; Biome::ALL.len()],
+}
+
+impl ColumnBiomes {
+    /// Distance in the climate space over which the neighboring biomes are blended
+    const BLEND_DISTANCE: f64 = 0.06;
+
+    pub fn new(temperature: f64, humidity: f64) -> Self {
+        let climate = Vector2::new(temperature, humidity);
+        let mut weights = Biome::ALL.map(|biome| {
+            let distance = biome.climate().distance(climate) / Self::BLEND_DISTANCE;
+            (-distance * distance).exp()
+        });
+
+        // Far from all the biomes every weight gets tiny, only the ratios matter
+        let max = weights.iter().copied().fold(f64::MIN_POSITIVE, f64::max);
+        let sum: f64 = weights.iter().map(|x| x / max).sum();
+        for weight in &mut weights {
+            *weight = *weight / max / sum;
+        }
+
+        ColumnBiomes { weights }
+    }
+
+    /// The biome with the largest weight, which sets the blocks of the column.
+    pub fn main(&self) -> Biome {
+        let (index, _) = self
+            .weights
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .unwrap();
+        Biome::ALL[index]
+    }
+
+    /// Averages a value of every biome by their weights.
+    pub fn blend(&self, f: impl Fn(Biome) -> f64) -> f64 {
+        Biome::ALL
+            .iter()
+            .zip(self.weights)
+            .map(|(&biome, weight)| f(biome) * weight)
+            .sum()
+    }
+}
@@ -0,0 +1,421 @@
+use std::{
+    f64::consts::{PI, TAU},
+    ops::Range,
+};
+
+use cgmath::{ElementWise, InnerSpace, Vector2, Vector3};
+
+use crate::{blocks::BlockId, BlockCoords, Chunk, ChunkCoords, WorldBounds};
+
+use super::{random, Column, Columns, Generator};
+
+/// Columns of a chunk and the ones next to it, indexed by their x then z local coords
+/// plus one.
+struct CaveColumns([[Column; Chunk::SIZE as usize + 2]; Chunk::SIZE as usize + 2]);
+
+impl CaveColumns {
+    #[inline]
+    fn get(&self, x: i32, z: i32) -> &Column {
+        &self.0[(x + 1) as usize][(z + 1) as usize]
+    }
+}
+
+/// Tunnels carved by a walk that slowly turns, starting in random chunk columns.
+pub struct WormRules {
+    /// Average number of worms starting in a chunk column
+    pub worms_per_column: f64,
+    /// Number of steps of one block
+    pub lengths: Range<i32>,
+    /// Largest horizontal radius of the tunnels, reached halfway through
+    pub radiuses: Range<f64>,
+    /// Vertical radius over horizontal radius
+    pub vertical_scale: f64,
+    /// World heights the worms start in, within the bounds of the world
+    pub start_heights: Range<i32>,
+    /// Steepest slope of the tunnels, in radians
+    pub max_pitch: f64,
+    /// Makes the worms of the different rules independent
+    pub salt: i32,
+}
+
+impl WormRules {
+    /// Narrow winding tunnels, and ravines which are tall and straight. The tunnels start
+    /// anywhere from the caves floor to under the ground.
+    pub fn all(bounds: WorldBounds) -> [WormRules; 2] {
+        let floor = Generator::caves_floor(bounds);
+        [
+            WormRules {
+                worms_per_column: 0.35,
+                lengths: 30..80,
+                radiuses: 1.2..2.8,
+                vertical_scale: 0.9,
+                start_heights: floor..48.max(floor),
+                max_pitch: 0.6,
+                salt: 0,
+            },
+            WormRules {
+                worms_per_column: 0.015,
+                lengths: 50..90,
+                radiuses: 1.5..3.,
+                vertical_scale: 4.,
+                start_heights: 18.max(floor)..34.max(floor),
+                max_pitch: 0.1,
+                salt: 1,
+            },
+        ]
+    }
+
+    /// Farthest horizontal distance from the start column the worms can carve.
+    fn reach(&self) -> f64 {
+        self.lengths.end as f64 + self.radiuses.end
+    }
+}
+
+/// Interpolates within the range, `t` is within 0..1.
+fn lerp(range: &Range<f64>, t: f64) -> f64 {
+    range.start + (range.end - range.start) * t
+}
+
+impl Generator {
+    /// Blocks of stone left between the caves and the ground under a water body
+    const UNDERWATER_CAVE_ROOF: i32 = 8;
+    /// Blocks of stone left above the caverns
+    const CAVERN_ROOF: i32 = 10;
+    /// World height the caverns stay under, they go down to the caves floor
+    const CAVERN_TOP: i32 = 40;
+    /// Caves up to this many blocks above the bottom of the world are flooded, making
+    /// underground lakes
+    const LAKE_DEPTH: i32 = 16;
+
+    /// Lowest height the caves start from, on top of the underground lakes.
+    fn caves_floor(bounds: WorldBounds) -> i32 {
+        bounds.min_y + Self::LAKE_DEPTH
+    }
+
+    fn cavern_heights(&self) -> Range<i32> {
+        let floor = Self::caves_floor(self.bounds);
+        floor..Self::CAVERN_TOP.max(floor)
+    }
+
+    /// Carves the tunnels, ravines and caverns out of the terrain of the chunk. The
+    /// caves do not open under a water body, and their lowest parts are flooded.
+    pub(super) fn carve_caves(
+        &self,
+        chunk: &mut Chunk,
+        chunk_coords: ChunkCoords,
+        columns: &Columns,
+    ) {
+        puffin::profile_function!();
+
+        // Nothing is carved above the ground, except the plants standing on it
+        let chunk_bottom = chunk_coords.y * Chunk::SIZE;
+        if columns
+            .iter()
+            .flatten()
+            .all(|column| column.height + 1 < chunk_bottom)
+        {
+            return;
+        }
+
+        let columns = self.cave_columns(chunk_coords, columns);
+        self.carve_caverns(chunk, chunk_coords, &columns);
+        for rules in &self.worm_rules {
+            self.carve_worms(chunk, chunk_coords, &columns, rules);
+        }
+    }
+
+    /// Adds the columns of the neighbor chunks along the borders of the chunk.
+    fn cave_columns(&self, chunk_coords: ChunkCoords, columns: &Columns) -> CaveColumns {
+        let mut result = [[Column::default(); Chunk::SIZE as usize + 2]; Chunk::SIZE as usize + 2];
+        for x in -1..=Chunk::SIZE {
+            for z in -1..=Chunk::SIZE {
+                let is_inside = (0..Chunk::SIZE).contains(&x) && (0..Chunk::SIZE).contains(&z);
+                result[(x + 1) as usize][(z + 1) as usize] = if is_inside {
+                    columns[x as usize][z as usize]
+                } else {
+                    self.get_column(
+                        chunk_coords.x * Chunk::SIZE + x,
+                        chunk_coords.z * Chunk::SIZE + z,
+                    )
+                };
+            }
+        }
+        CaveColumns(result)
+    }
+
+    /// Caves stay under the ground and away from the water bodies, including the ones
+    /// next to the column, which would flood them.
+    fn can_carve(columns: &CaveColumns, x: i32, z: i32, y: i32) -> bool {
+        let column = columns.get(x, z);
+        let is_under_ground = if column.is_land {
+            y <= column.height
+        } else {
+            y < column.height - Self::UNDERWATER_CAVE_ROOF
+        };
+
+        is_under_ground
+            && [(-1, 0), (1, 0), (0, -1), (0, 1)]
+                .iter()
+                .all(|&(dx, dz)| !columns.get(x + dx, z + dz).has_water_at(y))
+    }
+
+    /// Bedrock is never carved.
+    fn carve_block(&self, chunk: &mut Chunk, coords: BlockCoords, y: i32) {
+        let cell = &mut chunk[coords];
+        if cell.block_id == self.blocks.bedrock {
+            return;
+        }
+
+        cell.block_id = if y < self.bounds.min_y + Self::LAKE_DEPTH {
+            self.blocks.water
+        } else {
+            BlockId::AIR
+        };
+    }
+
+    fn carve_caverns(&self, chunk: &mut Chunk, chunk_coords: ChunkCoords, columns: &CaveColumns) {
+        let chunk_offset = chunk_coords.mul_element_wise(Chunk::SIZE);
+        let heights = self.cavern_heights();
+        if chunk_offset.y >= heights.end || chunk_offset.y + Chunk::SIZE <= heights.start {
+            return;
+        }
+
+        let noise = &self.cave_noise;
+        for x in 0..Chunk::SIZE {
+            for z in 0..Chunk::SIZE {
+                let column = columns.get(x, z);
+                for y in 0..Chunk::SIZE {
+                    let coords = BlockCoords { x, y, z };
+                    let world_coords = coords + chunk_offset;
+                    if !heights.contains(&world_coords.y)
+                        || world_coords.y >= column.height - Self::CAVERN_ROOF
+                        || !Self::can_carve(columns, x, z, world_coords.y)
+                    {
+                        continue;
+                    }
+
+                    // Squashed vertically so that the caverns are wide rather than tall
+                    let offset = world_coords
+                        .map(|x| x as f64)
+                        .mul_element_wise(Vector3::new(1., 2., 1.));
+                    let value = Self::get_noise_3d(noise, offset, 64., 0.8)
+                        + Self::get_noise_3d(noise, offset, 24., 0.3);
+
+                    // Shrinks the caverns near the bounds of the heights
+                    let edge_distance = (world_coords.y - heights.start)
+                        .min(heights.end - 1 - world_coords.y)
+                        as f64;
+                    let threshold = 0.8 + 0.1 * (1. - edge_distance / 6.).max(0.);
+                    if value > threshold {
+                        self.carve_block(chunk, coords, world_coords.y);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Walks every worm that can reach the chunk, carving the part of its tunnel that
+    /// overlaps the chunk.
+    fn carve_worms(
+        &self,
+        chunk: &mut Chunk,
+        chunk_coords: ChunkCoords,
+        columns: &CaveColumns,
+        rules: &WormRules,
+    ) {
+        let chunk_offset = chunk_coords.mul_element_wise(Chunk::SIZE);
+        let chunk_start = chunk_offset.map(|x| x as f64);
+        let chunk_end = chunk_start + Vector3::new(1., 1., 1.) * Chunk::SIZE as f64;
+        let reach = (rules.reach() / Chunk::SIZE as f64).ceil() as i32;
+
+        for source_x in chunk_coords.x - reach..=chunk_coords.x + reach {
+            for source_z in chunk_coords.z - reach..=chunk_coords.z + reach {
+                let source = Vector2::new(source_x, source_z);
+
+                let mut worm_count = rules.worms_per_column as i32;
+                if random(self.seed, &[source.x, source.y, rules.salt])
+                    < rules.worms_per_column.fract()
+                {
+                    worm_count += 1;
+                }
+
+                for worm in 0..worm_count {
+                    let worm_random =
+                        |i: i32| random(self.seed, &[source.x, source.y, rules.salt, worm, i]);
+
+                    let start_heights = &rules.start_heights;
+                    let mut position = Vector3::new(
+                        (source.x as f64 + worm_random(0)) * Chunk::SIZE as f64,
+                        start_heights.start as f64
+                            + worm_random(1) * (start_heights.end - start_heights.start) as f64,
+                        (source.y as f64 + worm_random(2)) * Chunk::SIZE as f64,
+                    );
+                    let lengths = &rules.lengths;
+                    let length = lengths.start
+                        + (worm_random(3) * (lengths.end - lengths.start) as f64) as i32;
+                    let max_radius = lerp(&rules.radiuses, worm_random(4));
+                    let mut yaw = worm_random(5) * TAU;
+                    let mut pitch = (worm_random(6) - 0.5) * rules.max_pitch;
+                    let (mut yaw_speed, mut pitch_speed) = (0., 0.);
+
+                    for step in 0..length {
+                        let t = step as f64 / length as f64;
+                        let horizontal_radius = 1. + (max_radius - 1.) * (t * PI).sin();
+                        let radius = Vector3::new(
+                            horizontal_radius,
+                            horizontal_radius * rules.vertical_scale,
+                            horizontal_radius,
+                        );
+
+                        let overlaps_chunk = (0..3).all(|i| {
+                            position[i] + radius[i] + 1. >= chunk_start[i]
+                                && position[i] - radius[i] - 1. <= chunk_end[i]
+                        });
+                        if overlaps_chunk {
+                            self.carve_ellipsoid(chunk, chunk_offset, columns, position, radius);
+                        }
+
+                        let direction = Vector3::new(
+                            pitch.cos() * yaw.cos(),
+                            pitch.sin(),
+                            pitch.cos() * yaw.sin(),
+                        );
+                        position += direction.normalize();
+
+                        let step_random = |i: i32| worm_random(7 + step * 2 + i);
+                        yaw += yaw_speed * 0.1;
+                        pitch =
+                            (pitch + pitch_speed * 0.1).clamp(-rules.max_pitch, rules.max_pitch);
+                        yaw_speed = yaw_speed * 0.75 + (step_random(0) - 0.5) * 2.;
+                        pitch_speed = pitch_speed * 0.75 + (step_random(1) - 0.5) * 1.;
+                    }
+                }
+            }
+        }
+    }
+
+    fn carve_ellipsoid(
+        &self,
+        chunk: &mut Chunk,
+        chunk_offset: BlockCoords,
+        columns: &CaveColumns,
+        center: Vector3<f64>,
+        radius: Vector3<f64>,
+    ) {
+        let is_inside = |coords: BlockCoords| {
+            let distance = (coords.map(|x| x as f64 + 0.5) - center).div_element_wise(radius);
+            distance.magnitude2() <= 1.
+        };
+
+        // One more block on top for the plants standing on the carved ground
+        let start = (center - radius).map(|x| x.floor() as i32) - chunk_offset;
+        let end = (center + radius).map(|x| x.ceil() as i32) - chunk_offset + Vector3::unit_y();
+        let start = start.map(|x| x.max(0));
+        let end = end.map(|x| x.min(Chunk::SIZE - 1));
+
+        for x in start.x..=end.x {
+            for z in start.z..=end.z {
+                let column = columns.get(x, z);
+                for y in start.y..=end.y {
+                    let coords = BlockCoords { x, y, z };
+                    let world_coords = coords + chunk_offset;
+
+                    if is_inside(world_coords) {
+                        if Self::can_carve(columns, x, z, world_coords.y) {
+                            self.carve_block(chunk, coords, world_coords.y);
+                        }
+                    } else if world_coords.y == column.height + 1
+                        && Self::can_carve(columns, x, z, column.height)
+                        && is_inside(world_coords - Vector3::unit_y())
+                    {
+                        chunk[coords].block_id = BlockId::AIR;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::generation::tests::{generate, generator};
+
+    /// Heights of the column where it has ground next to the water of a neighbor column.
+    fn heights_next_to_water(generator: &Generator, x: i32, z: i32) -> Vec<i32> {
+        let column = generator.get_column(x, z);
+        let neighbors =
+            [(-1, 0), (1, 0), (0, -1), (0, 1)].map(|(dx, dz)| generator.get_column(x + dx, z + dz));
+        (generator.bounds.min_y..=column.height)
+            .filter(|&y| neighbors.iter().any(|neighbor| neighbor.has_water_at(y)))
+            .collect()
+    }
+
+    #[test]
+    fn caves_do_not_open_next_to_water_bodies() {
+        let generator = generator();
+        let mut carved_shores = 0;
+
+        // Every shore around the origin, some of them must be reached by caves
+        let chunk_columns = (-32..32).flat_map(|x| (-32..32).map(move |z| (x, z)));
+        for (chunk_x, chunk_z) in chunk_columns {
+            let shores: Vec<_> = (0..Chunk::SIZE)
+                .flat_map(|x| (0..Chunk::SIZE).map(move |z| (x, z)))
+                .map(|(x, z)| {
+                    let (world_x, world_z) = (chunk_x * Chunk::SIZE + x, chunk_z * Chunk::SIZE + z);
+                    ((x, z), heights_next_to_water(&generator, world_x, world_z))
+                })
+                .filter(|(_, heights)| !heights.is_empty())
+                .collect();
+            let Some(lowest) = shores.iter().map(|(_, heights)| heights[0]).min() else {
+                continue;
+            };
+
+            let mut chunks = HashMap::new();
+            for chunk_y in lowest.div_euclid(Chunk::SIZE)..=Generator::WATER_HEIGHT / Chunk::SIZE {
+                let chunk_coords = ChunkCoords::new(chunk_x, chunk_y, chunk_z);
+                chunks.insert(chunk_y, generate(&generator, chunk_coords));
+            }
+
+            for ((x, z), heights) in &shores {
+                let world_x = chunk_x * Chunk::SIZE + x;
+                let world_z = chunk_z * Chunk::SIZE + z;
+                let is_carved = |y: i32| {
+                    let chunk = &chunks[&y.div_euclid(Chunk::SIZE)];
+                    let coords = BlockCoords::new(*x, y.rem_euclid(Chunk::SIZE), *z);
+                    chunk[coords].block_id == BlockId::AIR
+                };
+
+                for &y in heights {
+                    assert!(!is_carved(y), "{:?}", (world_x, y, world_z));
+                }
+                if (lowest..=heights[heights.len() - 1]).any(is_carved) {
+                    carved_shores += 1;
+                }
+            }
+        }
+
+        assert!(carved_shores > 0, "No caves reaching the shores");
+    }
+
+    #[test]
+    fn caves_start_above_the_bottom_of_the_world() {
+        let default_generator = generator();
+        assert_eq!(default_generator.cavern_heights(), -48..40);
+
+        for min_y in [-256, 0] {
+            let bounds = WorldBounds { min_y, max_y: 256 };
+            let generator = Generator::new(0, bounds).unwrap();
+
+            let heights = generator.cavern_heights();
+            assert_eq!(heights.start, min_y + Generator::LAKE_DEPTH);
+            assert!(!heights.is_empty());
+            for rules in &generator.worm_rules {
+                assert!(rules.start_heights.start >= heights.start);
+                assert!(!rules.start_heights.is_empty());
+            }
+        }
+    }
+}
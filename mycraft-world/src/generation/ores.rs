@@ -0,0 +1,168 @@
+use std::ops::Range;
+
+use cgmath::{ElementWise, Vector3};
+
+use crate::{blocks::BlockId, BlockCoords, Chunk, ChunkCoords, WorldBounds};
+
+use super::{hash, random, Generator, TerrainBlocks};
+
+/// How the veins of an ore are spread through the stone.
+pub struct OreRules {
+    pub block: BlockId,
+    /// World heights the veins start in, within the bounds of the world
+    pub heights: Range<i32>,
+    /// Number of steps of the walk through the stone, the same block can be visited twice.
+    /// At most the size of a chunk.
+    pub vein_size: i32,
+    /// Average number of veins starting in a chunk within the heights
+    pub veins_per_chunk: f64,
+}
+
+impl OreRules {
+    /// Rules of every ore in the order they are placed, the veins only replace stone.
+    /// The heights reaching below the bottom of the world are moved up into it, so that
+    /// every ore is generated.
+    pub fn all(blocks: &TerrainBlocks, bounds: WorldBounds) -> Vec<OreRules> {
+        let rules = vec![
+            OreRules {
+                block: blocks.coal_ore,
+                heights: 0..96,
+                vein_size: 12,
+                veins_per_chunk: 16.,
+            },
+            OreRules {
+                block: blocks.iron_ore,
+                heights: -32..56,
+                vein_size: 8,
+                veins_per_chunk: 10.,
+            },
+            OreRules {
+                block: blocks.gold_ore,
+                heights: -64..24,
+                vein_size: 7,
+                veins_per_chunk: 2.5,
+            },
+            OreRules {
+                block: blocks.diamond_ore,
+                heights: -64..-40,
+                vein_size: 5,
+                veins_per_chunk: 0.8,
+            },
+        ];
+
+        rules
+            .into_iter()
+            .map(|rules| {
+                let shift = (bounds.min_y - rules.heights.start).max(0);
+                OreRules {
+                    heights: rules.heights.start + shift..rules.heights.end + shift,
+                    ..rules
+                }
+            })
+            .collect()
+    }
+}
+
+const STEPS: [Vector3<i32>; 6] = [
+    Vector3::new(-1, 0, 0),
+    Vector3::new(1, 0, 0),
+    Vector3::new(0, -1, 0),
+    Vector3::new(0, 1, 0),
+    Vector3::new(0, 0, -1),
+    Vector3::new(0, 0, 1),
+];
+
+impl Generator {
+    /// Replaces the stone along the veins crossing the chunk. A vein is shorter than a
+    /// chunk, so only the ones starting in the chunk and its neighbors can reach it.
+    pub(super) fn place_ores(&self, chunk: &mut Chunk, chunk_coords: ChunkCoords) {
+        puffin::profile_function!();
+
+        let chunk_offset = chunk_coords.mul_element_wise(Chunk::SIZE);
+        for (ore_index, ore) in self.ore_rules.iter().enumerate() {
+            let ore_index = ore_index as i32;
+
+            for x in -1..=1 {
+                for y in -1..=1 {
+                    for z in -1..=1 {
+                        let source = chunk_coords + Vector3::new(x, y, z);
+                        let source_offset = source.mul_element_wise(Chunk::SIZE);
+                        if source_offset.y >= ore.heights.end
+                            || source_offset.y + Chunk::SIZE <= ore.heights.start
+                        {
+                            continue;
+                        }
+
+                        let source_values = [source.x, source.y, source.z, ore_index];
+                        let mut vein_count = ore.veins_per_chunk as i32;
+                        if random(self.seed, &source_values) < ore.veins_per_chunk.fract() {
+                            vein_count += 1;
+                        }
+
+                        for vein in 0..vein_count {
+                            let vein_hash = |i: i32| {
+                                hash(
+                                    self.seed,
+                                    &[source.x, source.y, source.z, ore_index, vein, i],
+                                )
+                            };
+
+                            let start = source_offset
+                                + Vector3::new(vein_hash(-1), vein_hash(-2), vein_hash(-3))
+                                    .map(|x| (x % Chunk::SIZE as u32) as i32);
+                            if !ore.heights.contains(&start.y) {
+                                continue;
+                            }
+
+                            let mut position = start;
+                            for step in 0..ore.vein_size {
+                                let coords: BlockCoords = position - chunk_offset;
+                                if (0..Chunk::SIZE).contains(&coords.x)
+                                    && (0..Chunk::SIZE).contains(&coords.y)
+                                    && (0..Chunk::SIZE).contains(&coords.z)
+                                    && chunk[coords].block_id == self.blocks.stone
+                                {
+                                    chunk[coords].block_id = ore.block;
+                                }
+                                position += STEPS[(vein_hash(step) % 6) as usize];
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::install_registries;
+
+    #[test]
+    fn every_ore_starts_within_the_bounds() {
+        install_registries();
+        let blocks = TerrainBlocks::resolve().unwrap();
+
+        for min_y in [-256, -64, 0, 32] {
+            let bounds = WorldBounds { min_y, max_y: 256 };
+            for rules in OreRules::all(&blocks, bounds) {
+                assert!(rules.heights.start >= min_y, "{:?}", rules.heights);
+                assert!(!rules.heights.is_empty());
+            }
+        }
+
+        // The default bounds reach the deepest ores
+        let default_rules = OreRules::all(&blocks, WorldBounds::default());
+        let deep_rules = OreRules::all(
+            &blocks,
+            WorldBounds {
+                min_y: -256,
+                max_y: 256,
+            },
+        );
+        for (default, deep) in default_rules.iter().zip(&deep_rules) {
+            assert_eq!(default.heights, deep.heights);
+        }
+    }
+}
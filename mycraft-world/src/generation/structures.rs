@@ -0,0 +1,513 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+    sync::OnceLock,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use cgmath::{ElementWise, Vector2};
+use serde::Deserialize;
+
+use crate::{blocks::BlockId, BlockCoords, Chunk, ChunkCoords};
+
+use super::{hash, random, Biome, Generator};
+
+/// Index of a template in the structure registry.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct StructureId(u16);
+
+/// Box of blocks filled with a single block, both corners included.
+struct Fill {
+    start: BlockCoords,
+    end: BlockCoords,
+    block: BlockId,
+}
+
+enum ChildPosition {
+    /// Relative to the origin of the parent
+    Relative(BlockCoords),
+    /// On the terrain surface at the column, relative to the origin of the parent
+    OnGround(Vector2<i32>),
+}
+
+struct Child {
+    position: ChildPosition,
+    template: StructureId,
+}
+
+/// Where a structure spawns on its own, at most once per square region of `spacing` blocks.
+pub struct Placement {
+    pub biomes: Vec<Biome>,
+    pub spacing: i32,
+    /// Chances for a region to get the structure
+    pub chance: f64,
+    /// Makes the regions of the placements independent
+    salt: i32,
+}
+
+pub struct StructureTemplate {
+    pub name: String,
+    /// Applied in order, the origin is the ground block under the structure
+    fills: Vec<Fill>,
+    children: Vec<Child>,
+    /// Farthest horizontal distance from the origin of the blocks, children included
+    reach: i32,
+    /// Highest block above the origin, or above the ground for the children placed on it
+    height: i32,
+    pub placements: Vec<Placement>,
+}
+
+pub struct StructureRegistry {
+    templates: Vec<StructureTemplate>,
+    ids_by_name: HashMap<String, StructureId>,
+}
+
+static REGISTRY: OnceLock<StructureRegistry> = OnceLock::new();
+
+#[inline]
+fn registry() -> &'static StructureRegistry {
+    REGISTRY
+        .get()
+        .expect("The structure registry is not loaded")
+}
+
+impl StructureTemplate {
+    pub fn by_id(id: StructureId) -> &'static Self {
+        &registry().templates[id.0 as usize]
+    }
+}
+
+impl StructureId {
+    pub fn by_name(name: &str) -> Option<StructureId> {
+        registry().ids_by_name.get(name).copied()
+    }
+}
+
+type CoordsDef = (i32, i32, i32);
+
+#[derive(Deserialize)]
+enum PartDef {
+    Block {
+        at: CoordsDef,
+        block: String,
+    },
+    Fill {
+        from: CoordsDef,
+        to: CoordsDef,
+        block: String,
+    },
+    Structure {
+        at: CoordsDef,
+        name: String,
+    },
+    OnGround {
+        at: (i32, i32),
+        name: String,
+    },
+}
+
+#[derive(Deserialize)]
+struct PlacementDef {
+    biomes: Vec<Biome>,
+    spacing: i32,
+    chance: f64,
+}
+
+#[derive(Deserialize)]
+struct StructureDef {
+    name: String,
+    parts: Vec<PartDef>,
+    #[serde(default)]
+    placements: Vec<PlacementDef>,
+}
+
+#[derive(Deserialize)]
+struct StructureDefinitions {
+    structures: Vec<StructureDef>,
+}
+
+fn to_coords((x, y, z): CoordsDef) -> BlockCoords {
+    BlockCoords { x, y, z }
+}
+
+/// Stable across runs, so that the placements do not depend on the order of the file.
+fn name_salt(name: &str, index: usize) -> i32 {
+    let name_hash = name.bytes().fold(0x811c_9dc5_u32, |hash, byte| {
+        (hash ^ byte as u32).wrapping_mul(0x0100_0193)
+    });
+    hash(name_hash, &[index as i32]) as i32
+}
+
+impl StructureDef {
+    fn into_template(
+        self,
+        ids_by_name: &HashMap<String, StructureId>,
+    ) -> Result<StructureTemplate> {
+        let get_block =
+            |name: &str| BlockId::by_name(name).ok_or_else(|| anyhow!("Unknown block {}", name));
+        let get_structure = |name: &str| {
+            ids_by_name
+                .get(name)
+                .copied()
+                .ok_or_else(|| anyhow!("Unknown structure {}", name))
+        };
+
+        let mut fills = vec![];
+        let mut children = vec![];
+        for part in self.parts {
+            match part {
+                PartDef::Block { at, block } => fills.push(Fill {
+                    start: to_coords(at),
+                    end: to_coords(at),
+                    block: get_block(&block)?,
+                }),
+                PartDef::Fill { from, to, block } => {
+                    let (from, to) = (to_coords(from), to_coords(to));
+                    fills.push(Fill {
+                        start: from.zip(to, i32::min),
+                        end: from.zip(to, i32::max),
+                        block: get_block(&block)?,
+                    })
+                }
+                PartDef::Structure { at, name } => children.push(Child {
+                    position: ChildPosition::Relative(to_coords(at)),
+                    template: get_structure(&name)?,
+                }),
+                PartDef::OnGround { at, name } => children.push(Child {
+                    position: ChildPosition::OnGround(at.into()),
+                    template: get_structure(&name)?,
+                }),
+            }
+        }
+
+        let placements = self
+            .placements
+            .into_iter()
+            .enumerate()
+            .map(|(i, def)| {
+                if def.spacing <= 0 {
+                    bail!("Spacing {} is not positive", def.spacing);
+                }
+                Ok(Placement {
+                    biomes: def.biomes,
+                    spacing: def.spacing,
+                    chance: def.chance,
+                    salt: name_salt(&self.name, i),
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(StructureTemplate {
+            name: self.name,
+            fills,
+            children,
+            reach: 0,
+            height: 0,
+            placements,
+        })
+    }
+}
+
+impl StructureRegistry {
+    pub fn load(path: &Path) -> Result<Self> {
+        let source = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to load structure definitions {}", path.display()))?;
+        let definitions: StructureDefinitions = ron::from_str(&source)
+            .with_context(|| format!("Failed to parse structure definitions {}", path.display()))?;
+
+        // Structures can contain the ones defined after them
+        let mut ids_by_name = HashMap::new();
+        for (i, def) in definitions.structures.iter().enumerate() {
+            if ids_by_name
+                .insert(def.name.clone(), StructureId(i as u16))
+                .is_some()
+            {
+                bail!("Structure {} is defined twice", def.name);
+            }
+        }
+
+        let mut templates = vec![];
+        for def in definitions.structures {
+            let name = def.name.clone();
+            let template = def
+                .into_template(&ids_by_name)
+                .with_context(|| format!("Invalid definition of structure {}", name))?;
+            templates.push(template);
+        }
+
+        let mut extents = HashMap::new();
+        for i in 0..templates.len() {
+            compute_extent(
+                &templates,
+                StructureId(i as u16),
+                &mut extents,
+                &mut HashSet::new(),
+            )?;
+        }
+        for (i, template) in templates.iter_mut().enumerate() {
+            (template.reach, template.height) = extents[&StructureId(i as u16)];
+        }
+
+        Ok(StructureRegistry {
+            templates,
+            ids_by_name,
+        })
+    }
+
+    /// Makes the registry available through [`StructureTemplate::by_id`] and
+    /// [`StructureId::by_name`].
+    pub fn install(self) -> Result<()> {
+        REGISTRY
+            .set(self)
+            .map_err(|_| anyhow!("The structure registry is already loaded"))
+    }
+}
+
+/// Returns the reach and the height of the structure.
+fn compute_extent(
+    templates: &[StructureTemplate],
+    id: StructureId,
+    extents: &mut HashMap<StructureId, (i32, i32)>,
+    visiting: &mut HashSet<StructureId>,
+) -> Result<(i32, i32)> {
+    if let Some(&extent) = extents.get(&id) {
+        return Ok(extent);
+    }
+
+    let template = &templates[id.0 as usize];
+    if !visiting.insert(id) {
+        bail!("Structure {} contains itself", template.name);
+    }
+
+    let (mut reach, mut height) = (0, 0);
+    for fill in &template.fills {
+        for coords in [fill.start, fill.end] {
+            reach = reach.max(coords.x.abs()).max(coords.z.abs());
+        }
+        height = height.max(fill.end.y);
+    }
+    for child in &template.children {
+        let (offset, child_origin_y) = match child.position {
+            ChildPosition::Relative(at) => (Vector2::new(at.x, at.z), at.y),
+            ChildPosition::OnGround(at) => (at, 0),
+        };
+        let (child_reach, child_height) =
+            compute_extent(templates, child.template, extents, visiting)?;
+        reach = reach.max(offset.x.abs().max(offset.y.abs()) + child_reach);
+        height = height.max(child_origin_y + child_height);
+    }
+
+    visiting.remove(&id);
+    extents.insert(id, (reach, height));
+    Ok((reach, height))
+}
+
+/// A template placed in the world, its children are placed separately.
+struct PlacedStructure {
+    template: &'static StructureTemplate,
+    origin: BlockCoords,
+}
+
+/// Highest block of the structures above the ground they stand on.
+pub(super) fn max_structure_height() -> i32 {
+    registry()
+        .templates
+        .iter()
+        .map(|template| template.height)
+        .max()
+        .unwrap_or(0)
+}
+
+impl Generator {
+    /// Stamps the part of every structure that overlaps the chunk. The structures are
+    /// found and stamped in the same order for every chunk, so the overlapping ones
+    /// end up the same on both sides of the chunk borders.
+    pub(super) fn stamp_structures(&self, chunk: &mut Chunk, chunk_coords: ChunkCoords) {
+        puffin::profile_function!();
+
+        let chunk_offset = chunk_coords.mul_element_wise(Chunk::SIZE);
+        for placed in self.structures_around(chunk_coords) {
+            for fill in &placed.template.fills {
+                let start = (placed.origin + fill.start - chunk_offset).map(|x| x.max(0));
+                let end = (placed.origin + fill.end - chunk_offset).map(|x| x.min(Chunk::SIZE - 1));
+                for x in start.x..=end.x {
+                    for y in start.y..=end.y {
+                        for z in start.z..=end.z {
+                            chunk[BlockCoords { x, y, z }].block_id = fill.block;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns the structures whose blocks may be within the columns of the chunk.
+    fn structures_around(&self, chunk_coords: ChunkCoords) -> Vec<PlacedStructure> {
+        let min = Vector2::new(chunk_coords.x, chunk_coords.z) * Chunk::SIZE;
+        let max = min + Vector2::new(Chunk::SIZE - 1, Chunk::SIZE - 1);
+
+        let mut result = vec![];
+        for template in &registry().templates {
+            for placement in &template.placements {
+                let spacing = placement.spacing;
+                let start = (min - Vector2::new(template.reach, template.reach))
+                    .map(|x| x.div_euclid(spacing));
+                let end = (max + Vector2::new(template.reach, template.reach))
+                    .map(|x| x.div_euclid(spacing));
+
+                for region_x in start.x..=end.x {
+                    for region_z in start.y..=end.y {
+                        let region = [region_x, region_z, placement.salt];
+                        if random(self.seed, &region) >= placement.chance {
+                            continue;
+                        }
+
+                        let column = Vector2::new(region_x, region_z) * spacing
+                            + Vector2::new(
+                                hash(self.seed, &[region_x, region_z, placement.salt, 1]),
+                                hash(self.seed, &[region_x, region_z, placement.salt, 2]),
+                            )
+                            .map(|x| (x % spacing as u32) as i32);
+
+                        let is_near = (min.x - template.reach..=max.x + template.reach)
+                            .contains(&column.x)
+                            && (min.y - template.reach..=max.y + template.reach)
+                                .contains(&column.y);
+                        if !is_near
+                            || !placement
+                                .biomes
+                                .contains(&self.get_biome(column.x, column.y))
+                        {
+                            continue;
+                        }
+
+                        if let Some(height) = self.get_land_height(column.x, column.y) {
+                            let origin = BlockCoords::new(column.x, height, column.y);
+                            self.place_structure(template, origin, &mut result);
+                        }
+                    }
+                }
+            }
+        }
+
+        result
+    }
+
+    fn place_structure(
+        &self,
+        template: &'static StructureTemplate,
+        origin: BlockCoords,
+        result: &mut Vec<PlacedStructure>,
+    ) {
+        result.push(PlacedStructure { template, origin });
+
+        for child in &template.children {
+            let child_origin = match child.position {
+                ChildPosition::Relative(at) => origin + at,
+                ChildPosition::OnGround(at) => {
+                    let (x, z) = (origin.x + at.x, origin.z + at.y);
+                    BlockCoords::new(x, self.get_height(x, z), z)
+                }
+            };
+            self.place_structure(
+                StructureTemplate::by_id(child.template),
+                child_origin,
+                result,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generation::tests::{generate, generator};
+
+    /// Block the structures leave at the coords, the later fills cover the earlier ones.
+    fn stamped_block(placed: &[PlacedStructure], coords: BlockCoords) -> Option<BlockId> {
+        let mut block = None;
+        for placed in placed {
+            for fill in &placed.template.fills {
+                let (start, end) = (placed.origin + fill.start, placed.origin + fill.end);
+                if (start.x..=end.x).contains(&coords.x)
+                    && (start.y..=end.y).contains(&coords.y)
+                    && (start.z..=end.z).contains(&coords.z)
+                {
+                    block = Some(fill.block);
+                }
+            }
+        }
+        block
+    }
+
+    /// Returns the coords of the blocks of the structure.
+    fn structure_blocks(placed: &PlacedStructure) -> Vec<BlockCoords> {
+        let mut blocks = vec![];
+        for fill in &placed.template.fills {
+            let (start, end) = (placed.origin + fill.start, placed.origin + fill.end);
+            for x in start.x..=end.x {
+                for y in start.y..=end.y {
+                    for z in start.z..=end.z {
+                        blocks.push(BlockCoords { x, y, z });
+                    }
+                }
+            }
+        }
+        blocks
+    }
+
+    #[test]
+    fn structure_across_a_chunk_border_is_stamped_on_both_sides() {
+        let generator = generator();
+        let to_chunk_coords = |coords: BlockCoords| coords.map(|x| x.div_euclid(Chunk::SIZE));
+
+        // The first structure found that spans two chunks along the x axis
+        let (placed, blocks) = (-8..8)
+            .flat_map(|x| (-8..8).map(move |z| ChunkCoords::new(x, 0, z)))
+            .flat_map(|coords| generator.structures_around(coords))
+            .map(|placed| {
+                let blocks = structure_blocks(&placed);
+                (placed, blocks)
+            })
+            .find(|(_, blocks)| {
+                let first = to_chunk_coords(blocks[0]);
+                blocks.iter().all(|&coords| {
+                    let chunk_coords = to_chunk_coords(coords);
+                    chunk_coords.y == first.y && chunk_coords.z == first.z
+                }) && blocks
+                    .iter()
+                    .any(|&coords| to_chunk_coords(coords).x != first.x)
+            })
+            .expect("No structure across a chunk border");
+
+        let chunks = [
+            blocks.iter().min_by_key(|coords| coords.x),
+            blocks.iter().max_by_key(|coords| coords.x),
+        ]
+        .map(|coords| {
+            let coords = to_chunk_coords(*coords.unwrap());
+            (coords, generate(&generator, coords))
+        });
+
+        for (chunk_coords, chunk) in &chunks {
+            let placed_around = generator.structures_around(*chunk_coords);
+            assert!(placed_around.iter().any(|x| {
+                std::ptr::eq(x.template, placed.template) && x.origin == placed.origin
+            }));
+
+            for &coords in &blocks {
+                if to_chunk_coords(coords) != *chunk_coords {
+                    continue;
+                }
+                let local_coords = coords.map(|x| x.rem_euclid(Chunk::SIZE));
+                assert_eq!(
+                    Some(chunk[local_coords].block_id),
+                    stamped_block(&placed_around, coords),
+                    "{:?} of {}",
+                    coords,
+                    placed.template.name
+                );
+            }
+        }
+    }
+}
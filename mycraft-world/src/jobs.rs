@@ -0,0 +1,153 @@
+use std::{
+    cell::Cell,
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+};
+
+use super::{
+    generation::Generator, light::recalculate_light, mesh::ChunkMeshes, utils::ChunkNeighborhood,
+    Chunk, ChunkCoords,
+};
+
+pub type JobId = u64;
+
+pub enum JobKind {
+    Generate,
+    Light(ChunkNeighborhood),
+    Mesh(ChunkNeighborhood),
+}
+
+struct Job {
+    id: JobId,
+    coords: ChunkCoords,
+    kind: JobKind,
+}
+
+pub enum JobOutput {
+    Generated(Arc<Chunk>),
+    Lit(Arc<Chunk>),
+    Meshed(ChunkMeshes),
+}
+
+pub struct JobResult {
+    pub id: JobId,
+    pub coords: ChunkCoords,
+    pub output: JobOutput,
+}
+
+/// Runs chunk generation, light calculation and mesh building on worker threads.
+pub struct JobPool {
+    job_sender: Option<Sender<Job>>,
+    result_receiver: Receiver<JobResult>,
+    workers: Vec<JoinHandle<()>>,
+
+    next_id: Cell<JobId>,
+    pending_count: Cell<usize>,
+}
+
+impl JobPool {
+    /// Maximum number of jobs waiting for a worker, per worker. Keeps the workers busy,
+    /// while letting closer chunks be submitted first when the camera moves.
+    const PENDING_JOBS_PER_WORKER: usize = 32;
+
+    pub fn new(generator: Arc<Generator>) -> Self {
+        let worker_count = thread::available_parallelism()
+            .map(|x| x.get().saturating_sub(1))
+            .unwrap_or(1)
+            .max(1);
+
+        let (job_sender, job_receiver) = mpsc::channel::<Job>();
+        let (result_sender, result_receiver) = mpsc::channel();
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+
+        let workers = (0..worker_count)
+            .map(|i| {
+                let generator = generator.clone();
+                let job_receiver = job_receiver.clone();
+                let result_sender = result_sender.clone();
+
+                thread::Builder::new()
+                    .name(format!("Chunk Worker {}", i))
+                    .spawn(move || loop {
+                        let job = job_receiver.lock().unwrap().recv();
+                        let Ok(job) = job else {
+                            break;
+                        };
+
+                        let result = JobResult {
+                            id: job.id,
+                            coords: job.coords,
+                            output: run_job(&generator, job.coords, job.kind),
+                        };
+                        if result_sender.send(result).is_err() {
+                            break;
+                        }
+                    })
+                    .expect("Failed to spawn a chunk worker thread")
+            })
+            .collect();
+
+        JobPool {
+            job_sender: Some(job_sender),
+            result_receiver,
+            workers,
+
+            next_id: Cell::new(0),
+            pending_count: Cell::new(0),
+        }
+    }
+
+    pub fn has_capacity(&self) -> bool {
+        self.pending_count.get() < self.workers.len() * Self::PENDING_JOBS_PER_WORKER
+    }
+
+    pub fn submit(&self, coords: ChunkCoords, kind: JobKind) -> JobId {
+        let id = self.next_id.get();
+        self.next_id.set(id + 1);
+        self.pending_count.set(self.pending_count.get() + 1);
+
+        self.job_sender
+            .as_ref()
+            .unwrap()
+            .send(Job { id, coords, kind })
+            .expect("All chunk workers have stopped");
+        id
+    }
+
+    pub fn try_receive(&self) -> Option<JobResult> {
+        let result = self.result_receiver.try_recv().ok()?;
+        self.pending_count.set(self.pending_count.get() - 1);
+        Some(result)
+    }
+}
+
+impl Drop for JobPool {
+    fn drop(&mut self) {
+        // Closing the channel makes the workers exit their loops
+        self.job_sender = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn run_job(generator: &Generator, coords: ChunkCoords, kind: JobKind) -> JobOutput {
+    match kind {
+        JobKind::Generate => {
+            let mut chunk = Chunk::new();
+            generator.generate_chunk(&mut chunk, coords);
+            JobOutput::Generated(Arc::new(chunk))
+        }
+
+        JobKind::Light(neighborhood) => {
+            let mut chunk = neighborhood.center().clone();
+            recalculate_light(&mut chunk, &neighborhood);
+            JobOutput::Lit(Arc::new(chunk))
+        }
+
+        JobKind::Mesh(neighborhood) => JobOutput::Meshed(ChunkMeshes::generate(&neighborhood)),
+    }
+}
@@ -0,0 +1,767 @@
+//! Blocks, terrain generation, lighting and fluids of a Mycraft world. Does not depend
+//! on a window or a GPU, chunk meshes are produced as plain vertex data.
+
+pub mod aabb;
+pub mod blocks;
+mod chunk_queue;
+pub mod consts;
+mod fluid;
+pub mod generation;
+mod jobs;
+mod light;
+pub mod mesh;
+pub mod raycasting;
+mod storage;
+#[cfg(test)]
+mod test_utils;
+mod utils;
+
+pub use light::{changed_faces, recalculate_light};
+pub use storage::{WorldMetadata, WorldStorage};
+pub use utils::{
+    get_chunk_and_block_coords, to_chunk_offset, to_local_chunk_coords, ChunkNeighborhood,
+};
+
+use std::{
+    cell::{Ref, RefCell, RefMut},
+    collections::HashMap,
+    ops::{Index, IndexMut, Range},
+    path::PathBuf,
+    rc::Rc,
+    sync::Arc,
+    time::Instant,
+};
+
+use anyhow::{bail, Result};
+use cgmath::Vector3;
+use serde::{Deserialize, Serialize};
+
+use self::{
+    blocks::{Block, BlockId},
+    chunk_queue::ChunkQueue,
+    fluid::FluidSimulation,
+    generation::Generator,
+    jobs::{JobId, JobKind, JobOutput, JobPool},
+    mesh::ChunkMeshes,
+    utils::ChunkNeighborhoodMut,
+};
+use crate::consts::{
+    DEFAULT_MAX_HEIGHT, DEFAULT_MIN_HEIGHT, MAX_UPDATE_TIME, RENDER_DISTANCE,
+    UNLOAD_DISTANCE_MARGIN,
+};
+
+pub type LightLevel = u8;
+pub type FluidLevel = u8;
+
+#[derive(Clone, Copy)]
+pub struct Cell {
+    pub block_id: BlockId,
+    pub sun_light: LightLevel,
+    pub block_light: LightLevel,
+    /// Only meaningful for fluid blocks, see [`fluid::SOURCE`].
+    pub fluid_level: FluidLevel,
+}
+
+impl Cell {
+    #[inline]
+    pub fn get_block(&self) -> &'static Block {
+        Block::by_id(self.block_id)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum ChunkStatus {
+    NotGenerated,
+    LightmapOutdated,
+    GraphicsOutdated,
+    Ready,
+}
+
+#[derive(Clone)]
+pub struct Chunk {
+    data: [[[Cell; Self::SIZE as usize]; Self::SIZE as usize]; Self::SIZE as usize],
+}
+
+impl Default for Chunk {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Chunk {
+    pub const SIZE: i32 = 16;
+
+    pub fn new() -> Self {
+        Chunk {
+            data: [[[Cell {
+                block_id: BlockId::AIR,
+                sun_light: 0,
+                block_light: 0,
+                fluid_level: fluid::SOURCE,
+            }; Self::SIZE as usize]; Self::SIZE as usize]; Self::SIZE as usize],
+        }
+    }
+
+    /// Returns true if all the blocks of the chunk are air.
+    pub fn is_empty(&self) -> bool {
+        self.data
+            .iter()
+            .flatten()
+            .flatten()
+            .all(|cell| cell.block_id == BlockId::AIR)
+    }
+}
+
+impl Index<BlockCoords> for Chunk {
+    type Output = Cell;
+
+    #[inline]
+    fn index(&self, coords: BlockCoords) -> &Self::Output {
+        &self.data[coords.x as usize][coords.y as usize][coords.z as usize]
+    }
+}
+
+impl IndexMut<BlockCoords> for Chunk {
+    #[inline]
+    fn index_mut(&mut self, coords: BlockCoords) -> &mut Self::Output {
+        &mut self.data[coords.x as usize][coords.y as usize][coords.z as usize]
+    }
+}
+
+pub type ChunkCoords = Vector3<i32>;
+pub type BlockCoords = Vector3<i32>;
+
+pub struct LoadedChunk {
+    /// Shared with the jobs, gets copied on write if a job still holds it.
+    data: Arc<Chunk>,
+    status: ChunkStatus,
+    /// Whether the chunk was changed since it was last saved.
+    modified: bool,
+    /// The job whose result is expected, results of other jobs are dropped.
+    pending_job: Option<PendingJob>,
+}
+
+struct PendingJob {
+    id: JobId,
+    /// The status the chunk had before the job was submitted.
+    previous_status: ChunkStatus,
+}
+
+impl LoadedChunk {
+    fn new(data: Arc<Chunk>, status: ChunkStatus) -> Self {
+        LoadedChunk {
+            data,
+            status,
+            modified: false,
+            pending_job: None,
+        }
+    }
+
+    /// Change the chunk status if the current one is higher.
+    fn invalidate(&mut self, new_status: ChunkStatus) {
+        self.status = self.status.min(new_status);
+    }
+
+    /// Drops the result of the running job, the chunk goes through its stage again.
+    fn cancel_job(&mut self) {
+        if let Some(job) = self.pending_job.take() {
+            self.invalidate(job.previous_status);
+        }
+    }
+}
+
+/// Heights the blocks of a world are within, chunks only exist between them.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct WorldBounds {
+    /// Height of the lowest blocks, a multiple of the chunk size
+    pub min_y: i32,
+    /// Height above the highest blocks, a multiple of the chunk size
+    pub max_y: i32,
+}
+
+impl Default for WorldBounds {
+    fn default() -> Self {
+        WorldBounds {
+            min_y: DEFAULT_MIN_HEIGHT,
+            max_y: DEFAULT_MAX_HEIGHT,
+        }
+    }
+}
+
+impl WorldBounds {
+    pub fn new(min_y: i32, max_y: i32) -> Result<Self> {
+        if min_y % Chunk::SIZE != 0 || max_y % Chunk::SIZE != 0 {
+            bail!(
+                "The world bounds {}..{} are not multiples of the chunk size {}",
+                min_y,
+                max_y,
+                Chunk::SIZE
+            );
+        }
+        if min_y >= max_y {
+            bail!("The world bounds {}..{} are empty", min_y, max_y);
+        }
+        Ok(WorldBounds { min_y, max_y })
+    }
+
+    /// Bounds between the given heights, a missing one is replaced by its default.
+    /// Returns None if neither is given.
+    pub fn from_heights(min_y: Option<i32>, max_y: Option<i32>) -> Result<Option<Self>> {
+        if min_y.is_none() && max_y.is_none() {
+            return Ok(None);
+        }
+
+        let default = WorldBounds::default();
+        let bounds = WorldBounds::new(
+            min_y.unwrap_or(default.min_y),
+            max_y.unwrap_or(default.max_y),
+        )?;
+        Ok(Some(bounds))
+    }
+
+    #[inline]
+    pub fn contains(&self, y: i32) -> bool {
+        (self.min_y..self.max_y).contains(&y)
+    }
+
+    /// Vertical coords of the chunks within the bounds.
+    pub fn chunk_layers(&self) -> Range<i32> {
+        self.min_y / Chunk::SIZE..self.max_y / Chunk::SIZE
+    }
+}
+
+pub struct WorldDesc {
+    /// Directory the world is saved to.
+    pub save_path: PathBuf,
+    /// Seed of a new world, an existing world keeps its seed. Random if not given.
+    pub seed: Option<u32>,
+    /// Bounds of a new world, an existing world keeps its bounds. The default ones if
+    /// not given.
+    pub bounds: Option<WorldBounds>,
+    /// Whether to build the meshes of the chunks, not needed without rendering.
+    pub generate_meshes: bool,
+}
+
+pub struct World {
+    chunks: HashMap<ChunkCoords, Rc<RefCell<LoadedChunk>>>,
+    chunk_queue: ChunkQueue,
+    job_pool: JobPool,
+    storage: WorldStorage,
+    fluids: FluidSimulation,
+    generator: Arc<Generator>,
+    /// Shared by the chunks above the terrain until something is placed in them
+    sky_chunk: Arc<Chunk>,
+    generate_meshes: bool,
+    seed: u32,
+    bounds: WorldBounds,
+
+    /// Meshes built since they were last taken.
+    new_meshes: Vec<(ChunkCoords, ChunkMeshes)>,
+    /// Chunks unloaded since they were last taken, only kept when building the meshes.
+    unloaded_chunks: Vec<ChunkCoords>,
+
+    /// Horizontal radius in chunks of the area loaded around the camera.
+    render_distance: i32,
+    /// The chunk column around which the chunks were loaded the last time.
+    loaded_area_center: Option<ChunkCoords>,
+
+    prev_viewer_chunk_coords: ChunkCoords,
+}
+
+impl World {
+    pub fn new(desc: WorldDesc) -> Result<Self> {
+        let metadata = WorldMetadata::load_or_create(&desc.save_path, desc.seed, desc.bounds)?;
+        if desc.seed.is_some_and(|seed| seed != metadata.seed) {
+            log::warn!(
+                "The world was created with the seed {}, ignoring the given seed",
+                metadata.seed
+            );
+        }
+        if desc.bounds.is_some_and(|bounds| bounds != metadata.bounds) {
+            log::warn!(
+                "The world was created with the bounds {}..{}, ignoring the given bounds",
+                metadata.bounds.min_y,
+                metadata.bounds.max_y
+            );
+        }
+
+        let generator = Arc::new(Generator::new(metadata.seed, metadata.bounds)?);
+        let mut sky_chunk = Chunk::new();
+        light::light_sky_chunk(&mut sky_chunk);
+
+        Ok(World {
+            chunks: HashMap::new(),
+            chunk_queue: ChunkQueue::new(),
+            job_pool: JobPool::new(generator.clone()),
+            seed: metadata.seed,
+            bounds: metadata.bounds,
+            storage: WorldStorage::new(desc.save_path)?,
+            fluids: FluidSimulation::new(),
+            generator,
+            sky_chunk: Arc::new(sky_chunk),
+            generate_meshes: desc.generate_meshes,
+
+            new_meshes: Vec::new(),
+            unloaded_chunks: Vec::new(),
+
+            render_distance: RENDER_DISTANCE,
+            loaded_area_center: None,
+
+            prev_viewer_chunk_coords: ChunkCoords::new(0, 0, 0),
+        })
+    }
+
+    pub fn load_chunk(&mut self, coords: ChunkCoords) {
+        if self.chunks.contains_key(&coords) {
+            return;
+        }
+
+        let mut chunk = Chunk::new();
+        let (data, status) = match self.storage.load_chunk(coords, &mut chunk) {
+            Ok(true) => {
+                self.invalidate_neighbors(coords, ChunkStatus::LightmapOutdated);
+                (Arc::new(chunk), ChunkStatus::GraphicsOutdated)
+            }
+            // Neither generated, lit nor meshed, the chunk is all air in full sunlight
+            Ok(false) if self.generator.is_above_terrain(coords) => {
+                self.invalidate_neighbors(coords, ChunkStatus::LightmapOutdated);
+                (self.sky_chunk.clone(), ChunkStatus::Ready)
+            }
+            Ok(false) => (Arc::new(chunk), ChunkStatus::NotGenerated),
+            Err(err) => {
+                log::error!("Failed to load chunk, regenerating it: {:?}", err);
+                (Arc::new(chunk), ChunkStatus::NotGenerated)
+            }
+        };
+
+        let chunk = Rc::new(RefCell::new(LoadedChunk::new(data, status)));
+        self.chunks.insert(coords, chunk.clone());
+        self.chunk_queue.insert(coords, chunk);
+    }
+
+    pub fn render_distance(&self) -> i32 {
+        self.render_distance
+    }
+
+    pub fn set_render_distance(&mut self, render_distance: i32) {
+        if render_distance != self.render_distance {
+            self.render_distance = render_distance;
+            self.loaded_area_center = None;
+        }
+    }
+
+    /// Loads the chunks within the render distance around the viewer, and unloads the ones
+    /// that went farther than the render distance plus a margin.
+    fn update_loaded_area(&mut self, viewer_chunk_coords: ChunkCoords) {
+        let center = ChunkCoords::new(viewer_chunk_coords.x, 0, viewer_chunk_coords.z);
+        if self.loaded_area_center == Some(center) {
+            return;
+        }
+
+        puffin::profile_function!();
+        self.loaded_area_center = Some(center);
+
+        self.unload_far_chunks(center);
+
+        let distance = self.render_distance;
+        for x in -distance..distance {
+            for z in -distance..distance {
+                if x * x + z * z < distance * distance {
+                    for y in self.bounds.chunk_layers() {
+                        self.load_chunk(center + ChunkCoords { x, y, z });
+                    }
+                }
+            }
+        }
+    }
+
+    fn unload_far_chunks(&mut self, center: ChunkCoords) {
+        let max_distance = self.render_distance + UNLOAD_DISTANCE_MARGIN;
+        let storage = &mut self.storage;
+        let unloaded_chunks = &mut self.unloaded_chunks;
+        let generate_meshes = self.generate_meshes;
+
+        self.chunks.retain(|&coords, chunk| {
+            let offset = coords - center;
+            if offset.x * offset.x + offset.z * offset.z < max_distance * max_distance {
+                return true;
+            }
+
+            let chunk = chunk.borrow();
+            if chunk.modified {
+                if let Err(err) = storage.save_chunk(coords, &chunk.data) {
+                    log::error!("Failed to save chunk, keeping it loaded: {:?}", err);
+                    return true;
+                }
+            }
+
+            if generate_meshes {
+                unloaded_chunks.push(coords);
+            }
+            false
+        });
+
+        let chunks = &self.chunks;
+        self.chunk_queue
+            .retain(|coords| chunks.contains_key(&coords));
+        self.storage.close_unused_regions(chunks.keys().copied());
+    }
+
+    /// Writes all the modified chunks to the disk.
+    pub fn save(&mut self) -> Result<()> {
+        puffin::profile_function!();
+
+        for (&coords, chunk) in self.chunks.iter() {
+            let mut chunk = chunk.borrow_mut();
+            if chunk.modified {
+                self.storage.save_chunk(coords, &chunk.data)?;
+                chunk.modified = false;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Loads the chunks around the viewer and brings them up to date, closest first.
+    pub fn update(&mut self, viewer_position: Vector3<f32>) {
+        puffin::profile_function!();
+
+        let update_start = Instant::now();
+
+        let (viewer_chunk_coords, _) = get_chunk_and_block_coords(viewer_position);
+        self.update_loaded_area(viewer_chunk_coords);
+
+        if viewer_chunk_coords != self.prev_viewer_chunk_coords {
+            self.chunk_queue.mark_unsorted();
+            self.prev_viewer_chunk_coords = viewer_chunk_coords;
+        }
+
+        if self.chunk_queue.needs_to_be_sorted() {
+            puffin::profile_scope!("Chunk queue sort");
+
+            self.chunk_queue.sort(viewer_chunk_coords);
+        }
+
+        self.apply_job_results(update_start);
+        self.update_fluids();
+
+        for (coords, chunk) in self.chunk_queue.iter() {
+            if !self.job_pool.has_capacity() {
+                break;
+            }
+
+            let mut chunk = chunk.borrow_mut();
+            if chunk.pending_job.is_none() {
+                self.submit_job(coords, &mut chunk);
+            }
+
+            let update_time = Instant::now() - update_start;
+            if update_time > MAX_UPDATE_TIME {
+                break;
+            }
+        }
+    }
+
+    /// Returns true if the chunks are not waiting to be generated, lit or meshed.
+    pub fn is_idle(&self) -> bool {
+        self.chunks
+            .values()
+            .all(|chunk| chunk.borrow().status == ChunkStatus::Ready)
+    }
+
+    /// Returns the meshes built since the last call, a chunk may appear more than once.
+    pub fn take_new_meshes(&mut self) -> Vec<(ChunkCoords, ChunkMeshes)> {
+        std::mem::take(&mut self.new_meshes)
+    }
+
+    /// Returns the chunks unloaded since the last call, their meshes are not valid anymore.
+    /// A chunk may be loaded again and appear in the new meshes after that.
+    pub fn take_unloaded_chunks(&mut self) -> Vec<ChunkCoords> {
+        std::mem::take(&mut self.unloaded_chunks)
+    }
+
+    /// Submits the job that brings the chunk to the next status.
+    fn submit_job(&self, coords: ChunkCoords, chunk: &mut LoadedChunk) {
+        // The shared sky chunk has no faces, and its light only has to be calculated if
+        // a neighbor brings another light than the sun's
+        if Arc::ptr_eq(&chunk.data, &self.sky_chunk)
+            && (chunk.status != ChunkStatus::LightmapOutdated
+                || light::is_lit_by_sky_only(&ChunkNeighborhood::new(
+                    self,
+                    chunk.data.clone(),
+                    coords,
+                )))
+        {
+            chunk.status = ChunkStatus::Ready;
+            return;
+        }
+
+        // The status is advanced right away, if the chunk gets invalidated while the job
+        // is running, it will go through the stage again.
+        let previous_status = chunk.status;
+        let kind = match chunk.status {
+            ChunkStatus::NotGenerated => JobKind::Generate,
+            ChunkStatus::LightmapOutdated => {
+                chunk.status = ChunkStatus::GraphicsOutdated;
+                JobKind::Light(ChunkNeighborhood::new(self, chunk.data.clone(), coords))
+            }
+            ChunkStatus::GraphicsOutdated if !self.generate_meshes => {
+                chunk.status = ChunkStatus::Ready;
+                return;
+            }
+            ChunkStatus::GraphicsOutdated => {
+                chunk.status = ChunkStatus::Ready;
+                JobKind::Mesh(ChunkNeighborhood::new(self, chunk.data.clone(), coords))
+            }
+            ChunkStatus::Ready => return,
+        };
+
+        chunk.pending_job = Some(PendingJob {
+            id: self.job_pool.submit(coords, kind),
+            previous_status,
+        });
+    }
+
+    fn apply_job_results(&mut self, update_start: Instant) {
+        puffin::profile_function!();
+
+        while let Some(result) = self.job_pool.try_receive() {
+            let Some(chunk) = self.chunks.get(&result.coords) else {
+                continue;
+            };
+
+            // The chunk was changed while the job was running, or reloaded
+            let mut chunk = chunk.borrow_mut();
+            if chunk.pending_job.as_ref().map(|x| x.id) != Some(result.id) {
+                continue;
+            }
+            chunk.pending_job = None;
+
+            match result.output {
+                JobOutput::Generated(data) => {
+                    chunk.data = data;
+                    chunk.status = ChunkStatus::LightmapOutdated;
+                    self.invalidate_neighbors(result.coords, ChunkStatus::LightmapOutdated);
+                }
+                JobOutput::Lit(data) => {
+                    // Light spreads across the borders, sunlight down the whole column
+                    for direction in light::changed_faces(&chunk.data, &data) {
+                        let neighbor_coords = result.coords + direction;
+                        if let Some(neighbor) = self.chunks.get(&neighbor_coords) {
+                            neighbor
+                                .borrow_mut()
+                                .invalidate(ChunkStatus::LightmapOutdated);
+                        }
+                    }
+                    chunk.data = data;
+                }
+                JobOutput::Meshed(meshes) => self.new_meshes.push((result.coords, meshes)),
+            }
+
+            let update_time = Instant::now() - update_start;
+            if update_time > MAX_UPDATE_TIME {
+                break;
+            }
+        }
+    }
+
+    pub fn is_chunk_loaded(&self, coords: ChunkCoords) -> bool {
+        self.chunks.contains_key(&coords)
+    }
+
+    /// Returns false if the chunk is not loaded or its terrain is not generated yet.
+    pub fn is_chunk_generated(&self, coords: ChunkCoords) -> bool {
+        self.chunks
+            .get(&coords)
+            .is_some_and(|chunk| chunk.borrow().status > ChunkStatus::NotGenerated)
+    }
+
+    #[inline]
+    pub fn borrow_chunk(&self, coords: ChunkCoords) -> Option<Ref<'_, Chunk>> {
+        let chunk = self.chunks.get(&coords)?.borrow();
+        Some(Ref::map(chunk, |x| x.data.as_ref()))
+    }
+
+    #[inline]
+    pub fn borrow_mut_chunk(&self, coords: ChunkCoords) -> Option<RefMut<'_, Chunk>> {
+        let chunk = self.chunks.get(&coords)?.borrow_mut();
+        Some(RefMut::map(chunk, |x| Arc::make_mut(&mut x.data)))
+    }
+
+    /// Returns a snapshot of the chunk that can be sent to other threads.
+    #[inline]
+    pub fn share_chunk(&self, coords: ChunkCoords) -> Option<Arc<Chunk>> {
+        Some(self.chunks.get(&coords)?.borrow().data.clone())
+    }
+
+    pub fn get_block(&self, coords: BlockCoords) -> Option<&'static Block> {
+        let (chunk_coords, block_coords) = to_local_chunk_coords(coords);
+        self.borrow_chunk(chunk_coords)
+            .map(|chunk| chunk[block_coords].get_block())
+    }
+
+    fn invalidate_neighbors(&self, chunk_coords: ChunkCoords, new_status: ChunkStatus) {
+        for x in -1..=1 {
+            for y in -1..=1 {
+                for z in -1..=1 {
+                    if x != 0 || y != 0 || z != 0 {
+                        let coords = chunk_coords + ChunkCoords { x, y, z };
+                        if let Some(chunk) = self.chunks.get(&coords) {
+                            chunk.borrow_mut().invalidate(new_status);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Does nothing outside of the world bounds.
+    pub fn set_block(&mut self, coords: BlockCoords, block_id: BlockId) {
+        if !self.bounds.contains(coords.y) {
+            return;
+        }
+        self.set_cell_contents(coords, block_id, fluid::SOURCE);
+    }
+
+    fn update_fluids(&mut self) {
+        puffin::profile_function!();
+
+        // All the changes are computed before applying them,
+        // so that fluid spreads by one block per tick
+        let changes: Vec<_> = self
+            .fluids
+            .take_due_updates()
+            .into_iter()
+            .filter_map(|coords| {
+                fluid::next_state(self, coords).map(|(block_id, level)| (coords, block_id, level))
+            })
+            .collect();
+
+        for (coords, block_id, level) in changes {
+            self.set_cell_contents(coords, block_id, level);
+        }
+    }
+
+    fn set_cell_contents(&mut self, coords: BlockCoords, block_id: BlockId, level: FluidLevel) {
+        let (chunk_coords, block_coords) = to_local_chunk_coords(coords);
+        if !self.is_chunk_generated(chunk_coords) {
+            return;
+        }
+
+        {
+            let mut chunk = self.chunks[&chunk_coords].borrow_mut();
+            let cell = &mut Arc::make_mut(&mut chunk.data)[block_coords];
+            cell.block_id = block_id;
+            cell.fluid_level = level;
+            chunk.modified = true;
+            chunk.cancel_job();
+            chunk.invalidate(ChunkStatus::GraphicsOutdated);
+        }
+
+        self.update_light_around(coords);
+
+        // Neighboring chunks may have faces next to the block
+        for direction in [Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z()] {
+            for neighbor_coords in [coords - direction, coords + direction] {
+                let (neighbor_chunk_coords, _) = to_local_chunk_coords(neighbor_coords);
+                if let Some(chunk) = self.chunks.get(&neighbor_chunk_coords) {
+                    chunk.borrow_mut().invalidate(ChunkStatus::GraphicsOutdated);
+                }
+            }
+        }
+
+        self.fluids.schedule_around(coords);
+    }
+
+    /// Updates the light around the changed block in the chunk and its neighbors.
+    fn update_light_around(&self, coords: BlockCoords) {
+        let (chunk_coords, block_coords) = to_local_chunk_coords(coords);
+
+        let changed_chunks = {
+            let Some(mut chunk) = self.borrow_mut_chunk(chunk_coords) else {
+                return;
+            };
+            let mut neighborhood = ChunkNeighborhoodMut::new(self, &mut chunk, chunk_coords);
+            light::update_light_around(&mut neighborhood, block_coords);
+            neighborhood.changed_chunks()
+        };
+
+        // Results of the running jobs would overwrite the new light
+        for coords in &changed_chunks {
+            let mut chunk = self.chunks[coords].borrow_mut();
+            chunk.modified = true;
+            chunk.cancel_job();
+            chunk.invalidate(ChunkStatus::GraphicsOutdated);
+        }
+
+        // Sunlight may go further down than the neighborhood reaches,
+        // the chunks below get lit again one by one
+        let below_coords = chunk_coords - Vector3::unit_y();
+        if changed_chunks.contains(&below_coords) {
+            if let Some(chunk) = self.chunks.get(&(below_coords - Vector3::unit_y())) {
+                chunk.borrow_mut().invalidate(ChunkStatus::LightmapOutdated);
+            }
+        }
+    }
+
+    pub fn seed(&self) -> u32 {
+        self.seed
+    }
+
+    pub fn bounds(&self) -> WorldBounds {
+        self.bounds
+    }
+
+    /// Height of the generated terrain surface at the column, before any block is
+    /// placed or removed.
+    pub fn terrain_height(&self, x: i32, z: i32) -> i32 {
+        self.generator.get_height(x, z)
+    }
+
+    pub fn num_chunks_loaded(&self) -> usize {
+        self.chunks.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::test_utils::{install_registries, temp_dir};
+
+    #[test]
+    fn chunks_above_the_terrain_share_the_sky_chunk() {
+        install_registries();
+        let mut world = World::new(WorldDesc {
+            save_path: temp_dir("world-sky"),
+            seed: Some(1234),
+            bounds: None,
+            generate_meshes: true,
+        })
+        .unwrap();
+        world.set_render_distance(2);
+
+        let start = Instant::now();
+        world.update(Vector3::new(0., 40., 0.));
+        while !world.is_idle() {
+            assert!(start.elapsed() < Duration::from_secs(60));
+            std::thread::sleep(Duration::from_millis(1));
+            world.update(Vector3::new(0., 40., 0.));
+        }
+
+        let mut sky_count = 0;
+        for (&coords, chunk) in &world.chunks {
+            if world.generator.is_above_terrain(coords) {
+                assert!(Arc::ptr_eq(&chunk.borrow().data, &world.sky_chunk));
+                sky_count += 1;
+            }
+        }
+        assert!(sky_count > 0);
+
+        let meshes = world.take_new_meshes();
+        assert!(meshes
+            .iter()
+            .all(|(coords, _)| !world.generator.is_above_terrain(*coords)));
+    }
+}
@@ -0,0 +1,499 @@
+use std::collections::VecDeque;
+
+use cgmath::Vector3;
+
+use super::{
+    utils::{ChunkNeighborhood, ChunkNeighborhoodMut},
+    BlockCoords, Cell, Chunk, LightLevel,
+};
+
+#[rustfmt::skip]
+const DIRECTIONS: [Vector3<i32>; 6] = [
+    Vector3 { x:  0, y:  0, z: -1 },
+    Vector3 { x:  0, y:  0, z:  1 },
+    Vector3 { x:  0, y: -1, z:  0 },
+    Vector3 { x:  0, y:  1, z:  0 },
+    Vector3 { x: -1, y:  0, z:  0 },
+    Vector3 { x:  1, y:  0, z:  0 },
+];
+
+const DOWN: Vector3<i32> = Vector3 { x: 0, y: -1, z: 0 };
+const UP: Vector3<i32> = Vector3 { x: 0, y: 1, z: 0 };
+
+const MAX_LIGHT: LightLevel = 15;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Channel {
+    Sun,
+    Block,
+}
+
+impl Channel {
+    #[inline]
+    fn get(self, cell: &Cell) -> LightLevel {
+        match self {
+            Channel::Sun => cell.sun_light,
+            Channel::Block => cell.block_light,
+        }
+    }
+
+    #[inline]
+    fn set(self, cell: &mut Cell, level: LightLevel) {
+        match self {
+            Channel::Sun => cell.sun_light = level,
+            Channel::Block => cell.block_light = level,
+        }
+    }
+
+    /// Light the cell emits by itself, regardless of its neighbors.
+    fn source_level(self, cells: &impl LightCells, coords: BlockCoords, cell: &Cell) -> LightLevel {
+        let block = cell.get_block();
+        match self {
+            Channel::Sun if block.is_transparent() && is_under_sky(cells, coords) => MAX_LIGHT,
+            Channel::Sun => 0,
+            Channel::Block => block.light_level(),
+        }
+    }
+
+    /// Light received by the neighbor in the direction, full sunlight goes down without fading.
+    #[inline]
+    fn received(self, level: LightLevel, direction: Vector3<i32>) -> LightLevel {
+        if self == Channel::Sun && level == MAX_LIGHT && direction == DOWN {
+            MAX_LIGHT
+        } else {
+            level.saturating_sub(1)
+        }
+    }
+}
+
+/// The sun lights the top layer of the world, there are no chunks above it.
+/// Chunks below get the sunlight through their top neighbors.
+#[inline]
+fn is_under_sky(cells: &impl LightCells, coords: BlockCoords) -> bool {
+    cells.get_cell(coords + UP).is_none()
+}
+
+/// Cells the light spreads through, coords are relative to the chunk being lit.
+trait LightCells {
+    fn get_cell(&self, coords: BlockCoords) -> Option<Cell>;
+
+    /// Returns `None` for the cells the light cannot be written to.
+    fn get_cell_mut(&mut self, coords: BlockCoords) -> Option<&mut Cell>;
+}
+
+/// The chunk being lit, with its neighbors as the read-only border. The neighborhood's
+/// center is a snapshot taken before the recalculation, so it is not used.
+struct ChunkCells<'a> {
+    chunk: &'a mut Chunk,
+    neighbors: &'a ChunkNeighborhood,
+}
+
+#[inline]
+fn is_inside_chunk(coords: BlockCoords) -> bool {
+    (0..Chunk::SIZE).contains(&coords.x)
+        && (0..Chunk::SIZE).contains(&coords.y)
+        && (0..Chunk::SIZE).contains(&coords.z)
+}
+
+impl LightCells for ChunkCells<'_> {
+    fn get_cell(&self, coords: BlockCoords) -> Option<Cell> {
+        if is_inside_chunk(coords) {
+            Some(self.chunk[coords])
+        } else {
+            self.neighbors.get_cell(coords)
+        }
+    }
+
+    fn get_cell_mut(&mut self, coords: BlockCoords) -> Option<&mut Cell> {
+        is_inside_chunk(coords).then(|| &mut self.chunk[coords])
+    }
+}
+
+impl LightCells for ChunkNeighborhoodMut<'_> {
+    fn get_cell(&self, coords: BlockCoords) -> Option<Cell> {
+        ChunkNeighborhoodMut::get_cell(self, coords)
+    }
+
+    fn get_cell_mut(&mut self, coords: BlockCoords) -> Option<&mut Cell> {
+        ChunkNeighborhoodMut::get_cell_mut(self, coords)
+    }
+}
+
+/// Spreads the light from the queued cells.
+fn propagate_added(
+    cells: &mut impl LightCells,
+    channel: Channel,
+    added: &mut VecDeque<BlockCoords>,
+) {
+    while let Some(coords) = added.pop_front() {
+        let Some(cell) = cells.get_cell(coords) else {
+            continue;
+        };
+        let level = channel.get(&cell);
+
+        for direction in DIRECTIONS {
+            let received = channel.received(level, direction);
+            if received == 0 {
+                continue;
+            }
+
+            let neighbor_coords = coords + direction;
+            let Some(neighbor) = cells.get_cell(neighbor_coords) else {
+                continue;
+            };
+            if !neighbor.get_block().is_transparent() || channel.get(&neighbor) >= received {
+                continue;
+            }
+
+            if let Some(neighbor) = cells.get_cell_mut(neighbor_coords) {
+                channel.set(neighbor, received);
+                added.push_back(neighbor_coords);
+            }
+        }
+    }
+}
+
+/// Darkens the cells lit by the removed light. Cells lit by other sources are queued
+/// to spread their light back into the darkened area.
+fn propagate_removed(
+    cells: &mut impl LightCells,
+    channel: Channel,
+    removed: &mut VecDeque<(BlockCoords, LightLevel)>,
+    added: &mut VecDeque<BlockCoords>,
+) {
+    while let Some((coords, level)) = removed.pop_front() {
+        for direction in DIRECTIONS {
+            let neighbor_coords = coords + direction;
+            let Some(neighbor) = cells.get_cell(neighbor_coords) else {
+                continue;
+            };
+            let neighbor_level = channel.get(&neighbor);
+            if neighbor_level == 0 {
+                continue;
+            }
+
+            let source_level = channel.source_level(cells, neighbor_coords, &neighbor);
+            let was_lit_by_removed = neighbor_level < level
+                || (neighbor_level == channel.received(level, direction)
+                    && source_level < neighbor_level);
+            if !was_lit_by_removed {
+                added.push_back(neighbor_coords);
+                continue;
+            }
+
+            if let Some(neighbor) = cells.get_cell_mut(neighbor_coords) {
+                channel.set(neighbor, source_level);
+                removed.push_back((neighbor_coords, neighbor_level));
+                if source_level > 0 {
+                    added.push_back(neighbor_coords);
+                }
+            }
+        }
+    }
+}
+
+/// Lights the chunk from scratch, taking the light coming from the neighbors into account.
+pub fn recalculate_light(chunk: &mut Chunk, neighbors: &ChunkNeighborhood) {
+    puffin::profile_function!("Light recalculation");
+
+    // Full sunlight coming from above goes straight down the columns, the rest of the
+    // light of the chunk above is spread along with the other border light
+    let mut is_fully_sunlit = true;
+    for x in 0..Chunk::SIZE {
+        for z in 0..Chunk::SIZE {
+            let mut sun_light = match neighbors.get_cell(BlockCoords::new(x, Chunk::SIZE, z)) {
+                None => MAX_LIGHT,
+                Some(cell) if cell.sun_light == MAX_LIGHT => MAX_LIGHT,
+                Some(_) => 0,
+            };
+            for y in (0..Chunk::SIZE).rev() {
+                let coords = BlockCoords { x, y, z };
+                let cell = &mut chunk[coords];
+                let block = cell.get_block();
+
+                if !block.is_transparent() {
+                    sun_light = 0;
+                }
+
+                cell.sun_light = sun_light;
+                cell.block_light = block.light_level();
+                is_fully_sunlit &= sun_light == MAX_LIGHT;
+            }
+        }
+    }
+
+    // Nothing can add sunlight to the chunks of open sky, which are most of the chunks
+    // above the terrain
+    let channels: &[Channel] = if is_fully_sunlit {
+        &[Channel::Block]
+    } else {
+        &[Channel::Sun, Channel::Block]
+    };
+
+    let mut cells = ChunkCells { chunk, neighbors };
+    for &channel in channels {
+        let mut added = VecDeque::new();
+        for x in -1..=Chunk::SIZE {
+            for y in -1..=Chunk::SIZE {
+                for z in -1..=Chunk::SIZE {
+                    let coords = BlockCoords { x, y, z };
+                    if cells.get_cell(coords).is_some_and(|x| channel.get(&x) > 1) {
+                        added.push_back(coords);
+                    }
+                }
+            }
+        }
+
+        propagate_added(&mut cells, channel, &mut added);
+    }
+}
+
+/// Updates the light after the block at the coords has changed. Only the cells
+/// whose light depends on the block are visited.
+pub fn update_light_around(cells: &mut ChunkNeighborhoodMut, coords: BlockCoords) {
+    puffin::profile_function!();
+
+    for channel in [Channel::Sun, Channel::Block] {
+        let mut removed = VecDeque::new();
+        let mut added = VecDeque::new();
+
+        let Some(cell) = cells.get_cell_mut(coords) else {
+            return;
+        };
+        let level = channel.get(cell);
+        if level > 0 {
+            channel.set(cell, 0);
+            removed.push_back((coords, level));
+        }
+        propagate_removed(cells, channel, &mut removed, &mut added);
+
+        let Some(cell) = cells.get_cell(coords) else {
+            return;
+        };
+        let source_level = channel.source_level(cells, coords, &cell);
+        if source_level > 0 {
+            if let Some(cell) = cells.get_cell_mut(coords) {
+                channel.set(cell, source_level);
+            }
+            added.push_back(coords);
+        }
+
+        // Let the light of the neighbors into the cell
+        if cell.get_block().is_transparent() {
+            added.extend(DIRECTIONS.iter().map(|direction| coords + direction));
+        }
+
+        propagate_added(cells, channel, &mut added);
+    }
+}
+
+/// Fills the air chunk with full sunlight, the light of a chunk of open sky.
+pub fn light_sky_chunk(chunk: &mut Chunk) {
+    for x in 0..Chunk::SIZE {
+        for y in 0..Chunk::SIZE {
+            for z in 0..Chunk::SIZE {
+                chunk[BlockCoords { x, y, z }].sun_light = MAX_LIGHT;
+            }
+        }
+    }
+}
+
+/// Returns true if the neighbors would leave a chunk of open sky as it is, with full
+/// sunlight coming from above and no block light coming from any side.
+pub fn is_lit_by_sky_only(neighbors: &ChunkNeighborhood) -> bool {
+    const OUTSIDE: [i32; 2] = [-1, Chunk::SIZE];
+
+    (0..Chunk::SIZE).all(|i| {
+        (0..Chunk::SIZE).all(|j| {
+            let top = neighbors.get_cell(BlockCoords::new(i, Chunk::SIZE, j));
+            if top.is_some_and(|cell| cell.sun_light != MAX_LIGHT) {
+                return false;
+            }
+
+            OUTSIDE.iter().all(|&k| {
+                [
+                    BlockCoords::new(k, i, j),
+                    BlockCoords::new(i, k, j),
+                    BlockCoords::new(i, j, k),
+                ]
+                .into_iter()
+                .all(|coords| {
+                    neighbors
+                        .get_cell(coords)
+                        .is_none_or(|cell| cell.block_light <= 1)
+                })
+            })
+        })
+    })
+}
+
+/// Returns the directions of the chunk faces whose light differs between the two versions
+/// of the chunk. The neighbors behind them have to be lit again.
+pub fn changed_faces(old: &Chunk, new: &Chunk) -> Vec<Vector3<i32>> {
+    const LAST: i32 = Chunk::SIZE - 1;
+
+    DIRECTIONS
+        .into_iter()
+        .filter(|direction| {
+            (0..Chunk::SIZE).any(|i| {
+                (0..Chunk::SIZE).any(|j| {
+                    let coords = match *direction {
+                        Vector3 { x: 0, y: 0, z } => BlockCoords::new(i, j, z.max(0) * LAST),
+                        Vector3 { x: 0, y, .. } => BlockCoords::new(i, y.max(0) * LAST, j),
+                        Vector3 { x, .. } => BlockCoords::new(x.max(0) * LAST, i, j),
+                    };
+                    let (old, new) = (old[coords], new[coords]);
+                    old.sun_light != new.sun_light || old.block_light != new.block_light
+                })
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, collections::HashMap, sync::Arc};
+
+    use cgmath::Zero;
+
+    use super::*;
+    use crate::{blocks::BlockId, test_utils::install_registries, ChunkCoords};
+
+    /// Dark air chunks around the center one.
+    fn dark_neighbors() -> HashMap<ChunkCoords, RefCell<Chunk>> {
+        let mut chunks = HashMap::new();
+        for x in -1..=1 {
+            for y in -1..=1 {
+                for z in -1..=1 {
+                    if x != 0 || y != 0 || z != 0 {
+                        chunks.insert(ChunkCoords::new(x, y, z), RefCell::new(Chunk::new()));
+                    }
+                }
+            }
+        }
+        chunks
+    }
+
+    fn neighborhood<'a>(
+        center: &'a mut Chunk,
+        neighbors: &'a HashMap<ChunkCoords, RefCell<Chunk>>,
+    ) -> ChunkNeighborhoodMut<'a> {
+        ChunkNeighborhoodMut::from_fn(center, ChunkCoords::zero(), |coords| {
+            neighbors.get(&coords).map(RefCell::borrow_mut)
+        })
+    }
+
+    fn set_block(cells: &mut ChunkNeighborhoodMut, coords: BlockCoords, block_id: BlockId) {
+        cells.get_cell_mut(coords).unwrap().block_id = block_id;
+        update_light_around(cells, coords);
+    }
+
+    /// Calls `f` with the coords of every cell of the neighborhood.
+    fn for_each_coords(mut f: impl FnMut(BlockCoords)) {
+        for x in -Chunk::SIZE..Chunk::SIZE * 2 {
+            for y in -Chunk::SIZE..Chunk::SIZE * 2 {
+                for z in -Chunk::SIZE..Chunk::SIZE * 2 {
+                    f(BlockCoords { x, y, z });
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn removed_torch_leaves_no_light() {
+        install_registries();
+        let torch = BlockId::by_name("torch").unwrap();
+
+        let mut center = Chunk::new();
+        let neighbors = dark_neighbors();
+        let mut cells = neighborhood(&mut center, &neighbors);
+
+        // Close to a corner, so that the light reaches into the neighbors
+        let coords = BlockCoords::new(2, 13, 1);
+        set_block(&mut cells, coords, torch);
+        assert_eq!(cells.get_cell(coords).unwrap().block_light, 10);
+        assert_eq!(
+            cells
+                .get_cell(BlockCoords::new(-2, 14, 0))
+                .unwrap()
+                .block_light,
+            4
+        );
+
+        set_block(&mut cells, coords, BlockId::AIR);
+        for_each_coords(|coords| {
+            assert_eq!(
+                cells.get_cell(coords).unwrap().block_light,
+                0,
+                "{:?}",
+                coords
+            );
+        });
+    }
+
+    #[test]
+    fn removed_torch_keeps_the_light_of_another_one() {
+        install_registries();
+        let torch = BlockId::by_name("torch").unwrap();
+        let (removed, kept) = (BlockCoords::new(4, 8, 8), BlockCoords::new(10, 9, 8));
+
+        let mut center = Chunk::new();
+        let neighbors = dark_neighbors();
+        let mut cells = neighborhood(&mut center, &neighbors);
+        set_block(&mut cells, removed, torch);
+        set_block(&mut cells, kept, torch);
+        set_block(&mut cells, removed, BlockId::AIR);
+
+        let mut expected_center = Chunk::new();
+        let expected_neighbors = dark_neighbors();
+        let mut expected = neighborhood(&mut expected_center, &expected_neighbors);
+        set_block(&mut expected, kept, torch);
+
+        for_each_coords(|coords| {
+            assert_eq!(
+                cells.get_cell(coords).unwrap().block_light,
+                expected.get_cell(coords).unwrap().block_light,
+                "{:?}",
+                coords
+            );
+        });
+    }
+
+    #[test]
+    fn sunlight_goes_down_across_chunks_until_blocked() {
+        install_registries();
+        let stone = BlockId::by_name("stone").unwrap();
+        let above_coords = ChunkCoords::unit_y();
+
+        // Only the chunk above, there is nothing above it
+        let mut center = Chunk::new();
+        let neighbors = HashMap::from([(above_coords, RefCell::new(Chunk::new()))]);
+
+        let mut above = neighbors[&above_coords].borrow().clone();
+        let above_neighbors =
+            ChunkNeighborhood::from_fn(Arc::new(above.clone()), above_coords, |_| None);
+        recalculate_light(&mut above, &above_neighbors);
+        *neighbors[&above_coords].borrow_mut() = above.clone();
+
+        let center_neighbors =
+            ChunkNeighborhood::from_fn(Arc::new(center.clone()), ChunkCoords::zero(), |coords| {
+                (coords == above_coords).then(|| Arc::new(above.clone()))
+            });
+        recalculate_light(&mut center, &center_neighbors);
+        for_each_coords(|coords| {
+            if is_inside_chunk(coords) {
+                assert_eq!(center[coords].sun_light, MAX_LIGHT, "{:?}", coords);
+            }
+        });
+
+        // At the bottom of the chunk above, the column below is lit from the sides only
+        let mut cells = neighborhood(&mut center, &neighbors);
+        set_block(&mut cells, BlockCoords::new(8, Chunk::SIZE, 8), stone);
+        for y in 0..Chunk::SIZE {
+            let cell = cells.get_cell(BlockCoords::new(8, y, 8)).unwrap();
+            assert_eq!(cell.sun_light, MAX_LIGHT - 1, "{}", y);
+            let cell = cells.get_cell(BlockCoords::new(9, y, 8)).unwrap();
+            assert_eq!(cell.sun_light, MAX_LIGHT, "{}", y);
+        }
+    }
+}
@@ -0,0 +1,729 @@
+use cgmath::{ElementWise, Vector2, Vector3, Zero};
+
+use super::{
+    blocks::BlockShape, fluid, utils::ChunkNeighborhood, BlockCoords, Cell, Chunk, FluidLevel,
+    LightLevel,
+};
+
+/// Vertex of a chunk mesh, packed into three integers in the format expected by the shaders.
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct Vertex(u32, u32, u32);
+
+pub struct VertexDesc {
+    pub pos: Vector3<u16>,
+    pub texture_id: u16,
+    /// In 1/16ths of the texture, the texture repeats past 16
+    pub texture_coords: Vector2<u16>,
+    pub diffused_light: u8,
+    /// In quarters of a light level, averaged over the cells around the vertex
+    pub sun_light: u8,
+    /// In quarters of a light level, averaged over the cells around the vertex
+    pub block_light: u8,
+    /// From 0 for a corner between two solid blocks to 3 for an open corner
+    pub ambient_occlusion: u8,
+}
+
+impl Vertex {
+    #[inline]
+    pub fn new(desc: VertexDesc) -> Self {
+        Vertex(
+            ((desc.pos.x as u32) & 0x1FF)
+                | (((desc.pos.y as u32) & 0x1FF) << 9)
+                | (((desc.pos.z as u32) & 0x1FF) << 18)
+                | (((desc.diffused_light as u32) & 0x3) << 27),
+            ((desc.texture_coords.x as u32) & 0x1FF)
+                | (((desc.texture_coords.y as u32) & 0x1FF) << 9),
+            (desc.texture_id as u32)
+                | (((desc.sun_light as u32) & 0x3F) << 16)
+                | (((desc.block_light as u32) & 0x3F) << 22)
+                | (((desc.ambient_occlusion as u32) & 0x3) << 28),
+        )
+    }
+}
+
+pub struct Face {
+    pub base_index: u32,
+    pub center: Vector3<f32>,
+    pub distance: f32,
+}
+
+impl Face {
+    const VERTEX_INDICES: [u32; 6] = [0, 1, 2, 2, 1, 3];
+
+    pub fn generate_default_indices(face_count: usize) -> Vec<u32> {
+        Self::VERTEX_INDICES
+            .iter()
+            .cycle()
+            .enumerate()
+            .map(|(i, x)| x + (i as u32 / 6) * 4)
+            .take(face_count * 6)
+            .collect()
+    }
+
+    pub fn generate_indices(faces: &[Face]) -> Vec<u32> {
+        faces
+            .iter()
+            .flat_map(|face| Self::VERTEX_INDICES.iter().map(|x| x + face.base_index))
+            .collect()
+    }
+}
+
+#[rustfmt::skip]
+const SOLID_BLOCK_FACES: [[Vector3<u16>; 4]; 6] = [
+    // Neg Z
+    [
+        Vector3 { x: 16, y: 0,  z: 0 },
+        Vector3 { x: 0,  y: 0,  z: 0 },
+        Vector3 { x: 16, y: 16, z: 0 },
+        Vector3 { x: 0,  y: 16, z: 0 },
+    ],
+    // Pos Z
+    [
+        Vector3 { x: 0,  y: 0,  z: 16 },
+        Vector3 { x: 16, y: 0,  z: 16 },
+        Vector3 { x: 0,  y: 16, z: 16 },
+        Vector3 { x: 16, y: 16, z: 16 },
+    ],
+    // Neg Y
+    [
+        Vector3 { x: 0,  y: 0,  z: 0  },
+        Vector3 { x: 16, y: 0,  z: 0  },
+        Vector3 { x: 0,  y: 0,  z: 16 },
+        Vector3 { x: 16, y: 0,  z: 16 },
+    ],
+    // Pos Y
+    [
+        Vector3 { x: 16, y: 16, z: 0  },
+        Vector3 { x: 0,  y: 16, z: 0  },
+        Vector3 { x: 16, y: 16, z: 16 },
+        Vector3 { x: 0,  y: 16, z: 16 },
+    ],
+    // Neg X
+    [
+        Vector3 { x: 0,  y: 0,  z: 0  },
+        Vector3 { x: 0,  y: 0,  z: 16 },
+        Vector3 { x: 0,  y: 16, z: 0  },
+        Vector3 { x: 0,  y: 16, z: 16 },
+    ],
+    // Pos X
+    [
+        Vector3 { x: 16, y: 0,  z: 16 },
+        Vector3 { x: 16, y: 0,  z: 0  },
+        Vector3 { x: 16, y: 16, z: 16 },
+        Vector3 { x: 16, y: 16, z: 0  },
+    ],
+];
+
+#[rustfmt::skip]
+const FLOWER_BLOCK_FACES: [[Vector3<u16>; 4]; 4] = [
+    [
+        Vector3 { x: 14, y: 0,  z: 2  },
+        Vector3 { x: 2,  y: 0,  z: 14 },
+        Vector3 { x: 14, y: 16, z: 2  },
+        Vector3 { x: 2,  y: 16, z: 14 },
+    ],
+    [
+        Vector3 { x: 2,  y: 0,  z: 2  },
+        Vector3 { x: 14, y: 0,  z: 14 },
+        Vector3 { x: 2,  y: 16, z: 2  },
+        Vector3 { x: 14, y: 16, z: 14 },
+    ],
+    [
+        Vector3 { x: 2,  y: 0,  z: 14 },
+        Vector3 { x: 14, y: 0,  z: 2  },
+        Vector3 { x: 2,  y: 16, z: 14 },
+        Vector3 { x: 14, y: 16, z: 2  },
+    ],
+    [
+        Vector3 { x: 14, y: 0,  z: 14 },
+        Vector3 { x: 2,  y: 0,  z: 2  },
+        Vector3 { x: 14, y: 16, z: 14 },
+        Vector3 { x: 2,  y: 16, z: 2  },
+    ],
+];
+
+#[rustfmt::skip]
+const TORCH_BLOCK_FACES: [[Vector3<u16>; 4]; 5] = [
+    // Pos Y
+    [
+        Vector3 { x: 9, y: 9, z: 7 },
+        Vector3 { x: 7, y: 9, z: 7 },
+        Vector3 { x: 9, y: 9, z: 9 },
+        Vector3 { x: 7, y: 9, z: 9 },
+    ],
+    // Neg Z
+    [
+        Vector3 { x: 16, y: 0,  z: 7 },
+        Vector3 { x: 0,  y: 0,  z: 7 },
+        Vector3 { x: 16, y: 16, z: 7 },
+        Vector3 { x: 0,  y: 16, z: 7 },
+    ],
+    // Pos Z
+    [
+        Vector3 { x: 0,  y: 0,  z: 9 },
+        Vector3 { x: 16, y: 0,  z: 9 },
+        Vector3 { x: 0,  y: 16, z: 9 },
+        Vector3 { x: 16, y: 16, z: 9 },
+    ],
+    // Neg X
+    [
+        Vector3 { x: 7, y: 0,  z: 0  },
+        Vector3 { x: 7, y: 0,  z: 16 },
+        Vector3 { x: 7, y: 16, z: 0  },
+        Vector3 { x: 7, y: 16, z: 16 },
+    ],
+    // Pos X
+    [
+        Vector3 { x: 9, y: 0,  z: 16 },
+        Vector3 { x: 9, y: 0,  z: 0  },
+        Vector3 { x: 9, y: 16, z: 16 },
+        Vector3 { x: 9, y: 16, z: 0  },
+    ],
+];
+
+#[rustfmt::skip]
+const TORCH_TOP_TEX_COORDS: [Vector2<u16>; 4] = [
+    Vector2 { x: 7, y: 9 },
+    Vector2 { x: 9, y: 9 },
+    Vector2 { x: 7, y: 7 },
+    Vector2 { x: 9, y: 7 },
+];
+
+#[rustfmt::skip]
+const NEIGHBOR_OFFSETS: [Vector3<i32>; 6] = [
+    Vector3 { x:  0, y:  0, z: -1 },
+    Vector3 { x:  0, y:  0, z:  1 },
+    Vector3 { x:  0, y: -1, z:  0 },
+    Vector3 { x:  0, y:  1, z:  0 },
+    Vector3 { x: -1, y:  0, z:  0 },
+    Vector3 { x:  1, y:  0, z:  0 },
+];
+
+const FACE_LIGHTING: [u8; 6] = [1, 1, 0, 3, 2, 2];
+
+/// Axis of the normal and the two axes of the plane of the faces of each direction.
+const FACE_AXES: [(usize, usize, usize); 6] = [
+    (2, 0, 1),
+    (2, 0, 1),
+    (1, 0, 2),
+    (1, 0, 2),
+    (0, 2, 1),
+    (0, 2, 1),
+];
+
+/// Order of the points of a face whose triangles share the other diagonal.
+const FLIPPED_POINTS: [usize; 4] = [2, 0, 3, 1];
+
+/// Light at a point of a face, the fields are the ones of [`VertexDesc`].
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct CornerLight {
+    sun_light: u8,
+    block_light: u8,
+    ambient_occlusion: u8,
+}
+
+impl CornerLight {
+    /// Light of a whole face taken from a single cell, without occlusion.
+    fn flat((sun_light, block_light): (LightLevel, LightLevel)) -> Self {
+        CornerLight {
+            sun_light: sun_light * 4,
+            block_light: block_light * 4,
+            ambient_occlusion: 3,
+        }
+    }
+
+    fn brightness(&self) -> u32 {
+        self.sun_light.max(self.block_light) as u32 * (self.ambient_occlusion as u32 + 1)
+    }
+}
+
+/// What a solid face looks like, the adjacent faces that look the same are merged.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct SolidFace {
+    texture_id: u16,
+    /// In the order of the points of the face
+    corners: [CornerLight; 4],
+}
+
+type FaceMask = [[Option<SolidFace>; Chunk::SIZE as usize]; Chunk::SIZE as usize];
+
+/// Height of the fluid surface in 1/16ths of a block.
+fn fluid_height(level: FluidLevel) -> u16 {
+    match level {
+        fluid::SOURCE | fluid::FALLING => 14,
+        level => 14 - 12 * level as u16 / fluid::MAX_FLOW_DISTANCE as u16,
+    }
+}
+
+/// Moves the bottom and the top edges of a full block face to the given heights.
+fn fluid_face(face: &[Vector3<u16>; 4], bottom: u16, top: u16) -> [Vector3<u16>; 4] {
+    face.map(|point| Vector3 {
+        y: if point.y == 0 { bottom } else { top },
+        ..point
+    })
+}
+
+#[rustfmt::skip]
+const TEX_COORDS: [Vector2<u16>; 4] = [
+    Vector2 { x: 0,  y: 16 },
+    Vector2 { x: 16, y: 16 },
+    Vector2 { x: 0,  y: 0  },
+    Vector2 { x: 16, y: 0  },
+];
+
+pub struct ChunkMeshes {
+    pub solid_vertices: Vec<Vertex>,
+    pub water_vertices: Vec<Vertex>,
+    pub water_faces: Vec<Face>,
+}
+
+struct MeshGenerationContext<'a> {
+    chunks: &'a ChunkNeighborhood,
+    current_block_coords: BlockCoords,
+    meshes: ChunkMeshes,
+}
+
+struct FaceDesc<'a> {
+    points: &'a [Vector3<u16>; 4],
+    texture_id: u16,
+    texture_coords: &'a [Vector2<u16>; 4],
+    diffused_light: u8,
+    corners: [CornerLight; 4],
+}
+
+impl<'a> MeshGenerationContext<'a> {
+    fn new(chunks: &'a ChunkNeighborhood) -> Self {
+        MeshGenerationContext {
+            chunks,
+            current_block_coords: BlockCoords::zero(),
+            meshes: ChunkMeshes {
+                solid_vertices: vec![],
+                water_vertices: vec![],
+                water_faces: vec![],
+            },
+        }
+    }
+
+    fn is_transparent(cell: Option<Cell>) -> bool {
+        cell.map_or(false, |cell| cell.get_block().is_transparent())
+    }
+
+    fn get_light_levels(cell: Option<Cell>) -> (u8, u8) {
+        cell.map_or((15, 0), |cell| (cell.sun_light, cell.block_light))
+    }
+
+    /// Returns the light at a corner of the face looking into the cell at `coords`, from
+    /// the cell and the three cells next to it around the corner. The cells outside of the
+    /// neighborhood are open.
+    fn get_corner_light(
+        &self,
+        coords: BlockCoords,
+        side_u: Vector3<i32>,
+        side_v: Vector3<i32>,
+    ) -> CornerLight {
+        let is_open =
+            |cell: &Option<Cell>| cell.is_none_or(|cell| cell.get_block().is_transparent());
+        let sides = [side_u, side_v].map(|offset| self.chunks.get_cell(coords + offset));
+
+        // The corner cell cannot be seen between two solid sides
+        let corner = if sides.iter().any(is_open) {
+            self.chunks.get_cell(coords + side_u + side_v)
+        } else {
+            sides[0]
+        };
+        let mut ambient_occlusion = 0;
+        let (mut sun_sum, mut block_sum) = Self::get_light_levels(self.chunks.get_cell(coords));
+        for cell in [sides[0], sides[1], corner]
+            .iter()
+            .filter(|cell| is_open(cell))
+        {
+            let (sun_light, block_light) = Self::get_light_levels(*cell);
+            sun_sum += sun_light;
+            block_sum += block_light;
+            ambient_occlusion += 1;
+        }
+
+        let count = ambient_occlusion + 1;
+        let average = |sum: u8| (sum * 4 + count / 2) / count;
+        CornerLight {
+            sun_light: average(sun_sum),
+            block_light: average(block_sum),
+            ambient_occlusion,
+        }
+    }
+
+    fn emit_face_vertices(
+        vertex_array: &mut Vec<Vertex>,
+        block_coords: BlockCoords,
+        desc: FaceDesc,
+    ) {
+        let offset = block_coords.map(|x| x as u16) * 16;
+
+        desc.points
+            .iter()
+            .zip(desc.texture_coords)
+            .zip(desc.corners)
+            .map(|((&pos, &tex), corner)| {
+                Vertex::new(VertexDesc {
+                    pos: pos + offset,
+                    texture_id: desc.texture_id,
+                    texture_coords: tex,
+                    diffused_light: desc.diffused_light,
+                    sun_light: corner.sun_light,
+                    block_light: corner.block_light,
+                    ambient_occlusion: corner.ambient_occlusion,
+                })
+            })
+            .for_each(|x| vertex_array.push(x));
+    }
+
+    fn emit_solid_face(&mut self, desc: FaceDesc) {
+        Self::emit_face_vertices(
+            &mut self.meshes.solid_vertices,
+            self.current_block_coords,
+            desc,
+        );
+    }
+
+    fn emit_water_face(&mut self, desc: FaceDesc) {
+        let offset = self.current_block_coords.map(|x| x as f32);
+        let sum_points = desc.points.iter().sum::<Vector3<u16>>();
+        let center_local = sum_points.map(|x| x as f32) / 16. / 4.;
+
+        self.meshes.water_faces.push(Face {
+            base_index: self.meshes.water_vertices.len() as u32,
+            center: offset + center_local,
+            distance: 0.,
+        });
+
+        Self::emit_face_vertices(
+            &mut self.meshes.water_vertices,
+            self.current_block_coords,
+            desc,
+        );
+    }
+
+    /// Returns the visible faces of the solid blocks in a layer of the chunk, the face
+    /// of the block at `coords[u_axis] = u` and `coords[v_axis] = v` is at `[u][v]`.
+    fn get_solid_faces(&self, direction: usize, layer: i32) -> FaceMask {
+        let (normal_axis, u_axis, v_axis) = FACE_AXES[direction];
+        let chunk = self.chunks.center();
+
+        let mut mask = [[None; Chunk::SIZE as usize]; Chunk::SIZE as usize];
+        for u in 0..Chunk::SIZE {
+            for v in 0..Chunk::SIZE {
+                let mut coords = BlockCoords::zero();
+                coords[normal_axis] = layer;
+                coords[u_axis] = u;
+                coords[v_axis] = v;
+
+                let BlockShape::Solid { texture_ids } = &chunk[coords].get_block().shape else {
+                    continue;
+                };
+                let neighbor_coords = coords + NEIGHBOR_OFFSETS[direction];
+                if !Self::is_transparent(self.chunks.get_cell(neighbor_coords)) {
+                    continue;
+                }
+
+                let corners = SOLID_BLOCK_FACES[direction].map(|point| {
+                    let side = |axis: usize| {
+                        let mut offset = Vector3::zero();
+                        offset[axis] = if point[axis] == 0 { -1 } else { 1 };
+                        offset
+                    };
+                    self.get_corner_light(neighbor_coords, side(u_axis), side(v_axis))
+                });
+                mask[u as usize][v as usize] = Some(SolidFace {
+                    texture_id: texture_ids[direction],
+                    corners,
+                });
+            }
+        }
+        mask
+    }
+
+    /// Emits the faces of the solid blocks, merging the adjacent faces of a layer that
+    /// look the same into rectangles. Each rectangle grows along `v` first, then along `u`
+    /// for as long as the whole column of faces matches.
+    fn emit_solid_blocks(&mut self) {
+        puffin::profile_function!();
+
+        const SIZE: usize = Chunk::SIZE as usize;
+        for (direction, &(normal_axis, u_axis, v_axis)) in FACE_AXES.iter().enumerate() {
+            for layer in 0..Chunk::SIZE {
+                let mut mask = self.get_solid_faces(direction, layer);
+
+                for u in 0..SIZE {
+                    for v in 0..SIZE {
+                        let Some(face) = mask[u][v] else {
+                            continue;
+                        };
+
+                        let height = 1 + mask[u][v + 1..]
+                            .iter()
+                            .take_while(|&&other| other == Some(face))
+                            .count();
+                        let width = 1 + mask[u + 1..]
+                            .iter()
+                            .take_while(|column| {
+                                column[v..v + height]
+                                    .iter()
+                                    .all(|&other| other == Some(face))
+                            })
+                            .count();
+                        for column in &mut mask[u..u + width] {
+                            column[v..v + height].fill(None);
+                        }
+
+                        let mut start = BlockCoords::zero();
+                        start[normal_axis] = layer;
+                        start[u_axis] = u as i32;
+                        start[v_axis] = v as i32;
+                        let mut size = Vector3::new(1, 1, 1);
+                        size[u_axis] = width as u16;
+                        size[v_axis] = height as u16;
+
+                        self.emit_solid_quad(direction, start, size, face);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Emits a face stretched over `size` blocks, with the texture repeated once per block.
+    /// The quad is split along the diagonal between its brightest corners, so that a dark
+    /// corner fades over a single triangle instead of a stripe across the face.
+    fn emit_solid_quad(
+        &mut self,
+        direction: usize,
+        start: BlockCoords,
+        size: Vector3<u16>,
+        face: SolidFace,
+    ) {
+        let points = SOLID_BLOCK_FACES[direction].map(|point| point.mul_element_wise(size));
+
+        // The texture follows the edges going from the first point to the second and third
+        let edge_length = |a: Vector3<u16>, b: Vector3<u16>| {
+            (0..3).map(|i| a[i].abs_diff(b[i])).max().unwrap() / 16
+        };
+        let texture_scale = Vector2::new(
+            edge_length(points[0], points[1]),
+            edge_length(points[0], points[2]),
+        );
+        let texture_coords = TEX_COORDS.map(|tex| tex.mul_element_wise(texture_scale));
+
+        // The triangles share the diagonal between the second and third points
+        let brightness = face.corners.map(|corner| corner.brightness());
+        let order = if brightness[0] + brightness[3] > brightness[1] + brightness[2] {
+            FLIPPED_POINTS
+        } else {
+            [0, 1, 2, 3]
+        };
+
+        Self::emit_face_vertices(
+            &mut self.meshes.solid_vertices,
+            start,
+            FaceDesc {
+                points: &order.map(|i| points[i]),
+                texture_id: face.texture_id,
+                texture_coords: &order.map(|i| texture_coords[i]),
+                diffused_light: FACE_LIGHTING[direction],
+                corners: order.map(|i| face.corners[i]),
+            },
+        );
+    }
+
+    /// Returns the height of the fluid surface in the cell, or `None` if there is no fluid.
+    fn get_fluid_height(&self, coords: BlockCoords) -> Option<u16> {
+        let cell = self.chunks.get_cell(coords)?;
+        if !fluid::is_fluid(&cell) {
+            return None;
+        }
+
+        let above = self.chunks.get_cell(coords + Vector3::unit_y());
+        if above.is_some_and(|x| fluid::is_fluid(&x)) {
+            Some(16)
+        } else {
+            Some(fluid_height(cell.fluid_level))
+        }
+    }
+
+    fn emit_water_block(&mut self, texture_id: u16) {
+        const BOTTOM_NEIGHBOR_OFFSET_INDEX: usize = 2;
+        const TOP_NEIGHBOR_OFFSET_INDEX: usize = 3;
+
+        let height = self
+            .get_fluid_height(self.current_block_coords)
+            .unwrap_or(16);
+
+        for (i, neighbor_offset) in NEIGHBOR_OFFSETS.iter().enumerate() {
+            let is_top_face = i == TOP_NEIGHBOR_OFFSET_INDEX;
+
+            let neighbor_coords = self.current_block_coords + neighbor_offset;
+            let neighbor_cell = self.chunks.get_cell(neighbor_coords);
+            let light_levels = if is_top_face {
+                Self::get_light_levels(self.chunks.get_cell(self.current_block_coords))
+            } else {
+                Self::get_light_levels(neighbor_cell)
+            };
+
+            let Some(neighbor_cell) = neighbor_cell else {
+                continue;
+            };
+
+            // Only the part of the face above the neighboring fluid is visible
+            let bottom = match self.get_fluid_height(neighbor_coords) {
+                Some(_) if is_top_face || i == BOTTOM_NEIGHBOR_OFFSET_INDEX => continue,
+                Some(neighbor_height) if neighbor_height >= height => continue,
+                Some(neighbor_height) => neighbor_height,
+                None if !neighbor_cell.get_block().is_transparent() => continue,
+                None => 0,
+            };
+
+            self.emit_water_face(FaceDesc {
+                points: &fluid_face(&SOLID_BLOCK_FACES[i], bottom, height),
+                texture_id,
+                texture_coords: &TEX_COORDS,
+                diffused_light: FACE_LIGHTING[i],
+                corners: [CornerLight::flat(light_levels); 4],
+            });
+        }
+    }
+
+    fn emit_flower_block(&mut self, texture_id: u16) {
+        let cell = self.chunks.get_cell(self.current_block_coords).unwrap();
+        let corner = CornerLight::flat((cell.sun_light, cell.block_light));
+
+        for points in &FLOWER_BLOCK_FACES {
+            self.emit_solid_face(FaceDesc {
+                points,
+                texture_id,
+                texture_coords: &TEX_COORDS,
+                diffused_light: 15,
+                corners: [corner; 4],
+            });
+        }
+    }
+
+    fn emit_torch_block(&mut self, texture_id: u16) {
+        let cell = self.chunks.get_cell(self.current_block_coords).unwrap();
+        let corner = CornerLight::flat((cell.sun_light, cell.block_light));
+
+        for (i, points) in TORCH_BLOCK_FACES.iter().enumerate() {
+            let texture_coords = if i == 0 {
+                &TORCH_TOP_TEX_COORDS
+            } else {
+                &TEX_COORDS
+            };
+            self.emit_solid_face(FaceDesc {
+                points,
+                texture_id,
+                texture_coords,
+                diffused_light: 15,
+                corners: [corner; 4],
+            });
+        }
+    }
+}
+
+impl ChunkMeshes {
+    pub fn generate(chunks: &ChunkNeighborhood) -> Self {
+        puffin::profile_function!();
+
+        let chunk = chunks.center();
+        if chunk.is_empty() {
+            return ChunkMeshes {
+                solid_vertices: vec![],
+                water_vertices: vec![],
+                water_faces: vec![],
+            };
+        }
+
+        let mut generation_context = MeshGenerationContext::new(chunks);
+        generation_context.emit_solid_blocks();
+
+        for x in 0..Chunk::SIZE {
+            for y in 0..Chunk::SIZE {
+                for z in 0..Chunk::SIZE {
+                    generation_context.current_block_coords = BlockCoords { x, y, z };
+                    let current_cell = chunk[generation_context.current_block_coords];
+
+                    match &current_cell.get_block().shape {
+                        BlockShape::Empty | BlockShape::Solid { .. } => {}
+                        BlockShape::Fluid { texture_id } => {
+                            generation_context.emit_water_block(*texture_id);
+                        }
+                        BlockShape::Flower { texture_id } => {
+                            generation_context.emit_flower_block(*texture_id);
+                        }
+                        BlockShape::Torch { texture_id } => {
+                            generation_context.emit_torch_block(*texture_id);
+                        }
+                    }
+                }
+            }
+        }
+
+        generation_context.meshes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{blocks::BlockId, test_utils::install_registries, ChunkCoords};
+
+    #[test]
+    fn flat_layer_is_merged_into_one_quad_per_direction() {
+        install_registries();
+        let stone = BlockId::by_name("stone").unwrap();
+
+        let mut chunk = Chunk::new();
+        for x in 0..Chunk::SIZE {
+            for z in 0..Chunk::SIZE {
+                chunk[BlockCoords::new(x, 8, z)].block_id = stone;
+            }
+        }
+
+        // Air around, so that the sides of the layer are visible
+        let air = Arc::new(Chunk::new());
+        let neighborhood =
+            ChunkNeighborhood::from_fn(Arc::new(chunk), ChunkCoords::zero(), |_| Some(air.clone()));
+        let meshes = ChunkMeshes::generate(&neighborhood);
+
+        assert_eq!(meshes.solid_vertices.len(), 6 * 4);
+        assert!(meshes.water_vertices.is_empty());
+
+        // The first word of a vertex holds its position in 1/16ths of a block
+        let positions: Vec<_> = meshes
+            .solid_vertices
+            .iter()
+            .map(|vertex| vertex.0)
+            .map(|x| Vector3::new(x & 0x1FF, (x >> 9) & 0x1FF, (x >> 18) & 0x1FF))
+            .collect();
+        for quad in positions.chunks_exact(4) {
+            let min = quad
+                .iter()
+                .fold(Vector3::new(u32::MAX, u32::MAX, u32::MAX), |a, b| {
+                    a.zip(*b, u32::min)
+                });
+            let max = quad
+                .iter()
+                .fold(Vector3::zero(), |a: Vector3<u32>, b| a.zip(*b, u32::max));
+            let size = max - min;
+
+            // The top and the bottom cover the whole layer, the sides its whole length
+            let mut extents = [size.x, size.y, size.z];
+            extents.sort();
+            let expected = if size.y == 0 {
+                [0, 256, 256]
+            } else {
+                [0, 16, 256]
+            };
+            assert_eq!(extents, expected);
+        }
+    }
+}
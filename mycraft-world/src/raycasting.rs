@@ -1,6 +1,6 @@
 use cgmath::{MetricSpace, Vector3, Zero};
 
-use crate::world::{blocks::Block, BlockCoords, World};
+use crate::{blocks::Block, BlockCoords, World};
 
 #[derive(Clone, Copy)]
 pub enum BlockSide {
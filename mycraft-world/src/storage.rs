@@ -0,0 +1,715 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{File, OpenOptions},
+    io::{ErrorKind, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use cgmath::Vector3;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    blocks::{block_names, BlockId},
+    fluid, BlockCoords, Cell, Chunk, ChunkCoords, WorldBounds,
+};
+
+type RegionCoords = Vector3<i32>;
+
+/// Region file header entry, describes where a chunk's payload is stored.
+#[derive(Clone, Copy, Default)]
+struct RegionEntry {
+    offset: u32,
+    length: u32,
+}
+
+impl RegionEntry {
+    const SIZE: usize = 8;
+
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+}
+
+/// Stores a cube of `SIZE`x`SIZE`x`SIZE` chunks in a single file.
+///
+/// The file starts with a magic number followed by an index with an entry for every chunk
+/// of the region. Chunk payloads are appended after the index, a payload is overwritten
+/// in place only if the new one fits into the old slot.
+///
+/// Files saved before the cells had a fluid level are read as well, they are rewritten
+/// in the current format the first time a chunk is written to them.
+struct RegionFile {
+    path: PathBuf,
+    file: File,
+    entries: Vec<RegionEntry>,
+    /// Whether the payloads use the runs without a fluid level
+    legacy: bool,
+}
+
+impl RegionFile {
+    const SIZE: i32 = 16;
+    const VOLUME: usize = (Self::SIZE * Self::SIZE * Self::SIZE) as usize;
+
+    const MAGIC: &'static [u8; 4] = b"MCR2";
+    const LEGACY_MAGIC: &'static [u8; 4] = b"MCR1";
+    const HEADER_SIZE: usize = Self::MAGIC.len() + Self::VOLUME * RegionEntry::SIZE;
+
+    fn open(path: &Path) -> Result<Self> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+
+        let mut entries = vec![RegionEntry::default(); Self::VOLUME];
+        let mut legacy = false;
+        if file.metadata()?.len() == 0 {
+            file.write_all(&Self::encode_header(&entries))?;
+        } else {
+            let mut header = vec![0; Self::HEADER_SIZE];
+            file.read_exact(&mut header)?;
+
+            let magic = &header[..Self::MAGIC.len()];
+            if magic == Self::LEGACY_MAGIC {
+                legacy = true;
+            } else if magic != Self::MAGIC {
+                bail!("Not a region file");
+            }
+
+            for (entry, bytes) in entries
+                .iter_mut()
+                .zip(header[Self::MAGIC.len()..].chunks_exact(RegionEntry::SIZE))
+            {
+                entry.offset = u32::from_le_bytes(bytes[0..4].try_into().unwrap());
+                entry.length = u32::from_le_bytes(bytes[4..8].try_into().unwrap());
+            }
+        }
+
+        Ok(RegionFile {
+            path: path.to_path_buf(),
+            file,
+            entries,
+            legacy,
+        })
+    }
+
+    fn encode_header(entries: &[RegionEntry]) -> Vec<u8> {
+        let mut header = Vec::with_capacity(Self::HEADER_SIZE);
+        header.extend_from_slice(Self::MAGIC);
+        for entry in entries {
+            header.extend_from_slice(&entry.offset.to_le_bytes());
+            header.extend_from_slice(&entry.length.to_le_bytes());
+        }
+        header
+    }
+
+    fn entry_index(local_coords: ChunkCoords) -> usize {
+        let size = Self::SIZE as usize;
+        local_coords.x as usize * size * size
+            + local_coords.y as usize * size
+            + local_coords.z as usize
+    }
+
+    /// Returns the payload in the current format.
+    fn read_entry(&mut self, index: usize) -> Result<Option<Vec<u8>>> {
+        let entry = self.entries[index];
+        if entry.is_empty() {
+            return Ok(None);
+        }
+
+        let mut data = vec![0; entry.length as usize];
+        self.file.seek(SeekFrom::Start(entry.offset as u64))?;
+        self.file.read_exact(&mut data)?;
+
+        if self.legacy {
+            data = upgrade_legacy_runs(&data)?;
+        }
+        Ok(Some(data))
+    }
+
+    fn read_chunk(&mut self, local_coords: ChunkCoords) -> Result<Option<Vec<u8>>> {
+        self.read_entry(Self::entry_index(local_coords))
+    }
+
+    /// Rewrites a legacy file in the current format. The upgraded file is written next to
+    /// it and then moved over it, so that the chunks are not lost if the writing fails.
+    fn upgrade(&mut self) -> Result<()> {
+        let mut entries = vec![RegionEntry::default(); Self::VOLUME];
+        let mut payloads = vec![];
+        for (index, entry) in entries.iter_mut().enumerate() {
+            if let Some(data) = self.read_entry(index)? {
+                *entry = RegionEntry {
+                    offset: (Self::HEADER_SIZE + payloads.len()) as u32,
+                    length: data.len() as u32,
+                };
+                payloads.extend_from_slice(&data);
+            }
+        }
+
+        let temp_path = self.path.with_extension("mcr.tmp");
+        {
+            let mut file = File::create(&temp_path)?;
+            file.write_all(&Self::encode_header(&entries))?;
+            file.write_all(&payloads)?;
+            file.sync_all()?;
+        }
+        std::fs::rename(&temp_path, &self.path)?;
+
+        *self = Self::open(&self.path)?;
+        Ok(())
+    }
+
+    fn write_chunk(&mut self, local_coords: ChunkCoords, data: &[u8]) -> Result<()> {
+        if self.legacy {
+            self.upgrade()?;
+        }
+
+        let index = Self::entry_index(local_coords);
+        let old_entry = self.entries[index];
+
+        let offset = if !old_entry.is_empty() && data.len() <= old_entry.length as usize {
+            old_entry.offset as u64
+        } else {
+            self.file.seek(SeekFrom::End(0))?
+        };
+
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.write_all(data)?;
+
+        let entry = RegionEntry {
+            offset: offset as u32,
+            length: data.len() as u32,
+        };
+        self.entries[index] = entry;
+
+        let entry_offset = Self::MAGIC.len() + index * RegionEntry::SIZE;
+        self.file.seek(SeekFrom::Start(entry_offset as u64))?;
+        self.file.write_all(&entry.offset.to_le_bytes())?;
+        self.file.write_all(&entry.length.to_le_bytes())?;
+
+        Ok(())
+    }
+}
+
+/// Returns the coords of the region containing the chunk, and the chunk coords within the region
+fn to_local_region_coords(chunk_coords: ChunkCoords) -> (RegionCoords, ChunkCoords) {
+    let region_coords = chunk_coords.map(|x| x.div_euclid(RegionFile::SIZE));
+    let local_coords = chunk_coords.map(|x| x.rem_euclid(RegionFile::SIZE));
+
+    (region_coords, local_coords)
+}
+
+/// Maps block ids stored in the save to the ids in the block registry, so that saves
+/// survive changes to the block definitions.
+struct BlockPalette {
+    from_saved: Vec<BlockId>,
+    to_saved: Vec<u16>,
+}
+
+impl BlockPalette {
+    const FILE_NAME: &'static str = "blocks.txt";
+
+    /// Reads the names of the saved blocks, adding the blocks that are not in the save yet.
+    fn load(world_path: &Path) -> Result<Self> {
+        let path = world_path.join(Self::FILE_NAME);
+        let mut names: Vec<String> = match std::fs::read_to_string(&path) {
+            Ok(source) => source.lines().map(str::to_string).collect(),
+            Err(err) if err.kind() == ErrorKind::NotFound => vec![],
+            Err(err) => {
+                return Err(err).with_context(|| format!("Failed to read {}", path.display()))
+            }
+        };
+        let saved_count = names.len();
+
+        let to_saved = block_names()
+            .map(|name| match names.iter().position(|x| x == name) {
+                Some(saved_id) => saved_id as u16,
+                None => {
+                    names.push(name.to_string());
+                    (names.len() - 1) as u16
+                }
+            })
+            .collect();
+
+        let from_saved = names
+            .iter()
+            .map(|name| {
+                BlockId::by_name(name).unwrap_or_else(|| {
+                    log::warn!("Saved block {} is not defined, replacing it with air", name);
+                    BlockId::AIR
+                })
+            })
+            .collect();
+
+        if names.len() != saved_count {
+            std::fs::create_dir_all(world_path)?;
+            std::fs::write(&path, names.join("\n"))
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+        }
+
+        Ok(BlockPalette {
+            from_saved,
+            to_saved,
+        })
+    }
+}
+
+/// Settings the world was created with, kept in the world directory.
+#[derive(Serialize, Deserialize)]
+pub struct WorldMetadata {
+    pub seed: u32,
+    /// Missing from the worlds created before the bounds could be set
+    #[serde(default = "legacy_bounds")]
+    pub bounds: WorldBounds,
+}
+
+/// The worlds used to start at the height 0 and have 16 layers of chunks.
+fn legacy_bounds() -> WorldBounds {
+    WorldBounds {
+        min_y: 0,
+        max_y: 16 * Chunk::SIZE,
+    }
+}
+
+impl WorldMetadata {
+    const FILE_NAME: &'static str = "world.ron";
+
+    /// Reads the metadata of the world, or creates the world with the seed and the bounds
+    /// if it does not exist yet. A random seed and the default bounds are picked if none
+    /// are given. Worlds saved before the metadata existed get the settings they were
+    /// generated with.
+    pub fn load_or_create(
+        world_path: &Path,
+        seed: Option<u32>,
+        bounds: Option<WorldBounds>,
+    ) -> Result<Self> {
+        let path = world_path.join(Self::FILE_NAME);
+        match std::fs::read_to_string(&path) {
+            Ok(source) => ron::from_str(&source)
+                .with_context(|| format!("Failed to parse {}", path.display())),
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                let metadata = if Self::is_legacy_world(world_path) {
+                    log::info!("Adding the metadata of the world saved without it");
+                    WorldMetadata {
+                        seed: LEGACY_SEED,
+                        bounds: legacy_bounds(),
+                    }
+                } else {
+                    WorldMetadata {
+                        seed: seed.unwrap_or_else(random_seed),
+                        bounds: bounds.unwrap_or_default(),
+                    }
+                };
+                let source = ron::ser::to_string_pretty(&metadata, Default::default())?;
+                std::fs::create_dir_all(world_path)?;
+                std::fs::write(&path, source)
+                    .with_context(|| format!("Failed to write {}", path.display()))?;
+                Ok(metadata)
+            }
+            Err(err) => Err(err).with_context(|| format!("Failed to read {}", path.display())),
+        }
+    }
+
+    /// Whether chunks or blocks were saved in the directory, which means that the world
+    /// was created before the metadata.
+    fn is_legacy_world(world_path: &Path) -> bool {
+        world_path.join(WorldStorage::REGIONS_DIR).exists()
+            || world_path.join(BlockPalette::FILE_NAME).exists()
+    }
+}
+
+/// Seed of the worlds generated before it could be chosen.
+const LEGACY_SEED: u32 = 0;
+
+fn random_seed() -> u32 {
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    time.as_nanos() as u32 ^ time.as_secs() as u32
+}
+
+/// Saves and loads chunks to and from region files in the world directory.
+pub struct WorldStorage {
+    path: PathBuf,
+    /// Open region files, closed once none of their chunks are loaded
+    regions: HashMap<RegionCoords, RegionFile>,
+    palette: BlockPalette,
+}
+
+impl WorldStorage {
+    const REGIONS_DIR: &'static str = "regions";
+
+    pub fn new(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let palette = BlockPalette::load(&path)?;
+
+        Ok(WorldStorage {
+            path,
+            regions: HashMap::new(),
+            palette,
+        })
+    }
+
+    /// Opens the region file, returns `None` if it does not exist and `create` is false.
+    fn get_region(
+        &mut self,
+        region_coords: RegionCoords,
+        create: bool,
+    ) -> Result<Option<&mut RegionFile>> {
+        use std::collections::hash_map::Entry;
+
+        match self.regions.entry(region_coords) {
+            Entry::Occupied(entry) => Ok(Some(entry.into_mut())),
+            Entry::Vacant(entry) => {
+                let file_name = format!(
+                    "r.{}.{}.{}.mcr",
+                    region_coords.x, region_coords.y, region_coords.z
+                );
+                let path = self.path.join(Self::REGIONS_DIR).join(file_name);
+                if !create && !path.exists() {
+                    return Ok(None);
+                }
+                std::fs::create_dir_all(path.parent().unwrap())?;
+
+                let region = RegionFile::open(&path)
+                    .with_context(|| format!("Failed to open region file {}", path.display()))?;
+                Ok(Some(entry.insert(region)))
+            }
+        }
+    }
+
+    /// Closes the region files that contain none of the chunks.
+    pub fn close_unused_regions(&mut self, chunks: impl Iterator<Item = ChunkCoords>) {
+        let used: HashSet<_> = chunks
+            .map(|coords| to_local_region_coords(coords).0)
+            .collect();
+        self.regions
+            .retain(|region_coords, _| used.contains(region_coords));
+    }
+
+    /// Reads the chunk into `chunk`, returns false if the chunk has never been saved.
+    pub fn load_chunk(&mut self, coords: ChunkCoords, chunk: &mut Chunk) -> Result<bool> {
+        let (region_coords, local_coords) = to_local_region_coords(coords);
+        let Some(region) = self.get_region(region_coords, false)? else {
+            return Ok(false);
+        };
+
+        if let Some(data) = region.read_chunk(local_coords)? {
+            decode_chunk(&data, &self.palette, chunk)
+                .with_context(|| format!("Failed to decode chunk {:?}", coords))?;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    pub fn save_chunk(&mut self, coords: ChunkCoords, chunk: &Chunk) -> Result<()> {
+        let (region_coords, local_coords) = to_local_region_coords(coords);
+        let data = encode_chunk(chunk, &self.palette);
+        let region = self.get_region(region_coords, true)?.unwrap();
+
+        region
+            .write_chunk(local_coords, &data)
+            .with_context(|| format!("Failed to save chunk {:?}", coords))
+    }
+}
+
+/// Size of a single run in an encoded chunk: length, block id, packed light levels
+/// and fluid level.
+const RUN_SIZE: usize = 6;
+/// Size of a run saved before the cells had a fluid level.
+const LEGACY_RUN_SIZE: usize = 5;
+
+fn for_each_coords(mut f: impl FnMut(BlockCoords)) {
+    for x in 0..Chunk::SIZE {
+        for y in 0..Chunk::SIZE {
+            for z in 0..Chunk::SIZE {
+                f(BlockCoords { x, y, z });
+            }
+        }
+    }
+}
+
+fn cells_equal(a: &Cell, b: &Cell) -> bool {
+    a.block_id == b.block_id
+        && a.sun_light == b.sun_light
+        && a.block_light == b.block_light
+        && a.fluid_level == b.fluid_level
+}
+
+/// Run-length encodes the cells of the chunk.
+fn encode_chunk(chunk: &Chunk, palette: &BlockPalette) -> Vec<u8> {
+    let mut data = vec![];
+    let mut emit_run = |length: u16, cell: &Cell| {
+        let saved_id = palette.to_saved[u16::from(cell.block_id) as usize];
+        data.extend_from_slice(&length.to_le_bytes());
+        data.extend_from_slice(&saved_id.to_le_bytes());
+        data.push((cell.sun_light << 4) | (cell.block_light & 0xF));
+        data.push(cell.fluid_level);
+    };
+
+    let mut current: Option<(u16, Cell)> = None;
+    for_each_coords(|coords| {
+        let cell = chunk[coords];
+        match &mut current {
+            Some((length, run_cell)) if cells_equal(run_cell, &cell) => *length += 1,
+            _ => {
+                if let Some((length, run_cell)) = &current {
+                    emit_run(*length, run_cell);
+                }
+                current = Some((1, cell));
+            }
+        }
+    });
+
+    if let Some((length, run_cell)) = &current {
+        emit_run(*length, run_cell);
+    }
+
+    data
+}
+
+/// Converts the runs of a legacy payload to the current ones, the cells get the fluid
+/// level of a source.
+fn upgrade_legacy_runs(data: &[u8]) -> Result<Vec<u8>> {
+    if !data.len().is_multiple_of(LEGACY_RUN_SIZE) {
+        bail!("Truncated chunk data");
+    }
+
+    let mut upgraded = Vec::with_capacity(data.len() / LEGACY_RUN_SIZE * RUN_SIZE);
+    for run in data.chunks_exact(LEGACY_RUN_SIZE) {
+        upgraded.extend_from_slice(run);
+        upgraded.push(fluid::SOURCE);
+    }
+    Ok(upgraded)
+}
+
+fn decode_chunk(data: &[u8], palette: &BlockPalette, chunk: &mut Chunk) -> Result<()> {
+    if !data.len().is_multiple_of(RUN_SIZE) {
+        bail!("Truncated chunk data");
+    }
+
+    const CHUNK_VOLUME: usize = (Chunk::SIZE * Chunk::SIZE * Chunk::SIZE) as usize;
+
+    let mut cells = Vec::with_capacity(CHUNK_VOLUME);
+    for run in data.chunks_exact(RUN_SIZE) {
+        let length = u16::from_le_bytes([run[0], run[1]]);
+        let saved_id = u16::from_le_bytes([run[2], run[3]]);
+        let block_id = *palette
+            .from_saved
+            .get(saved_id as usize)
+            .ok_or_else(|| anyhow!("Unknown block id {}", saved_id))?;
+
+        let cell = Cell {
+            block_id,
+            sun_light: run[4] >> 4,
+            block_light: run[4] & 0xF,
+            fluid_level: run[5],
+        };
+        cells.extend(std::iter::repeat_n(cell, length as usize));
+    }
+
+    if cells.len() != CHUNK_VOLUME {
+        bail!("Invalid chunk size: {} cells", cells.len());
+    }
+
+    let mut cells = cells.into_iter();
+    for_each_coords(|coords| chunk[coords] = cells.next().unwrap());
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{install_registries, temp_dir};
+
+    fn mixed_chunk() -> Chunk {
+        let stone = BlockId::by_name("stone").unwrap();
+        let water = BlockId::by_name("water").unwrap();
+
+        let mut chunk = Chunk::new();
+        for_each_coords(|coords| {
+            let cell = &mut chunk[coords];
+            if coords.y < 4 {
+                cell.block_id = stone;
+            } else if coords.y < 8 {
+                cell.block_id = water;
+                cell.fluid_level = (coords.x % 8) as u8;
+            }
+            cell.sun_light = (coords.y % 16) as u8;
+            cell.block_light = (coords.z % 3) as u8;
+        });
+        chunk
+    }
+
+    fn assert_chunks_equal(a: &Chunk, b: &Chunk) {
+        for_each_coords(|coords| assert!(cells_equal(&a[coords], &b[coords]), "{:?}", coords));
+    }
+
+    #[test]
+    fn encoded_chunk_decodes_to_the_same_cells() {
+        install_registries();
+        let palette = BlockPalette::load(&temp_dir("storage-round-trip")).unwrap();
+
+        let chunk = mixed_chunk();
+        let data = encode_chunk(&chunk, &palette);
+        let mut decoded = Chunk::new();
+        decode_chunk(&data, &palette, &mut decoded).unwrap();
+        assert_chunks_equal(&chunk, &decoded);
+    }
+
+    #[test]
+    fn truncated_chunk_is_rejected() {
+        install_registries();
+        let palette = BlockPalette::load(&temp_dir("storage-truncated")).unwrap();
+
+        let data = encode_chunk(&mixed_chunk(), &palette);
+        let mut decoded = Chunk::new();
+        assert!(decode_chunk(&data[..data.len() - 1], &palette, &mut decoded).is_err());
+    }
+
+    #[test]
+    fn region_file_keeps_the_chunks_apart() {
+        let path = temp_dir("storage-region").join("region.mcr");
+        let (first, second) = (ChunkCoords::new(0, 0, 0), ChunkCoords::new(3, 15, 7));
+
+        {
+            let mut region = RegionFile::open(&path).unwrap();
+            assert_eq!(region.read_chunk(first).unwrap(), None);
+            region.write_chunk(first, &[1; 12]).unwrap();
+            region.write_chunk(second, &[2; 18]).unwrap();
+        }
+
+        // Reopened, the header is read back from the file
+        let mut region = RegionFile::open(&path).unwrap();
+        assert_eq!(region.read_chunk(first).unwrap(), Some(vec![1; 12]));
+        assert_eq!(region.read_chunk(second).unwrap(), Some(vec![2; 18]));
+
+        // Does not fit in the old slot, so it must not overwrite the second chunk
+        region.write_chunk(first, &[3; 30]).unwrap();
+        assert_eq!(region.read_chunk(first).unwrap(), Some(vec![3; 30]));
+        assert_eq!(region.read_chunk(second).unwrap(), Some(vec![2; 18]));
+
+        let mut region = RegionFile::open(&path).unwrap();
+        assert_eq!(region.read_chunk(first).unwrap(), Some(vec![3; 30]));
+        assert_eq!(region.read_chunk(second).unwrap(), Some(vec![2; 18]));
+    }
+
+    /// A run of the whole chunk, without a fluid level
+    const LEGACY_RUN: [u8; LEGACY_RUN_SIZE] = [0x00, 0x10, 5, 0, 0xF0];
+    const UPGRADED_RUN: [u8; RUN_SIZE] = [0x00, 0x10, 5, 0, 0xF0, fluid::SOURCE];
+
+    /// Writes a region file in the legacy format with the run in the first chunk.
+    fn write_legacy_region(path: &Path) {
+        let mut entries = vec![RegionEntry::default(); RegionFile::VOLUME];
+        entries[0] = RegionEntry {
+            offset: RegionFile::HEADER_SIZE as u32,
+            length: LEGACY_RUN.len() as u32,
+        };
+        let mut contents = RegionFile::encode_header(&entries);
+        contents[..4].copy_from_slice(RegionFile::LEGACY_MAGIC);
+        contents.extend_from_slice(&LEGACY_RUN);
+        std::fs::write(path, contents).unwrap();
+    }
+
+    #[test]
+    fn legacy_region_file_is_upgraded_on_write() {
+        let path = temp_dir("storage-legacy-region").join("region.mcr");
+        let (first, second) = (ChunkCoords::new(0, 0, 0), ChunkCoords::new(1, 1, 1));
+        write_legacy_region(&path);
+
+        let upgraded_run = UPGRADED_RUN.to_vec();
+        let mut region = RegionFile::open(&path).unwrap();
+        assert_eq!(
+            region.read_chunk(first).unwrap(),
+            Some(upgraded_run.clone())
+        );
+
+        region.write_chunk(second, &[2; 12]).unwrap();
+        assert_eq!(&std::fs::read(&path).unwrap()[..4], RegionFile::MAGIC);
+
+        let mut region = RegionFile::open(&path).unwrap();
+        assert_eq!(region.read_chunk(first).unwrap(), Some(upgraded_run));
+        assert_eq!(region.read_chunk(second).unwrap(), Some(vec![2; 12]));
+    }
+
+    #[test]
+    fn upgraded_region_file_keeps_its_chunks() {
+        let dir = temp_dir("storage-upgrade");
+        let path = dir.join("region.mcr");
+        let chunk = ChunkCoords::new(0, 0, 0);
+        write_legacy_region(&path);
+
+        let mut region = RegionFile::open(&path).unwrap();
+        region.upgrade().unwrap();
+        assert!(!region.legacy);
+        assert_eq!(
+            region.read_chunk(chunk).unwrap(),
+            Some(UPGRADED_RUN.to_vec())
+        );
+
+        // Only the upgraded file is left
+        let files: Vec<_> = std::fs::read_dir(&dir).unwrap().collect();
+        assert_eq!(files.len(), 1);
+
+        let mut region = RegionFile::open(&path).unwrap();
+        assert!(!region.legacy);
+        assert_eq!(
+            region.read_chunk(chunk).unwrap(),
+            Some(UPGRADED_RUN.to_vec())
+        );
+        assert_eq!(region.read_chunk(ChunkCoords::new(1, 1, 1)).unwrap(), None);
+    }
+
+    #[test]
+    fn world_saved_without_metadata_keeps_its_settings() {
+        let path = temp_dir("storage-legacy-metadata");
+        std::fs::write(path.join(BlockPalette::FILE_NAME), "stone").unwrap();
+
+        let metadata = WorldMetadata::load_or_create(&path, Some(42), None).unwrap();
+        assert_eq!(metadata.seed, LEGACY_SEED);
+        assert_eq!(metadata.bounds, legacy_bounds());
+
+        let path = temp_dir("storage-new-metadata");
+        let metadata = WorldMetadata::load_or_create(&path, Some(42), None).unwrap();
+        assert_eq!(metadata.seed, 42);
+        assert_eq!(metadata.bounds, WorldBounds::default());
+
+        // Read back from the file, the given settings are ignored
+        let metadata = WorldMetadata::load_or_create(&path, Some(7), None).unwrap();
+        assert_eq!(metadata.seed, 42);
+    }
+
+    #[test]
+    fn region_files_are_created_on_write_and_closed_when_unused() {
+        install_registries();
+        let path = temp_dir("storage-regions");
+        let mut storage = WorldStorage::new(&path).unwrap();
+        let (near, far) = (ChunkCoords::new(0, 0, 0), ChunkCoords::new(40, 0, 0));
+        let region_count = || match std::fs::read_dir(path.join(WorldStorage::REGIONS_DIR)) {
+            Ok(entries) => entries.count(),
+            Err(_) => 0,
+        };
+
+        let mut chunk = Chunk::new();
+        assert!(!storage.load_chunk(near, &mut chunk).unwrap());
+        assert_eq!(region_count(), 0);
+
+        storage.save_chunk(near, &mixed_chunk()).unwrap();
+        storage.save_chunk(far, &mixed_chunk()).unwrap();
+        assert_eq!(region_count(), 2);
+        assert_eq!(storage.regions.len(), 2);
+
+        storage.close_unused_regions(std::iter::once(near));
+        assert_eq!(storage.regions.len(), 1);
+
+        // Opened again to be read
+        assert!(storage.load_chunk(far, &mut chunk).unwrap());
+        assert_chunks_equal(&chunk, &mixed_chunk());
+    }
+}
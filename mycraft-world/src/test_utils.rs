@@ -0,0 +1,32 @@
+use std::{path::PathBuf, sync::Once};
+
+use crate::{
+    blocks::{load_texture_names, BlockRegistry},
+    generation::StructureRegistry,
+};
+
+/// Installs the block and the structure registries of the game resources, once for
+/// all the tests.
+pub fn install_registries() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        let res = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../res");
+        let texture_names = load_texture_names(&res.join("textures").join("blocks")).unwrap();
+        BlockRegistry::load(&res.join("blocks.ron"), &texture_names)
+            .unwrap()
+            .install()
+            .unwrap();
+        StructureRegistry::load(&res.join("structures.ron"))
+            .unwrap()
+            .install()
+            .unwrap();
+    });
+}
+
+/// Returns an empty directory for the test, the previous run's files are removed.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("mycraft-test-{}", name));
+    let _ = std::fs::remove_dir_all(&path);
+    std::fs::create_dir_all(&path).unwrap();
+    path
+}
@@ -1,6 +1,6 @@
-use std::cell::{Ref, RefMut};
+use std::{cell::RefMut, sync::Arc};
 
-use cgmath::{ElementWise, Vector3};
+use cgmath::{ElementWise, Vector3, Zero};
 
 use super::{BlockCoords, Cell, Chunk, ChunkCoords, World};
 
@@ -27,10 +27,10 @@ pub fn to_chunk_offset(chunk_coords: ChunkCoords) -> Vector3<f32> {
     chunk_coords.mul_element_wise(Chunk::SIZE).map(|x| x as f32)
 }
 
-/// Borrows a 3x3x3 chunk region
-pub struct ChunkNeighborhood<'a> {
-    chunk: &'a Chunk,
-    neighbors: [[[Option<Ref<'a, Chunk>>; 3]; 3]; 3],
+/// Snapshot of a 3x3x3 chunk region, can be sent to other threads
+pub struct ChunkNeighborhood {
+    chunk: Arc<Chunk>,
+    neighbors: [[[Option<Arc<Chunk>>; 3]; 3]; 3],
 }
 
 const fn empty_neighbor_array<T>() -> [[[Option<T>; 3]; 3]; 3] {
@@ -41,10 +41,9 @@ const fn empty_neighbor_array<T>() -> [[[Option<T>; 3]; 3]; 3] {
     ]
 }
 
-fn borrow_neighborhood<'a, T: 'a>(
-    world: &'a World,
+fn borrow_neighborhood<T>(
     chunk_coords: ChunkCoords,
-    f: impl Fn(&'a World, ChunkCoords) -> Option<T>,
+    f: impl Fn(ChunkCoords) -> Option<T>,
 ) -> [[[Option<T>; 3]; 3]; 3] {
     let mut neighbors = empty_neighbor_array();
     #[allow(clippy::needless_range_loop)]
@@ -57,7 +56,7 @@ fn borrow_neighborhood<'a, T: 'a>(
                         y: y as i32 - 1,
                         z: z as i32 - 1,
                     };
-                    neighbors[x][y][z] = f(world, chunk_coords + offset);
+                    neighbors[x][y][z] = f(chunk_coords + offset);
                 }
             }
         }
@@ -66,12 +65,26 @@ fn borrow_neighborhood<'a, T: 'a>(
     neighbors
 }
 
-impl<'a> ChunkNeighborhood<'a> {
-    pub fn new(world: &'a World, chunk: &'a Chunk, chunk_coords: ChunkCoords) -> Self {
-        let neighbors = borrow_neighborhood(world, chunk_coords, World::borrow_chunk);
+impl ChunkNeighborhood {
+    pub fn new(world: &World, chunk: Arc<Chunk>, chunk_coords: ChunkCoords) -> Self {
+        Self::from_fn(chunk, chunk_coords, |coords| world.share_chunk(coords))
+    }
+
+    /// Takes the neighbors from `get_chunk`, for chunks that are not in a `World`.
+    pub fn from_fn(
+        chunk: Arc<Chunk>,
+        chunk_coords: ChunkCoords,
+        get_chunk: impl Fn(ChunkCoords) -> Option<Arc<Chunk>>,
+    ) -> Self {
+        let neighbors = borrow_neighborhood(chunk_coords, get_chunk);
         ChunkNeighborhood { chunk, neighbors }
     }
 
+    #[inline]
+    pub fn center(&self) -> &Chunk {
+        &self.chunk
+    }
+
     // Coords are relative to middle chunk in chunks array
     pub fn get_cell(&self, coords: Vector3<i32>) -> Option<Cell> {
         if (0..Chunk::SIZE).contains(&coords.x)
@@ -89,16 +102,39 @@ impl<'a> ChunkNeighborhood<'a> {
     }
 }
 
-/// Borrows a 3x3x3 chunk region mutably
+/// Borrows a 3x3x3 chunk region mutably, skipping the chunks that are not generated yet
 pub struct ChunkNeighborhoodMut<'a> {
     chunk: &'a mut Chunk,
+    chunk_coords: ChunkCoords,
     neighbors: [[[Option<RefMut<'a, Chunk>>; 3]; 3]; 3],
+    /// Chunks whose cells were borrowed mutably, relative to the center
+    changed: [[[bool; 3]; 3]; 3],
 }
 
 impl<'a> ChunkNeighborhoodMut<'a> {
     pub fn new(world: &'a World, chunk: &'a mut Chunk, chunk_coords: ChunkCoords) -> Self {
-        let neighbors = borrow_neighborhood(world, chunk_coords, World::borrow_mut_chunk);
-        ChunkNeighborhoodMut { chunk, neighbors }
+        Self::from_fn(chunk, chunk_coords, |coords| {
+            if world.is_chunk_generated(coords) {
+                world.borrow_mut_chunk(coords)
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Borrows the neighbors from `borrow_chunk`, for chunks that are not in a `World`.
+    pub fn from_fn(
+        chunk: &'a mut Chunk,
+        chunk_coords: ChunkCoords,
+        borrow_chunk: impl Fn(ChunkCoords) -> Option<RefMut<'a, Chunk>>,
+    ) -> Self {
+        let neighbors = borrow_neighborhood(chunk_coords, borrow_chunk);
+        ChunkNeighborhoodMut {
+            chunk,
+            chunk_coords,
+            neighbors,
+            changed: [[[false; 3]; 3]; 3],
+        }
     }
 
     pub fn get_cell(&self, coords: Vector3<i32>) -> Option<Cell> {
@@ -111,23 +147,47 @@ impl<'a> ChunkNeighborhoodMut<'a> {
 
         let (chunk_coords, block_coords) = to_local_chunk_coords(coords);
         let array_coords = chunk_coords.map(|x| (x + 1) as usize);
-        self.neighbors[array_coords.x][array_coords.y][array_coords.z]
+        self.neighbors
+            .get(array_coords.x)?
+            .get(array_coords.y)?
+            .get(array_coords.z)?
             .as_ref()
             .map(|chunk| chunk[block_coords])
     }
 
     pub fn get_cell_mut(&mut self, coords: Vector3<i32>) -> Option<&mut Cell> {
-        if (0..Chunk::SIZE).contains(&coords.x)
-            && (0..Chunk::SIZE).contains(&coords.y)
-            && (0..Chunk::SIZE).contains(&coords.z)
-        {
-            return Some(&mut self.chunk[coords]);
-        }
-
         let (chunk_coords, block_coords) = to_local_chunk_coords(coords);
         let array_coords = chunk_coords.map(|x| (x + 1) as usize);
-        self.neighbors[array_coords.x][array_coords.y][array_coords.z]
-            .as_mut()
-            .map(|chunk| &mut chunk[block_coords])
+        let changed = self
+            .changed
+            .get_mut(array_coords.x)?
+            .get_mut(array_coords.y)?
+            .get_mut(array_coords.z)?;
+
+        if chunk_coords == ChunkCoords::zero() {
+            *changed = true;
+            return Some(&mut self.chunk[block_coords]);
+        }
+
+        let chunk = self.neighbors[array_coords.x][array_coords.y][array_coords.z].as_mut()?;
+        *changed = true;
+        Some(&mut chunk[block_coords])
+    }
+
+    /// Returns the coords of the chunks whose cells were borrowed mutably.
+    pub fn changed_chunks(&self) -> Vec<ChunkCoords> {
+        let mut result = vec![];
+        for (x, plane) in self.changed.iter().enumerate() {
+            for (y, row) in plane.iter().enumerate() {
+                for (z, &changed) in row.iter().enumerate() {
+                    if changed {
+                        let offset = Vector3::new(x, y, z).map(|x| x as i32 - 1);
+                        result.push(self.chunk_coords + offset);
+                    }
+                }
+            }
+        }
+
+        result
     }
 }
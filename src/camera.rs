@@ -23,6 +23,7 @@ pub struct Camera {
     fov: f32,
     near: f32,
     far: f32,
+    aspect_ratio: f32,
 
     pub position: Vector3<f32>,
     rotation: Vector2<f32>,
@@ -50,6 +51,7 @@ impl Camera {
             fov: 60.,
             near: 0.01,
             far: 500.,
+            aspect_ratio: 1.,
 
             position: Vector3::zero(),
             rotation: Vector2::zero(),
@@ -75,10 +77,19 @@ impl Camera {
     }
 
     pub fn resize_projection(&mut self, aspect_ratio: f32) {
+        self.aspect_ratio = aspect_ratio;
         self.projection =
             cgmath::perspective(cgmath::Deg(self.fov), aspect_ratio, self.near, self.far);
     }
 
+    /// Moves the far plane, which follows the view distance.
+    pub fn set_far(&mut self, far: f32) {
+        if far != self.far {
+            self.far = far;
+            self.resize_projection(self.aspect_ratio);
+        }
+    }
+
     pub fn rotate(&mut self, amount: Vector2<f32>) {
         self.rotation += amount;
 
@@ -90,15 +101,16 @@ impl Camera {
         }
     }
 
-    pub fn move_relative_to_view(&mut self, amount: Vector3<f32>) {
+    /// Rotates the vector around the Y axis to match the horizontal view direction.
+    pub fn relative_to_view(&self, amount: Vector3<f32>) -> Vector3<f32> {
         let sin = self.rotation.x.to_radians().sin();
         let cos = self.rotation.x.to_radians().cos();
 
-        self.position += Vector3 {
+        Vector3 {
             x: amount.x * cos - amount.z * sin,
             y: amount.y,
             z: amount.z * cos + amount.x * sin,
-        };
+        }
     }
 
     #[inline]
@@ -113,6 +125,18 @@ impl Camera {
 
         Vector3::new(vec4.x, vec4.y, vec4.z)
     }
+
+    /// Returns the center and the radius of a sphere containing the part of the view
+    /// between the two distances. The radius does not change as the camera turns.
+    pub fn get_slice_bounds(&self, near: f32, far: f32) -> (Vector3<f32>, f32) {
+        let center = self.position + self.get_direction() * (near + far) / 2.;
+
+        // Squared distance from the view axis to the corners, over the squared distance
+        let half_fov = (self.fov / 2.).to_radians();
+        let corner_ratio = half_fov.tan().powi(2) * (1. + self.aspect_ratio.powi(2));
+        let radius = ((far - near).powi(2) / 4. + far * far * corner_ratio).sqrt();
+        (center, radius)
+    }
 }
 
 impl Bindable for Camera {
@@ -1,17 +1,27 @@
-use std::time::Duration;
-
 pub const WIN_TITLE: &str = "Mycraft";
 pub const WIN_SIZE: (u32, u32) = (1080, 720);
 pub const FPS: u32 = 60;
 
-pub const MAX_UPDATE_TIME: Duration = Duration::from_millis(15);
-
 pub const CAMERA_MOVEMENT_SPEED: f32 = 8.;
 pub const MOUSE_SENSITIVITY: f32 = 0.2;
 
-pub const RENDER_DISTANCE: i32 = 32;
-pub const WORLD_HEIGHT: i32 = 16;
 pub const MAX_RAYCASTING_DISTANCE: f32 = 6.;
 
 pub const MIDNIGHT_SUNLIGHT: f32 = 0.2;
 pub const DAY_LENGTH_SECS: f32 = 10. * 60.;
+
+/// Fraction of the view distance at which the fog starts
+pub const FOG_START: f32 = 0.6;
+/// Far plane of the camera over the view distance, leaving room for the blocks far below
+pub const FAR_PLANE_FACTOR: f32 = 2.;
+
+pub const SHADOW_MAP_SIZE: u32 = 2048;
+pub const SHADOW_CASCADE_COUNT: usize = 3;
+/// Distances from the camera at which the shadow cascades end, the shadows stop at the last
+pub const SHADOW_CASCADE_ENDS: [f32; SHADOW_CASCADE_COUNT] = [20., 60., 160.];
+/// How far towards the sun the blocks casting shadows into a cascade can be
+pub const SHADOW_CASTER_DISTANCE: f32 = 160.;
+/// Height of the sun direction above which the shadows are fully visible
+pub const SHADOW_FADE_SUN_HEIGHT: f32 = 0.2;
+
+pub const WORLD_SAVE_PATH: &str = "./world";
@@ -4,6 +4,7 @@ use winit::{dpi::PhysicalSize, window::Window};
 
 pub struct Context {
     pub window: Window,
+    pub adapter: wgpu::Adapter,
     pub device: wgpu::Device,
     pub queue: wgpu::Queue,
     pub surface: wgpu::Surface,
@@ -11,6 +12,10 @@ pub struct Context {
 }
 
 impl Context {
+    /// Lets the chunks be drawn by a single indirect draw call per pass.
+    const MULTI_DRAW_FEATURES: wgpu::Features =
+        wgpu::Features::MULTI_DRAW_INDIRECT.union(wgpu::Features::INDIRECT_FIRST_INSTANCE);
+
     pub async fn new(window: Window) -> Self {
         let size = window.inner_size();
 
@@ -33,8 +38,15 @@ impl Context {
         let (device, queue) = adapter
             .request_device(
                 &wgpu::DeviceDescriptor {
-                    features: wgpu::Features::empty(),
-                    limits: wgpu::Limits::default(),
+                    features: adapter.features() & Self::MULTI_DRAW_FEATURES,
+                    // The chunk buffers grow past the default limits
+                    limits: wgpu::Limits {
+                        max_buffer_size: adapter.limits().max_buffer_size,
+                        max_storage_buffer_binding_size: adapter
+                            .limits()
+                            .max_storage_buffer_binding_size,
+                        ..Default::default()
+                    },
                     label: Some("Device"),
                 },
                 None,
@@ -49,6 +61,7 @@ impl Context {
 
         Context {
             window,
+            adapter,
             device,
             queue,
             surface,
@@ -56,6 +69,11 @@ impl Context {
         }
     }
 
+    /// Otherwise the draw calls of the indirect buffers are issued one by one.
+    pub fn supports_multi_draw_indirect(&self) -> bool {
+        self.device.features().contains(Self::MULTI_DRAW_FEATURES)
+    }
+
     pub fn resize(&self, new_size: PhysicalSize<u32>) {
         let mut surface_config = self.surface_config.borrow_mut();
         surface_config.width = new_size.width;
@@ -1,7 +1,12 @@
 use std::{collections::HashMap, rc::Rc};
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use cgmath::{Vector2, Vector3, Zero};
+use mycraft_world::{
+    aabb::Aabb,
+    blocks::{Block, BlockId, BlockShape},
+    get_chunk_and_block_coords, raycasting, to_chunk_offset, Chunk, World, WorldBounds, WorldDesc,
+};
 use winit::{
     event::{
         DeviceEvent, ElementState, Event, KeyboardInput, MouseButton, VirtualKeyCode, WindowEvent,
@@ -15,37 +20,43 @@ use crate::{
     context::Context,
     egui::EguiContext,
     meshes::*,
+    player::{MovementMode, Player},
     rendering::{
         line_renderer::{LineMesh, LineMeshUniform, LineRenderer},
+        post_processing::PostProcessing,
+        shadows::ShadowMap,
         sky_renderer::SkyRenderer,
-        texture::DepthBuffer,
+        texture::{ColorBuffer, DepthBuffer, TextureFiltering},
+        world_graphics::WorldGraphics,
         world_renderer::WorldRenderer,
         RenderTargetWithDepth,
     },
     resources::Resources,
     sky::Sky,
-    utils::{
-        input::{Input3d, Input3dDesc},
-        raycasting,
-    },
-    world::{blocks::BlockId, get_chunk_and_block_coords, to_chunk_offset, ChunkCoords, World},
+    utils::input::{Input3d, Input3dDesc},
 };
 
 pub struct Mycraft {
     context: Rc<Context>,
 
+    /// In HDR, the sky, the world and the lines are drawn to it, then post-processed
+    /// to the screen
+    scene_buffer: ColorBuffer,
     depth_buffer: DepthBuffer,
     world_renderer: WorldRenderer,
     sky_renderer: SkyRenderer,
     line_renderer: LineRenderer,
+    post_processing: PostProcessing,
 
     sky: Sky,
+    shadow_map: ShadowMap,
     block_selection: LineMesh,
 
     chunk_borders: LineMesh,
     draw_chunk_borders: bool,
 
     camera: Camera,
+    player: Player,
     looking_at: Option<raycasting::Hit>,
     movement_input: Input3d,
     in_menu: bool,
@@ -55,24 +66,27 @@ pub struct Mycraft {
     hotbar: HashMap<VirtualKeyCode, BlockId>,
 
     world: World,
+    world_graphics: WorldGraphics,
 }
 
 impl Mycraft {
-    pub fn try_new(context: Rc<Context>) -> Result<Self> {
-        let mut world = World::new(context.clone());
-        for x in -RENDER_DISTANCE..RENDER_DISTANCE {
-            for z in -RENDER_DISTANCE..RENDER_DISTANCE {
-                for y in 0..WORLD_HEIGHT {
-                    if x * x + z * z < RENDER_DISTANCE * RENDER_DISTANCE {
-                        world.load_chunk(ChunkCoords { x, y, z });
-                    }
-                }
-            }
-        }
-
-        let resources = Resources::try_load(&context, "./res")?;
-
-        let sky = Sky::new(context.clone());
+    pub fn try_new(
+        context: Rc<Context>,
+        seed: Option<u32>,
+        bounds: Option<WorldBounds>,
+        filtering: TextureFiltering,
+    ) -> Result<Self> {
+        let resources = Resources::try_load(&context, "./res", filtering)?;
+        let world = World::new(WorldDesc {
+            save_path: WORLD_SAVE_PATH.into(),
+            seed,
+            bounds,
+            generate_meshes: true,
+        })?;
+        let world_graphics = WorldGraphics::new(context.clone());
+
+        let shadow_map = ShadowMap::new(context.clone(), resources.blocks_texture.clone());
+        let sky = Sky::new(context.clone(), &resources.sky_texture, &shadow_map);
 
         let block_selection = LineMesh::new(
             context.clone(),
@@ -96,23 +110,35 @@ impl Mycraft {
             },
         );
 
-        let depth_buffer = {
+        let surface_size = {
             let surface_config = context.surface_config.borrow();
-            DepthBuffer::new(
-                context.clone(),
-                "Block Depth Buffer",
-                Vector2 {
-                    x: surface_config.width,
-                    y: surface_config.height,
-                },
-            )
+            Vector2 {
+                x: surface_config.width,
+                y: surface_config.height,
+            }
         };
-        let world_renderer = WorldRenderer::new(&context, resources.blocks_texture);
-        let sky_renderer = SkyRenderer::new(&context, resources.sky_texture);
+        let scene_buffer = ColorBuffer::new(
+            context.clone(),
+            "Scene Buffer",
+            ColorBuffer::HDR_FORMAT,
+            surface_size,
+        );
+        let depth_buffer = DepthBuffer::new(context.clone(), "Block Depth Buffer", surface_size);
+        let world_renderer = WorldRenderer::new(
+            context.clone(),
+            resources.blocks_texture,
+            &scene_buffer,
+            &depth_buffer,
+        );
+        let sky_renderer = SkyRenderer::new(&context);
         let line_renderer = LineRenderer::new(&context);
+        let post_processing = PostProcessing::new(context.clone(), &scene_buffer, &depth_buffer);
 
-        let mut camera = Camera::new(context.clone(), "Camera");
-        camera.position = Vector3::new(0., 40., 0.);
+        let camera = Camera::new(context.clone(), "Camera");
+        // On the block above the surface, the collisions cannot push the body out of the
+        // terrain
+        let spawn_height = (world.terrain_height(0, 0) + 1) as f32;
+        let player = Player::new(Vector3::new(0.5, spawn_height, 0.5));
 
         use winit::event::VirtualKeyCode::*;
         let movement_input = Input3d::new(Input3dDesc {
@@ -124,19 +150,27 @@ impl Mycraft {
             neg_z: W,
         });
 
-        let hotbar: HashMap<VirtualKeyCode, BlockId> = HashMap::from([
-            (Key1, BlockId::Stone),
-            (Key2, BlockId::Grass),
-            (Key3, BlockId::Dirt),
-            (Key4, BlockId::Trunk),
-            (Key5, BlockId::Leaves),
-            (Key6, BlockId::Water),
-            (Key7, BlockId::Sand),
-            (Key8, BlockId::Planks),
-            (Key9, BlockId::RedFlower),
-            (Key0, BlockId::YellowFlower),
-            (Minus, BlockId::Torch),
-        ]);
+        let hotbar = [
+            (Key1, "stone"),
+            (Key2, "grass"),
+            (Key3, "dirt"),
+            (Key4, "trunk"),
+            (Key5, "leaves"),
+            (Key6, "water"),
+            (Key7, "sand"),
+            (Key8, "planks"),
+            (Key9, "red_flower"),
+            (Key0, "yellow_flower"),
+            (Minus, "torch"),
+        ]
+        .into_iter()
+        .map(|(key, name)| {
+            let block_id = BlockId::by_name(name)
+                .ok_or_else(|| anyhow!("Hotbar block {} is not defined", name))?;
+            Ok((key, block_id))
+        })
+        .collect::<Result<HashMap<VirtualKeyCode, BlockId>>>()?;
+        let current_block = hotbar[&Key3];
 
         let egui = EguiContext::new(context.clone());
         puffin::set_scopes_on(false);
@@ -144,27 +178,32 @@ impl Mycraft {
         Ok(Mycraft {
             context,
 
+            scene_buffer,
             depth_buffer,
             world_renderer,
             sky_renderer,
             line_renderer,
+            post_processing,
 
             sky,
+            shadow_map,
             block_selection,
 
             chunk_borders,
             draw_chunk_borders: false,
 
             camera,
+            player,
             looking_at: None,
             movement_input,
             in_menu: false,
             egui,
 
-            current_block: BlockId::Dirt,
+            current_block,
             hotbar,
 
             world,
+            world_graphics,
         })
     }
 
@@ -215,6 +254,10 @@ impl Mycraft {
                                 self.current_block = *block_id;
                             }
 
+                            if *code == VirtualKeyCode::F {
+                                self.player.toggle_noclip();
+                            }
+
                             if *code == VirtualKeyCode::Escape {
                                 if self.in_menu {
                                     self.grab_cursor();
@@ -234,14 +277,24 @@ impl Mycraft {
                         if let Some(hit) = &self.looking_at {
                             match button {
                                 MouseButton::Left => {
-                                    self.world.set_block(hit.coords, BlockId::Air);
+                                    self.world.set_block(hit.coords, BlockId::AIR);
                                 }
 
                                 MouseButton::Right => {
-                                    self.world.set_block(
-                                        hit.coords + hit.side.to_direction(),
-                                        self.current_block,
-                                    );
+                                    let coords = hit.coords + hit.side.to_direction();
+                                    let block_aabb = Aabb {
+                                        start: coords.map(|x| x as f32),
+                                        size: Vector3::new(1., 1., 1.),
+                                    };
+
+                                    // Do not place blocks inside the player
+                                    let block = Block::by_id(self.current_block);
+                                    if !block.is_collidable()
+                                        || self.player.movement_mode == MovementMode::Noclip
+                                        || !self.player.body().intersects(&block_aabb)
+                                    {
+                                        self.world.set_block(coords, self.current_block);
+                                    }
                                 }
 
                                 _ => {}
@@ -273,7 +326,16 @@ impl Mycraft {
 
     pub fn resize(&mut self, size: Vector2<u32>) {
         self.camera.resize_projection(size.x as f32 / size.y as f32);
+        self.scene_buffer.resize(size);
         self.depth_buffer.resize(size);
+        self.world_renderer
+            .resize(&self.scene_buffer, &self.depth_buffer);
+        self.post_processing
+            .resize(&self.scene_buffer, &self.depth_buffer);
+    }
+
+    pub fn save(&mut self) -> Result<()> {
+        self.world.save()
     }
 
     pub fn update(&mut self, delta: std::time::Duration) {
@@ -282,11 +344,32 @@ impl Mycraft {
 
         let delta_secs = delta.as_secs_f32();
 
+        // The last ring of chunks is fully in the fog, hiding the jagged edge of the world
+        let view_distance = ((self.world.render_distance() - 1) * Chunk::SIZE) as f32;
+        self.sky.set_view_distance(view_distance);
         self.sky.update(delta);
+        self.camera.set_far(view_distance * FAR_PLANE_FACTOR);
 
-        let movement = self.movement_input.get_value() * CAMERA_MOVEMENT_SPEED * delta_secs;
-        self.camera.move_relative_to_view(movement);
+        let movement = self
+            .camera
+            .relative_to_view(self.movement_input.get_value());
+        self.player.update(&self.world, movement, delta_secs);
+        self.camera.position = self.player.eye_position();
         self.camera.update_matrix();
+        self.shadow_map
+            .update(&self.camera, self.sky.sun_direction());
+
+        let camera_block = self
+            .world
+            .get_block(self.camera.position.map(|x| x.floor() as i32));
+        let underwater = matches!(
+            camera_block,
+            Some(Block {
+                shape: BlockShape::Fluid { .. },
+                ..
+            })
+        );
+        self.post_processing.set_underwater(underwater);
 
         self.looking_at = raycasting::cast_ray(
             &self.world,
@@ -308,7 +391,8 @@ impl Mycraft {
             offset: to_chunk_offset(get_chunk_and_block_coords(self.camera.position).0),
         });
 
-        self.world.update(&self.camera);
+        self.world.update(self.camera.position);
+        self.world_graphics.update(&mut self.world, &self.camera);
     }
 
     pub fn render(&mut self, target: &wgpu::TextureView) {
@@ -322,17 +406,23 @@ impl Mycraft {
                 });
 
         let target_with_depth = RenderTargetWithDepth {
-            color: target,
+            color: self.scene_buffer.get_texture_view(),
             depth: self.depth_buffer.get_texture_view(),
         };
 
-        self.sky_renderer
-            .draw(&mut encoder, target, &self.camera, &self.sky);
+        self.shadow_map.draw(&mut encoder, &self.world_graphics);
+        self.sky_renderer.draw(
+            &mut encoder,
+            self.scene_buffer.get_texture_view(),
+            &self.camera,
+            &self.sky,
+        );
         self.world_renderer.draw(
             &mut encoder,
-            target_with_depth,
+            &self.scene_buffer,
+            &self.depth_buffer,
             &self.camera,
-            self.world.render_queue_iter(),
+            &self.world_graphics,
             &self.sky,
         );
 
@@ -354,20 +444,40 @@ impl Mycraft {
             );
         }
 
+        self.post_processing
+            .draw(&mut encoder, target, &self.camera, &self.sky);
+
         self.egui.draw_frame(&mut encoder, target, |ctx| {
             if !self.in_menu {
                 ctx.set_cursor_icon(egui::CursorIcon::None);
             }
 
             egui::Window::new("Debug").show(ctx, |ui| {
+                ui.label(format!("Seed: {}", self.world.seed()));
                 ui.label(format!("Chunks loaded: {}", self.world.num_chunks_loaded()));
                 ui.label(format!(
                     "Chunks rendered: {}",
-                    self.world.num_chunks_rendered()
+                    self.world_graphics.num_chunks_rendered()
                 ));
 
+                let mut render_distance = self.world.render_distance();
+                let slider =
+                    egui::Slider::new(&mut render_distance, 2..=64).text("Render distance");
+                if ui.add(slider).changed() {
+                    self.world.set_render_distance(render_distance);
+                }
+
+                let mut noclip = self.player.movement_mode == MovementMode::Noclip;
+                if ui.checkbox(&mut noclip, "Noclip (F)").changed() {
+                    self.player.toggle_noclip();
+                }
+
                 ui.checkbox(&mut self.draw_chunk_borders, "Draw chunk borders");
 
+                ui.collapsing("Post-processing", |ui| {
+                    self.post_processing.settings.show(ui);
+                });
+
                 let mut profiling_on = puffin::are_scopes_on();
                 if ui.checkbox(&mut profiling_on, "Profiling").changed() {
                     puffin::set_scopes_on(profiling_on);
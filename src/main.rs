@@ -4,11 +4,11 @@ mod context;
 mod egui;
 mod game;
 mod meshes;
+mod player;
 mod rendering;
 mod resources;
 mod sky;
 mod utils;
-mod world;
 
 use std::{
     rc::Rc,
@@ -17,6 +17,8 @@ use std::{
 
 use anyhow::Result;
 use cgmath::Vector2;
+use clap::Parser;
+use mycraft_world::WorldBounds;
 use winit::{
     dpi::PhysicalSize,
     event::{Event, StartCause, WindowEvent},
@@ -24,7 +26,23 @@ use winit::{
     window::WindowBuilder,
 };
 
-use crate::{consts::*, context::Context, game::Mycraft};
+use crate::{consts::*, context::Context, game::Mycraft, rendering::texture::TextureFiltering};
+
+#[derive(Parser)]
+struct Args {
+    /// Seed used to create the world, an existing world keeps its own seed
+    #[arg(long)]
+    seed: Option<u32>,
+    /// Height of the lowest blocks of a new world, a multiple of 16
+    #[arg(long, allow_hyphen_values = true)]
+    min_height: Option<i32>,
+    /// Height above the highest blocks of a new world, a multiple of 16
+    #[arg(long, allow_hyphen_values = true)]
+    max_height: Option<i32>,
+    /// How the block textures are filtered
+    #[arg(long, value_enum, default_value_t = TextureFiltering::Trilinear)]
+    filtering: TextureFiltering,
+}
 
 fn main() -> Result<()> {
     fn resize(context: &Context, game: &mut Mycraft, size: PhysicalSize<u32>) {
@@ -36,6 +54,8 @@ fn main() -> Result<()> {
         game.resize(Vector2::new(size.width, size.height));
     }
 
+    let args = Args::parse();
+
     let event_loop = EventLoop::new();
     let window = WindowBuilder::new()
         .with_title(WIN_TITLE)
@@ -48,7 +68,8 @@ fn main() -> Result<()> {
     env_logger::init();
 
     let context = Rc::new(pollster::block_on(Context::new(window)));
-    let mut game = Mycraft::try_new(context.clone())?;
+    let bounds = WorldBounds::from_heights(args.min_height, args.max_height)?;
+    let mut game = Mycraft::try_new(context.clone(), args.seed, bounds, args.filtering)?;
 
     let frame_duration = Duration::new(1, 0) / FPS;
     let mut last_frame_time = Instant::now();
@@ -114,6 +135,12 @@ fn main() -> Result<()> {
                 }
             }
 
+            Event::LoopDestroyed => {
+                if let Err(err) = game.save() {
+                    log::error!("Failed to save the world: {:?}", err);
+                }
+            }
+
             _ => {}
         }
     });
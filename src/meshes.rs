@@ -1,6 +1,6 @@
 use cgmath::Vector3;
 
-use crate::world::Chunk;
+use mycraft_world::Chunk;
 
 const fn vec3(x: f32, y: f32, z: f32) -> Vector3<f32> {
     Vector3 { x, y, z }
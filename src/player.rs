@@ -0,0 +1,205 @@
+use cgmath::{Vector3, Zero};
+
+use mycraft_world::{
+    aabb::Aabb,
+    blocks::{Block, BlockShape},
+    get_chunk_and_block_coords, BlockCoords, World,
+};
+
+use crate::consts::*;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum MovementMode {
+    Walking,
+    /// Flying through blocks, ignoring gravity
+    Noclip,
+}
+
+pub struct Player {
+    /// Position of the center of the bottom of the body
+    pub position: Vector3<f32>,
+    velocity: Vector3<f32>,
+    pub movement_mode: MovementMode,
+
+    on_ground: bool,
+    in_fluid: bool,
+}
+
+impl Player {
+    const WIDTH: f32 = 0.6;
+    const HEIGHT: f32 = 1.8;
+    const EYE_HEIGHT: f32 = 1.62;
+
+    const WALKING_SPEED: f32 = 4.3;
+    const JUMP_SPEED: f32 = 8.;
+    const GRAVITY: f32 = 28.;
+    const MAX_FALLING_SPEED: f32 = 50.;
+
+    const FLUID_SPEED_FACTOR: f32 = 0.5;
+    const FLUID_GRAVITY: f32 = 6.;
+    const MAX_SINKING_SPEED: f32 = 2.;
+    const SWIMMING_SPEED: f32 = 3.;
+
+    /// Gap left between the body and the blocks it collides with
+    const COLLISION_MARGIN: f32 = 0.001;
+
+    pub fn new(position: Vector3<f32>) -> Self {
+        Player {
+            position,
+            velocity: Vector3::zero(),
+            movement_mode: MovementMode::Walking,
+
+            on_ground: false,
+            in_fluid: false,
+        }
+    }
+
+    pub fn eye_position(&self) -> Vector3<f32> {
+        self.position + Vector3::unit_y() * Self::EYE_HEIGHT
+    }
+
+    pub fn body(&self) -> Aabb {
+        Aabb {
+            start: self.position - Vector3::new(Self::WIDTH / 2., 0., Self::WIDTH / 2.),
+            size: Vector3::new(Self::WIDTH, Self::HEIGHT, Self::WIDTH),
+        }
+    }
+
+    pub fn toggle_noclip(&mut self) {
+        self.movement_mode = match self.movement_mode {
+            MovementMode::Walking => MovementMode::Noclip,
+            MovementMode::Noclip => MovementMode::Walking,
+        };
+        self.velocity = Vector3::zero();
+    }
+
+    /// Moves the player, `input` is the desired direction of movement in world space,
+    /// in walking mode its Y component makes the player jump or swim up.
+    pub fn update(&mut self, world: &World, input: Vector3<f32>, delta_secs: f32) {
+        if self.movement_mode == MovementMode::Noclip {
+            self.position += input * CAMERA_MOVEMENT_SPEED * delta_secs;
+            return;
+        }
+
+        // Wait for the terrain to appear instead of falling through it
+        let (chunk_coords, _) = get_chunk_and_block_coords(self.position);
+        if !world.is_chunk_generated(chunk_coords) {
+            return;
+        }
+
+        self.in_fluid = self.touches_fluid(world);
+
+        let horizontal_speed = if self.in_fluid {
+            Self::WALKING_SPEED * Self::FLUID_SPEED_FACTOR
+        } else {
+            Self::WALKING_SPEED
+        };
+        self.velocity.x = input.x * horizontal_speed;
+        self.velocity.z = input.z * horizontal_speed;
+
+        if self.in_fluid {
+            self.velocity.y -= Self::FLUID_GRAVITY * delta_secs;
+            self.velocity.y = self.velocity.y.max(-Self::MAX_SINKING_SPEED);
+            if input.y > 0. {
+                self.velocity.y = Self::SWIMMING_SPEED;
+            }
+        } else {
+            self.velocity.y -= Self::GRAVITY * delta_secs;
+            self.velocity.y = self.velocity.y.max(-Self::MAX_FALLING_SPEED);
+            if input.y > 0. && self.on_ground {
+                self.velocity.y = Self::JUMP_SPEED;
+            }
+        }
+
+        let movement = self.velocity * delta_secs;
+        self.on_ground = false;
+        for axis in [1, 0, 2] {
+            let moved = self.move_along_axis(world, axis, movement[axis]);
+            if moved != movement[axis] {
+                if axis == 1 && movement[axis] < 0. {
+                    self.on_ground = true;
+                }
+                self.velocity[axis] = 0.;
+            }
+        }
+    }
+
+    fn touches_fluid(&self, world: &World) -> bool {
+        let body = self.body();
+        let start = body.start.map(|x| x.floor() as i32);
+        let end = body.end().map(|x| x.ceil() as i32);
+
+        for x in start.x..end.x {
+            for y in start.y..end.y {
+                for z in start.z..end.z {
+                    let block = world.get_block(BlockCoords { x, y, z });
+                    if let Some(Block {
+                        shape: BlockShape::Fluid { .. },
+                        ..
+                    }) = block
+                    {
+                        return true;
+                    }
+                }
+            }
+        }
+
+        false
+    }
+
+    /// Moves the body along the axis until it hits a collidable block,
+    /// returns the distance actually moved.
+    fn move_along_axis(&mut self, world: &World, axis: usize, amount: f32) -> f32 {
+        if amount == 0. {
+            return 0.;
+        }
+
+        let body = self.body();
+        let (body_start, body_end) = (body.start[axis], body.end()[axis]);
+
+        // Blocks the body sweeps through, blocks it already overlaps are ignored
+        // so that it can get out of them
+        let mut sweep_start = body.start.map(|x| x.floor() as i32);
+        let mut sweep_end = body.end().map(|x| x.ceil() as i32);
+        if amount > 0. {
+            sweep_start[axis] = body_end.ceil() as i32 - 1;
+            sweep_end[axis] = (body_end + amount).ceil() as i32;
+        } else {
+            sweep_start[axis] = (body_start + amount).floor() as i32;
+            sweep_end[axis] = body_start.floor() as i32 + 1;
+        }
+
+        let mut allowed = amount;
+        for x in sweep_start.x..sweep_end.x {
+            for y in sweep_start.y..sweep_end.y {
+                for z in sweep_start.z..sweep_end.z {
+                    let coords = BlockCoords { x, y, z };
+                    let is_collidable = world
+                        .get_block(coords)
+                        .is_none_or(|block| block.is_collidable());
+                    if !is_collidable {
+                        continue;
+                    }
+
+                    let block_start = coords[axis] as f32;
+                    let block_end = block_start + 1.;
+                    if amount > 0. && block_start >= body_end - Self::COLLISION_MARGIN {
+                        allowed = allowed.min(block_start - body_end - Self::COLLISION_MARGIN);
+                    } else if amount < 0. && block_end <= body_start + Self::COLLISION_MARGIN {
+                        allowed = allowed.max(block_end - body_start + Self::COLLISION_MARGIN);
+                    }
+                }
+            }
+        }
+
+        // Never push the body backwards, even if it is within the margin
+        let allowed = if amount > 0. {
+            allowed.max(0.)
+        } else {
+            allowed.min(0.)
+        };
+
+        self.position[axis] += allowed;
+        allowed
+    }
+}
@@ -1,8 +1,12 @@
+pub mod chunk_buffers;
 pub mod frustrum;
 pub mod line_renderer;
+pub mod post_processing;
+pub mod shadows;
 pub mod sky_renderer;
 pub mod texture;
 pub mod uniform;
+pub mod world_graphics;
 pub mod world_renderer;
 
 use crate::context::Context;
@@ -0,0 +1,443 @@
+use std::{ops::Range, rc::Rc};
+
+use cgmath::Vector4;
+use mycraft_world::mesh::Vertex;
+use wgpu::util::DrawIndexedIndirect;
+
+use crate::{context::Context, utils::as_bytes_slice};
+
+use super::Bindable;
+
+/// Sub-allocates ranges of elements in one large buffer, which doubles in size when full.
+/// The content is copied to the new buffer, so the ranges stay valid.
+pub struct BufferPool {
+    context: Rc<Context>,
+    label: String,
+    usage: wgpu::BufferUsages,
+    element_size: u64,
+    buffer: wgpu::Buffer,
+    capacity: u32,
+    /// Sorted by start, merged with their neighbors when freed
+    free_ranges: Vec<Range<u32>>,
+}
+
+impl BufferPool {
+    pub fn new(
+        context: Rc<Context>,
+        label: &str,
+        usage: wgpu::BufferUsages,
+        element_size: u64,
+        capacity: u32,
+    ) -> Self {
+        let usage = usage | wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::COPY_DST;
+        let buffer = create_buffer(&context, label, usage, element_size * capacity as u64);
+
+        BufferPool {
+            context,
+            label: label.to_string(),
+            usage,
+            element_size,
+            buffer,
+            capacity,
+            free_ranges: std::iter::once(0..capacity).collect(),
+        }
+    }
+
+    /// Returns the range of elements holding the data. Returns `true` as well if the
+    /// buffer was replaced by a larger one. Returns `None` if the buffer would outgrow
+    /// the limits of the device.
+    pub fn allocate<T>(&mut self, data: &[T]) -> Option<(Range<u32>, bool)> {
+        debug_assert_eq!(std::mem::size_of::<T>() as u64, self.element_size);
+
+        let len = data.len() as u32;
+        if len == 0 {
+            return Some((0..0, false));
+        }
+
+        let mut grown = false;
+        let index = loop {
+            match self
+                .free_ranges
+                .iter()
+                .position(|range| range.len() as u32 >= len)
+            {
+                Some(index) => break index,
+                None => {
+                    self.grow(len)?;
+                    grown = true;
+                }
+            }
+        };
+
+        let free_range = &mut self.free_ranges[index];
+        let range = free_range.start..free_range.start + len;
+        free_range.start += len;
+        if free_range.start == free_range.end {
+            self.free_ranges.remove(index);
+        }
+
+        self.write(range.clone(), data);
+        Some((range, grown))
+    }
+
+    pub fn free(&mut self, range: Range<u32>) {
+        if range.is_empty() {
+            return;
+        }
+
+        let index = self
+            .free_ranges
+            .partition_point(|free_range| free_range.start < range.start);
+        self.free_ranges.insert(index, range);
+
+        if index + 1 < self.free_ranges.len()
+            && self.free_ranges[index].end == self.free_ranges[index + 1].start
+        {
+            let next = self.free_ranges.remove(index + 1);
+            self.free_ranges[index].end = next.end;
+        }
+        if index > 0 && self.free_ranges[index - 1].end == self.free_ranges[index].start {
+            let current = self.free_ranges.remove(index);
+            self.free_ranges[index - 1].end = current.end;
+        }
+    }
+
+    /// Overwrites the elements of an allocated range.
+    pub fn write<T>(&self, range: Range<u32>, data: &[T]) {
+        assert_eq!(range.len(), data.len());
+        self.context.queue.write_buffer(
+            &self.buffer,
+            range.start as u64 * self.element_size,
+            as_bytes_slice(data),
+        );
+    }
+
+    pub fn get_buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+
+    /// Doubles the capacity until the new free space at the end fits the elements.
+    /// Returns `None` without growing if the buffer would be too large for the device.
+    fn grow(&mut self, len: u32) -> Option<()> {
+        let tail_start = match self.free_ranges.last() {
+            Some(last) if last.end == self.capacity => last.start,
+            _ => self.capacity,
+        };
+        let mut capacity = self.capacity.checked_mul(2)?;
+        while capacity - tail_start < len {
+            capacity = capacity.checked_mul(2)?;
+        }
+
+        let size = self.element_size * capacity as u64;
+        if size > self.max_size() {
+            return None;
+        }
+        let buffer = create_buffer(&self.context, &self.label, self.usage, size);
+
+        let mut encoder =
+            self.context
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some(&format!("{} Growth Encoder", self.label)),
+                });
+        encoder.copy_buffer_to_buffer(
+            &self.buffer,
+            0,
+            &buffer,
+            0,
+            self.element_size * self.capacity as u64,
+        );
+        self.context.queue.submit(std::iter::once(encoder.finish()));
+
+        match self.free_ranges.last_mut() {
+            Some(last) if last.end == self.capacity => last.end = capacity,
+            _ => self.free_ranges.push(self.capacity..capacity),
+        }
+        self.buffer = buffer;
+        self.capacity = capacity;
+        Some(())
+    }
+
+    fn max_size(&self) -> u64 {
+        let limits = self.context.device.limits();
+        if self.usage.contains(wgpu::BufferUsages::STORAGE) {
+            // The whole buffer is bound
+            limits
+                .max_buffer_size
+                .min(limits.max_storage_buffer_binding_size as u64)
+        } else {
+            limits.max_buffer_size
+        }
+    }
+}
+
+fn create_buffer(
+    context: &Context,
+    label: &str,
+    usage: wgpu::BufferUsages,
+    size: u64,
+) -> wgpu::Buffer {
+    context.device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some(label),
+        size,
+        usage,
+        mapped_at_creation: false,
+    })
+}
+
+/// Where the meshes of a chunk are in the pools of `ChunkBuffers`.
+#[derive(Clone)]
+pub struct ChunkAllocation {
+    pub solid_vertices: Range<u32>,
+    pub solid_indices: Range<u32>,
+    pub water_vertices: Range<u32>,
+    pub water_indices: Range<u32>,
+    /// Index of the offset of the chunk, read by the shaders as the instance index
+    pub slot: u32,
+}
+
+impl ChunkAllocation {
+    pub fn solid_draw(&self) -> DrawIndexedIndirect {
+        self.draw(&self.solid_vertices, &self.solid_indices)
+    }
+
+    pub fn water_draw(&self) -> DrawIndexedIndirect {
+        self.draw(&self.water_vertices, &self.water_indices)
+    }
+
+    fn draw(&self, vertices: &Range<u32>, indices: &Range<u32>) -> DrawIndexedIndirect {
+        DrawIndexedIndirect {
+            vertex_count: indices.len() as u32,
+            instance_count: 1,
+            base_index: indices.start,
+            vertex_offset: vertices.start as i32,
+            base_instance: self.slot,
+        }
+    }
+}
+
+/// The meshes and the offsets of all the chunks, in buffers shared by all of them so that
+/// they are bound once per pass.
+pub struct ChunkBuffers {
+    context: Rc<Context>,
+    vertices: BufferPool,
+    indices: BufferPool,
+    /// One per chunk, with the chunk offset in the first three components
+    offsets: BufferPool,
+    bind_group: wgpu::BindGroup,
+}
+
+impl ChunkBuffers {
+    const INITIAL_VERTEX_CAPACITY: u32 = 1 << 20;
+    const INITIAL_INDEX_CAPACITY: u32 = 3 << 19;
+    const INITIAL_CHUNK_CAPACITY: u32 = 1 << 10;
+
+    pub fn new(context: Rc<Context>) -> Self {
+        let vertices = BufferPool::new(
+            context.clone(),
+            "Chunk Vertices",
+            wgpu::BufferUsages::VERTEX,
+            std::mem::size_of::<Vertex>() as u64,
+            Self::INITIAL_VERTEX_CAPACITY,
+        );
+        let indices = BufferPool::new(
+            context.clone(),
+            "Chunk Indices",
+            wgpu::BufferUsages::INDEX,
+            std::mem::size_of::<u32>() as u64,
+            Self::INITIAL_INDEX_CAPACITY,
+        );
+        let offsets = BufferPool::new(
+            context.clone(),
+            "Chunk Offsets",
+            wgpu::BufferUsages::STORAGE,
+            std::mem::size_of::<Vector4<f32>>() as u64,
+            Self::INITIAL_CHUNK_CAPACITY,
+        );
+        let bind_group = create_bind_group(&context, &offsets);
+
+        ChunkBuffers {
+            context,
+            vertices,
+            indices,
+            offsets,
+            bind_group,
+        }
+    }
+
+    pub fn allocate(
+        &mut self,
+        solid_vertices: &[Vertex],
+        solid_indices: &[u32],
+        water_vertices: &[Vertex],
+        water_indices: &[u32],
+        offset: Vector4<f32>,
+    ) -> Option<ChunkAllocation> {
+        let (slot, offsets_grown) = self.offsets.allocate(std::slice::from_ref(&offset))?;
+        // The bind group holds the previous buffer
+        if offsets_grown {
+            self.bind_group = create_bind_group(&self.context, &self.offsets);
+        }
+
+        // Empty ranges until allocated, so that a partial allocation can be freed
+        let mut allocation = ChunkAllocation {
+            solid_vertices: 0..0,
+            solid_indices: 0..0,
+            water_vertices: 0..0,
+            water_indices: 0..0,
+            slot: slot.start,
+        };
+        let allocated = (|| {
+            allocation.solid_vertices = self.vertices.allocate(solid_vertices)?.0;
+            allocation.solid_indices = self.indices.allocate(solid_indices)?.0;
+            allocation.water_vertices = self.vertices.allocate(water_vertices)?.0;
+            allocation.water_indices = self.indices.allocate(water_indices)?.0;
+            Some(())
+        })();
+
+        if allocated.is_none() {
+            self.free(allocation);
+            return None;
+        }
+        Some(allocation)
+    }
+
+    pub fn free(&mut self, allocation: ChunkAllocation) {
+        self.vertices.free(allocation.solid_vertices);
+        self.indices.free(allocation.solid_indices);
+        self.vertices.free(allocation.water_vertices);
+        self.indices.free(allocation.water_indices);
+        self.offsets.free(allocation.slot..allocation.slot + 1);
+    }
+
+    pub fn write_indices(&self, range: Range<u32>, indices: &[u32]) {
+        self.indices.write(range, indices);
+    }
+
+    /// Binds the shared vertices, indices and chunk offsets for the draw lists.
+    pub fn bind<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, offsets_group: u32) {
+        render_pass.set_bind_group(offsets_group, &self.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertices.get_buffer().slice(..));
+        render_pass.set_index_buffer(
+            self.indices.get_buffer().slice(..),
+            wgpu::IndexFormat::Uint32,
+        );
+    }
+}
+
+impl Bindable for ChunkBuffers {
+    fn create_bind_group_layout(context: &Context) -> wgpu::BindGroupLayout {
+        context
+            .device
+            .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Chunk Offsets Bind Group Layout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            })
+    }
+
+    fn get_bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+}
+
+fn create_bind_group(context: &Context, offsets: &BufferPool) -> wgpu::BindGroup {
+    context
+        .device
+        .create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Chunk Offsets Bind Group"),
+            layout: &ChunkBuffers::create_bind_group_layout(context),
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: offsets.get_buffer().as_entire_binding(),
+            }],
+        })
+}
+
+/// Draw commands of the chunks for one pass. They are sent at once with an indirect
+/// multi-draw when the device supports it, or issued one by one otherwise.
+pub struct DrawList {
+    context: Rc<Context>,
+    label: String,
+    commands: Vec<DrawIndexedIndirect>,
+    /// `None` without indirect multi-draws
+    indirect_buffer: Option<wgpu::Buffer>,
+    capacity: usize,
+}
+
+impl DrawList {
+    pub fn new(context: Rc<Context>, label: &str) -> Self {
+        let indirect_buffer = context
+            .supports_multi_draw_indirect()
+            .then(|| create_indirect_buffer(&context, label, 0));
+
+        DrawList {
+            context,
+            label: label.to_string(),
+            commands: Vec::new(),
+            indirect_buffer,
+            capacity: 0,
+        }
+    }
+
+    /// Replaces the commands, the empty meshes are left out.
+    pub fn update(&mut self, commands: impl Iterator<Item = DrawIndexedIndirect>) {
+        self.commands.clear();
+        self.commands
+            .extend(commands.filter(|command| command.vertex_count > 0));
+
+        let Some(indirect_buffer) = &mut self.indirect_buffer else {
+            return;
+        };
+        if self.commands.len() > self.capacity {
+            self.capacity = self.commands.len().next_power_of_two();
+            *indirect_buffer = create_indirect_buffer(&self.context, &self.label, self.capacity);
+        }
+        self.context
+            .queue
+            .write_buffer(indirect_buffer, 0, as_bytes_slice(&self.commands));
+    }
+
+    /// Draws the meshes, after `ChunkBuffers::bind`.
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        if self.commands.is_empty() {
+            return;
+        }
+
+        match &self.indirect_buffer {
+            Some(indirect_buffer) => {
+                render_pass.multi_draw_indexed_indirect(
+                    indirect_buffer,
+                    0,
+                    self.commands.len() as u32,
+                );
+            }
+            None => {
+                for command in &self.commands {
+                    render_pass.draw_indexed(
+                        command.base_index..command.base_index + command.vertex_count,
+                        command.vertex_offset,
+                        command.base_instance..command.base_instance + 1,
+                    );
+                }
+            }
+        }
+    }
+}
+
+fn create_indirect_buffer(context: &Context, label: &str, capacity: usize) -> wgpu::Buffer {
+    create_buffer(
+        context,
+        &format!("{} Indirect Buffer", label),
+        wgpu::BufferUsages::INDIRECT | wgpu::BufferUsages::COPY_DST,
+        (capacity.max(1) * std::mem::size_of::<DrawIndexedIndirect>()) as u64,
+    )
+}
@@ -1,6 +1,6 @@
 use cgmath::{InnerSpace, Matrix4, Vector3};
 
-use crate::utils::aabb::Aabb;
+use mycraft_world::aabb::Aabb;
 
 struct Plane {
     distance_from_origin: f32,
@@ -3,7 +3,11 @@ use std::rc::Rc;
 use cgmath::Vector3;
 use wgpu::util::DeviceExt;
 
-use super::{texture::DepthBuffer, uniform::Uniform, Bindable, RenderTargetWithDepth};
+use super::{
+    texture::{ColorBuffer, DepthBuffer},
+    uniform::Uniform,
+    Bindable, RenderTargetWithDepth,
+};
 use crate::{camera::Camera, context::Context, utils::as_bytes_slice};
 
 #[repr(C, align(16))]
@@ -87,7 +91,7 @@ impl LineRenderer {
                         module: &shader,
                         entry_point: "fs_main",
                         targets: &[Some(wgpu::ColorTargetState {
-                            format: context.surface_config.borrow().format,
+                            format: ColorBuffer::HDR_FORMAT,
                             blend: None,
                             write_mask: wgpu::ColorWrites::ALL,
                         })],
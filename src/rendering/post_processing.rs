@@ -0,0 +1,567 @@
+use std::rc::Rc;
+
+use cgmath::Vector2;
+
+use crate::{camera::Camera, context::Context, sky::Sky};
+
+use super::{
+    texture::{ColorBuffer, DepthBuffer},
+    uniform::Uniform,
+    Bindable,
+};
+
+/// Passes of the chain and their parameters, changed at runtime from the debug window.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct PostProcessingSettings {
+    /// Glow around the bright parts of the scene, like the torches and the sun on the water
+    pub bloom: bool,
+    /// Brightness above which the scene glows
+    pub bloom_threshold: f32,
+    pub bloom_intensity: f32,
+    /// Maps the HDR colors to the screen with a filmic curve, clamps them otherwise
+    pub tone_mapping: bool,
+    pub exposure: f32,
+    pub fxaa: bool,
+    /// Darkens the corners of the screen
+    pub vignette: bool,
+    pub vignette_strength: f32,
+}
+
+impl Default for PostProcessingSettings {
+    fn default() -> Self {
+        PostProcessingSettings {
+            bloom: true,
+            bloom_threshold: 0.9,
+            bloom_intensity: 0.4,
+            tone_mapping: true,
+            exposure: 1.2,
+            fxaa: true,
+            vignette: false,
+            vignette_strength: 0.3,
+        }
+    }
+}
+
+#[repr(C, align(16))]
+struct PostProcessingUniform {
+    bloom_threshold: f32,
+    /// 0 when the bloom is disabled
+    bloom_intensity: f32,
+    /// 1 when the tone mapping is enabled, 0 otherwise
+    tone_mapping: f32,
+    exposure: f32,
+    vignette_strength: f32,
+}
+
+/// Intermediate target of the chain, with the bind group reading it in the next pass.
+struct PassBuffer {
+    buffer: ColorBuffer,
+    bind_group: wgpu::BindGroup,
+}
+
+/// Takes the HDR scene through a chain of full-screen passes and blits the result to the
+/// screen. The underwater pass runs when the camera is in a fluid, the others are chosen
+/// by the settings.
+pub struct PostProcessing {
+    context: Rc<Context>,
+    pub settings: PostProcessingSettings,
+    underwater: bool,
+
+    uniform: Uniform<PostProcessingUniform>,
+    sampler: wgpu::Sampler,
+    layout: wgpu::BindGroupLayout,
+    bloom_layout: wgpu::BindGroupLayout,
+
+    /// Reads the scene rendered by the other renderers
+    scene_bind_group: wgpu::BindGroup,
+    /// Output of the underwater pass
+    hdr: PassBuffer,
+    /// At half the resolution, blurred back and forth between the two
+    bloom: [PassBuffer; 2],
+    /// The blurred bloom, for the tone mapping pass
+    bloom_bind_group: wgpu::BindGroup,
+    /// Tone mapped to the format of the screen, passed back and forth between the two
+    ldr: [PassBuffer; 2],
+
+    underwater_pipeline: wgpu::RenderPipeline,
+    bloom_extract_pipeline: wgpu::RenderPipeline,
+    bloom_horizontal_pipeline: wgpu::RenderPipeline,
+    bloom_vertical_pipeline: wgpu::RenderPipeline,
+    tone_mapping_pipeline: wgpu::RenderPipeline,
+    fxaa_pipeline: wgpu::RenderPipeline,
+    vignette_pipeline: wgpu::RenderPipeline,
+    blit_pipeline: wgpu::RenderPipeline,
+}
+
+impl PostProcessing {
+    pub fn new(context: Rc<Context>, scene: &ColorBuffer, depth_buffer: &DepthBuffer) -> Self {
+        let settings = PostProcessingSettings::default();
+        let uniform = Uniform::new(
+            context.clone(),
+            "Post Processing Uniform",
+            settings.to_uniform(),
+        );
+        let sampler = context.device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Post Processing Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let layout = create_bind_group_layout(&context);
+        let bloom_layout = create_bloom_bind_group_layout(&context);
+
+        let surface_format = context.surface_config.borrow().format;
+        let size = scene.size();
+        let bloom_size = bloom_size(size);
+
+        let new_buffer = |label: &str, format: wgpu::TextureFormat, size: Vector2<u32>| {
+            let buffer = ColorBuffer::new(context.clone(), label, format, size);
+            let bind_group =
+                create_bind_group(&context, &layout, &uniform, &buffer, &sampler, depth_buffer);
+            PassBuffer { buffer, bind_group }
+        };
+        let hdr = new_buffer("Post Processing HDR Buffer", ColorBuffer::HDR_FORMAT, size);
+        let bloom = [
+            new_buffer("Bloom Buffer 0", ColorBuffer::HDR_FORMAT, bloom_size),
+            new_buffer("Bloom Buffer 1", ColorBuffer::HDR_FORMAT, bloom_size),
+        ];
+        let ldr = [
+            new_buffer("Post Processing LDR Buffer 0", surface_format, size),
+            new_buffer("Post Processing LDR Buffer 1", surface_format, size),
+        ];
+
+        let scene_bind_group =
+            create_bind_group(&context, &layout, &uniform, scene, &sampler, depth_buffer);
+        let bloom_bind_group =
+            create_bloom_bind_group(&context, &bloom_layout, &bloom[0].buffer, &sampler);
+
+        let hdr_pipeline = |entry_point: &str| {
+            create_pipeline(&context, entry_point, ColorBuffer::HDR_FORMAT, false)
+        };
+        let ldr_pipeline =
+            |entry_point: &str| create_pipeline(&context, entry_point, surface_format, false);
+
+        PostProcessing {
+            underwater_pipeline: hdr_pipeline("fs_underwater"),
+            bloom_extract_pipeline: hdr_pipeline("fs_bloom_extract"),
+            bloom_horizontal_pipeline: hdr_pipeline("fs_bloom_horizontal"),
+            bloom_vertical_pipeline: hdr_pipeline("fs_bloom_vertical"),
+            tone_mapping_pipeline: create_pipeline(
+                &context,
+                "fs_tone_mapping",
+                surface_format,
+                true,
+            ),
+            fxaa_pipeline: ldr_pipeline("fs_fxaa"),
+            vignette_pipeline: ldr_pipeline("fs_vignette"),
+            blit_pipeline: ldr_pipeline("fs_blit"),
+
+            context,
+            settings,
+            underwater: false,
+
+            uniform,
+            sampler,
+            layout,
+            bloom_layout,
+
+            scene_bind_group,
+            hdr,
+            bloom,
+            bloom_bind_group,
+            ldr,
+        }
+    }
+
+    /// Follows the size of the scene, after it and the depth buffer are resized.
+    pub fn resize(&mut self, scene: &ColorBuffer, depth_buffer: &DepthBuffer) {
+        let size = scene.size();
+        let bloom_size = bloom_size(size);
+
+        self.scene_bind_group = create_bind_group(
+            &self.context,
+            &self.layout,
+            &self.uniform,
+            scene,
+            &self.sampler,
+            depth_buffer,
+        );
+        for (pass_buffer, size) in std::iter::once((&mut self.hdr, size))
+            .chain(self.bloom.iter_mut().map(|bloom| (bloom, bloom_size)))
+            .chain(self.ldr.iter_mut().map(|ldr| (ldr, size)))
+        {
+            pass_buffer.buffer.resize(size);
+            pass_buffer.bind_group = create_bind_group(
+                &self.context,
+                &self.layout,
+                &self.uniform,
+                &pass_buffer.buffer,
+                &self.sampler,
+                depth_buffer,
+            );
+        }
+        self.bloom_bind_group = create_bloom_bind_group(
+            &self.context,
+            &self.bloom_layout,
+            &self.bloom[0].buffer,
+            &self.sampler,
+        );
+    }
+
+    pub fn set_underwater(&mut self, underwater: bool) {
+        self.underwater = underwater;
+    }
+
+    /// Runs the enabled passes over the scene, the last one drawing to the target.
+    pub fn draw(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        target: &wgpu::TextureView,
+        camera: &Camera,
+        sky: &Sky,
+    ) {
+        puffin::profile_function!();
+
+        self.uniform.write(self.settings.to_uniform());
+
+        let pass = |encoder: &mut wgpu::CommandEncoder,
+                    pipeline: &wgpu::RenderPipeline,
+                    input: &wgpu::BindGroup,
+                    output: &wgpu::TextureView| {
+            self.draw_pass(encoder, pipeline, input, None, output, camera, sky);
+        };
+
+        let mut hdr_input = &self.scene_bind_group;
+        if self.underwater {
+            pass(
+                encoder,
+                &self.underwater_pipeline,
+                hdr_input,
+                self.hdr.buffer.get_texture_view(),
+            );
+            hdr_input = &self.hdr.bind_group;
+        }
+
+        if self.settings.bloom {
+            let [bloom_0, bloom_1] = &self.bloom;
+            pass(
+                encoder,
+                &self.bloom_extract_pipeline,
+                hdr_input,
+                bloom_0.buffer.get_texture_view(),
+            );
+            pass(
+                encoder,
+                &self.bloom_horizontal_pipeline,
+                &bloom_0.bind_group,
+                bloom_1.buffer.get_texture_view(),
+            );
+            pass(
+                encoder,
+                &self.bloom_vertical_pipeline,
+                &bloom_1.bind_group,
+                bloom_0.buffer.get_texture_view(),
+            );
+        }
+
+        // From here on, the chain goes back and forth between the two LDR buffers
+        let [ldr_0, ldr_1] = &self.ldr;
+        let (mut current, mut next) = (ldr_0, ldr_1);
+        self.draw_pass(
+            encoder,
+            &self.tone_mapping_pipeline,
+            hdr_input,
+            Some(&self.bloom_bind_group),
+            current.buffer.get_texture_view(),
+            camera,
+            sky,
+        );
+
+        let ldr_passes = [
+            (self.settings.fxaa, &self.fxaa_pipeline),
+            (self.settings.vignette, &self.vignette_pipeline),
+        ];
+        for (_, pipeline) in ldr_passes.into_iter().filter(|(enabled, _)| *enabled) {
+            pass(
+                encoder,
+                pipeline,
+                &current.bind_group,
+                next.buffer.get_texture_view(),
+            );
+            std::mem::swap(&mut current, &mut next);
+        }
+
+        pass(encoder, &self.blit_pipeline, &current.bind_group, target);
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn draw_pass(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        pipeline: &wgpu::RenderPipeline,
+        input: &wgpu::BindGroup,
+        bloom: Option<&wgpu::BindGroup>,
+        output: &wgpu::TextureView,
+        camera: &Camera,
+        sky: &Sky,
+    ) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Post Processing Render Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: output,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, camera.get_bind_group(), &[]);
+        render_pass.set_bind_group(1, sky.get_bind_group(), &[]);
+        render_pass.set_bind_group(2, input, &[]);
+        // Not bound to the bloom passes, which write to it
+        if let Some(bloom) = bloom {
+            render_pass.set_bind_group(3, bloom, &[]);
+        }
+        // A single triangle covering the screen, made by the vertex shader
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+impl PostProcessingSettings {
+    fn to_uniform(self) -> PostProcessingUniform {
+        PostProcessingUniform {
+            bloom_threshold: self.bloom_threshold,
+            bloom_intensity: if self.bloom { self.bloom_intensity } else { 0. },
+            tone_mapping: if self.tone_mapping { 1. } else { 0. },
+            exposure: self.exposure,
+            vignette_strength: self.vignette_strength,
+        }
+    }
+
+    /// Adds the checkboxes and the sliders of the passes to the debug window.
+    pub fn show(&mut self, ui: &mut egui::Ui) {
+        ui.checkbox(&mut self.bloom, "Bloom");
+        ui.add_enabled(
+            self.bloom,
+            egui::Slider::new(&mut self.bloom_threshold, 0.0..=2.0).text("Bloom threshold"),
+        );
+        ui.add_enabled(
+            self.bloom,
+            egui::Slider::new(&mut self.bloom_intensity, 0.0..=2.0).text("Bloom intensity"),
+        );
+
+        ui.checkbox(&mut self.tone_mapping, "Tone mapping");
+        ui.add(egui::Slider::new(&mut self.exposure, 0.1..=4.0).text("Exposure"));
+
+        ui.checkbox(&mut self.fxaa, "FXAA");
+
+        ui.checkbox(&mut self.vignette, "Vignette");
+        ui.add_enabled(
+            self.vignette,
+            egui::Slider::new(&mut self.vignette_strength, 0.0..=1.0).text("Vignette strength"),
+        );
+    }
+}
+
+fn bloom_size(size: Vector2<u32>) -> Vector2<u32> {
+    size.map(|x| (x / 2).max(1))
+}
+
+fn create_bind_group_layout(context: &Context) -> wgpu::BindGroupLayout {
+    context
+        .device
+        .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Post Processing Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Depth,
+                    },
+                    count: None,
+                },
+            ],
+        })
+}
+
+/// The input of a pass, with the settings and the depth of the scene.
+fn create_bind_group(
+    context: &Context,
+    layout: &wgpu::BindGroupLayout,
+    uniform: &Uniform<PostProcessingUniform>,
+    input: &ColorBuffer,
+    sampler: &wgpu::Sampler,
+    depth_buffer: &DepthBuffer,
+) -> wgpu::BindGroup {
+    context
+        .device
+        .create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Post Processing Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform.as_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(input.get_texture_view()),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(depth_buffer.get_texture_view()),
+                },
+            ],
+        })
+}
+
+fn create_bloom_bind_group_layout(context: &Context) -> wgpu::BindGroupLayout {
+    context
+        .device
+        .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Bloom Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        })
+}
+
+fn create_bloom_bind_group(
+    context: &Context,
+    layout: &wgpu::BindGroupLayout,
+    bloom: &ColorBuffer,
+    sampler: &wgpu::Sampler,
+) -> wgpu::BindGroup {
+    context
+        .device
+        .create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Bloom Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(bloom.get_texture_view()),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+            ],
+        })
+}
+
+fn create_pipeline(
+    context: &Context,
+    entry_point: &str,
+    format: wgpu::TextureFormat,
+    reads_bloom: bool,
+) -> wgpu::RenderPipeline {
+    let camera_layout = Camera::create_bind_group_layout(context);
+    let sky_layout = Sky::create_bind_group_layout(context);
+    let input_layout = create_bind_group_layout(context);
+    let bloom_layout = create_bloom_bind_group_layout(context);
+    let bind_group_layouts: &[&wgpu::BindGroupLayout] = if reads_bloom {
+        &[&camera_layout, &sky_layout, &input_layout, &bloom_layout]
+    } else {
+        &[&camera_layout, &sky_layout, &input_layout]
+    };
+
+    let layout = context
+        .device
+        .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Post Processing Render Pipeline Layout"),
+            bind_group_layouts,
+            push_constant_ranges: &[],
+        });
+
+    let shader = context
+        .device
+        .create_shader_module(wgpu::include_wgsl!("post_processing_shader.wgsl"));
+
+    context
+        .device
+        .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(&format!("Post Processing Render Pipeline {}", entry_point)),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point,
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        })
+}
@@ -0,0 +1,213 @@
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+struct Camera {
+    matrix: mat4x4<f32>,
+    inverse_matrix: mat4x4<f32>,
+    position: vec3<f32>,
+}
+
+@group(0) @binding(0)
+var<uniform> camera: Camera;
+
+// A triangle twice the size of the screen, so that it covers all of it
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    var out: VertexOutput;
+    let uv = vec2(f32((index << 1u) & 2u), f32(index & 2u));
+    out.position = vec4(uv.x * 2. - 1., 1. - uv.y * 2., 0., 1.);
+    out.uv = uv;
+    return out;
+}
+
+struct SkyUniform {
+    sun_direction: vec3<f32>,
+    time: f32,
+    sun_light: f32,
+    fog_start: f32,
+    fog_end: f32,
+}
+
+@group(1) @binding(0)
+var<uniform> sky_uniform: SkyUniform;
+
+struct PostProcessing {
+    bloom_threshold: f32,
+    bloom_intensity: f32,
+    tone_mapping: f32,
+    exposure: f32,
+    vignette_strength: f32,
+}
+
+@group(2) @binding(0)
+var<uniform> post_processing: PostProcessing;
+// Output of the previous pass
+@group(2) @binding(1)
+var input_texture: texture_2d<f32>;
+@group(2) @binding(2)
+var input_sampler: sampler;
+@group(2) @binding(3)
+var depth_texture: texture_depth_2d;
+
+// Read by the tone mapping only
+@group(3) @binding(0)
+var bloom_texture: texture_2d<f32>;
+@group(3) @binding(1)
+var bloom_sampler: sampler;
+
+fn sample_input(uv: vec2<f32>) -> vec4<f32> {
+    return textureSampleLevel(input_texture, input_sampler, uv, 0.);
+}
+
+fn input_texel_size() -> vec2<f32> {
+    return 1. / vec2<f32>(textureDimensions(input_texture));
+}
+
+// Same as DAY_LENGTH_SECS, the time of the sky is in days
+const DAY_LENGTH_SECS: f32 = 600.;
+
+const UNDERWATER_TINT: vec3<f32> = vec3<f32>(0.45, 0.7, 0.85);
+const UNDERWATER_FOG_COLOR: vec3<f32> = vec3<f32>(0.05, 0.2, 0.35);
+const UNDERWATER_FOG_DENSITY: f32 = 0.08;
+
+// Distance from the camera to the scene at the point of the screen.
+fn scene_distance(uv: vec2<f32>) -> f32 {
+    let size = vec2<f32>(textureDimensions(depth_texture));
+    let coords = clamp(vec2<i32>(uv * size), vec2(0), vec2<i32>(size) - 1);
+    let depth = textureLoad(depth_texture, coords, 0);
+
+    let position = camera.inverse_matrix * vec4(uv.x * 2. - 1., 1. - uv.y * 2., depth, 1.);
+    return length(position.xyz / position.w);
+}
+
+// Tinted, fogged and distorted, the camera is in a fluid.
+@fragment
+fn fs_underwater(in: VertexOutput) -> @location(0) vec4<f32> {
+    // Waves slowly bending the view
+    let seconds = sky_uniform.time * DAY_LENGTH_SECS;
+    let distortion = vec2(
+        sin(in.uv.y * 30. + seconds * 2.),
+        cos(in.uv.x * 24. + seconds * 1.7),
+    ) * 0.004;
+    let uv = in.uv + distortion;
+
+    let color = sample_input(uv).rgb * UNDERWATER_TINT;
+
+    // The sky is at the far plane, hidden by the fog
+    let fog = 1. - exp(-scene_distance(uv) * UNDERWATER_FOG_DENSITY);
+    let fog_color = UNDERWATER_FOG_COLOR * sky_uniform.sun_light;
+    return vec4(mix(color, fog_color, fog), 1.);
+}
+
+// Keeps the parts brighter than the threshold, at half the resolution.
+@fragment
+fn fs_bloom_extract(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = sample_input(in.uv).rgb;
+    let brightness = max(color.r, max(color.g, color.b));
+    let contribution = max(brightness - post_processing.bloom_threshold, 0.) / max(brightness, 0.0001);
+    return vec4(color * contribution, 1.);
+}
+
+// Gaussian blur in one direction, the bilinear sampler blends the texels in between.
+fn blur(uv: vec2<f32>, direction: vec2<f32>) -> vec4<f32> {
+    var weights = array<f32, 5>(0.227027, 0.1945946, 0.1216216, 0.054054, 0.016216);
+    let step = direction * input_texel_size() * 1.5;
+
+    var color = sample_input(uv).rgb * weights[0];
+    for (var i = 1; i < 5; i++) {
+        let offset = step * f32(i);
+        color += sample_input(uv + offset).rgb * weights[i];
+        color += sample_input(uv - offset).rgb * weights[i];
+    }
+    return vec4(color, 1.);
+}
+
+@fragment
+fn fs_bloom_horizontal(in: VertexOutput) -> @location(0) vec4<f32> {
+    return blur(in.uv, vec2(1., 0.));
+}
+
+@fragment
+fn fs_bloom_vertical(in: VertexOutput) -> @location(0) vec4<f32> {
+    return blur(in.uv, vec2(0., 1.));
+}
+
+// Fitted ACES filmic curve, from Krzysztof Narkowicz.
+fn aces(color: vec3<f32>) -> vec3<f32> {
+    let a = 2.51;
+    let b = 0.03;
+    let c = 2.43;
+    let d = 0.59;
+    let e = 0.14;
+    return clamp((color * (a * color + b)) / (color * (c * color + d) + e), vec3(0.), vec3(1.));
+}
+
+// From the HDR scene to the range of the screen.
+@fragment
+fn fs_tone_mapping(in: VertexOutput) -> @location(0) vec4<f32> {
+    let bloom = textureSampleLevel(bloom_texture, bloom_sampler, in.uv, 0.).rgb;
+    let color = (sample_input(in.uv).rgb + bloom * post_processing.bloom_intensity) * post_processing.exposure;
+
+    if (post_processing.tone_mapping == 0.) {
+        return vec4(clamp(color, vec3(0.), vec3(1.)), 1.);
+    }
+    return vec4(aces(color), 1.);
+}
+
+// Perceived brightness, roughly in gamma space.
+fn luma(color: vec3<f32>) -> f32 {
+    return sqrt(dot(color, vec3(0.299, 0.587, 0.114)));
+}
+
+// Fast approximate anti-aliasing, blurs the edges along their direction.
+@fragment
+fn fs_fxaa(in: VertexOutput) -> @location(0) vec4<f32> {
+    let texel = input_texel_size();
+    let center = sample_input(in.uv);
+
+    let luma_center = luma(center.rgb);
+    let luma_nw = luma(sample_input(in.uv + vec2(-1., -1.) * texel).rgb);
+    let luma_ne = luma(sample_input(in.uv + vec2(1., -1.) * texel).rgb);
+    let luma_sw = luma(sample_input(in.uv + vec2(-1., 1.) * texel).rgb);
+    let luma_se = luma(sample_input(in.uv + vec2(1., 1.) * texel).rgb);
+
+    let luma_min = min(luma_center, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+    let luma_max = max(luma_center, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+    if (luma_max - luma_min < max(0.0312, luma_max * 0.125)) {
+        return center;
+    }
+
+    var direction = vec2(
+        (luma_sw + luma_se) - (luma_nw + luma_ne),
+        (luma_nw + luma_sw) - (luma_ne + luma_se),
+    );
+    let direction_reduce = max((luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * 0.125, 1. / 128.);
+    let scale = 1. / (min(abs(direction.x), abs(direction.y)) + direction_reduce);
+    direction = clamp(direction * scale, vec2(-8.), vec2(8.)) * texel;
+
+    let close = 0.5 * (sample_input(in.uv - direction / 6.) + sample_input(in.uv + direction / 6.));
+    let far = close * 0.5 + 0.25 * (sample_input(in.uv - direction * 0.5) + sample_input(in.uv + direction * 0.5));
+
+    // The far samples went past the edge
+    let luma_far = luma(far.rgb);
+    if (luma_far < luma_min || luma_far > luma_max) {
+        return close;
+    }
+    return far;
+}
+
+@fragment
+fn fs_vignette(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = sample_input(in.uv);
+    let distance = length(in.uv - 0.5) * 2. / sqrt(2.);
+    let darkening = post_processing.vignette_strength * smoothstep(0.4, 1., distance);
+    return vec4(color.rgb * (1. - darkening), color.a);
+}
+
+// Copies the result of the chain to the screen.
+@fragment
+fn fs_blit(in: VertexOutput) -> @location(0) vec4<f32> {
+    return sample_input(in.uv);
+}
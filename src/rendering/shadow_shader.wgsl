@@ -0,0 +1,50 @@
+struct VertexInput {
+    @location(0) val: vec3<u32>,
+}
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+    @location(1) @interpolate(flat) texture_id: u32,
+}
+
+@group(0) @binding(0)
+var<uniform> light_matrix: mat4x4<f32>;
+
+@group(1) @binding(0)
+var block_textures: texture_2d_array<f32>;
+@group(1) @binding(1)
+var block_sampler: sampler;
+
+// Offset of each chunk, the draw of a chunk starts at its instance index
+@group(2) @binding(0)
+var<storage, read> chunk_offsets: array<vec4<f32>>;
+
+@vertex
+fn vs_main(in: VertexInput, @builtin(instance_index) chunk: u32) -> VertexOutput {
+    var out: VertexOutput;
+    let chunk_offset = chunk_offsets[chunk].xyz;
+
+    let position_x   = (in.val.x) & 0x1FFu;
+    let position_y   = (in.val.x >> 9u) & 0x1FFu;
+    let position_z   = (in.val.x >> 18u) & 0x1FFu;
+    let tex_coords_x = (in.val.y) & 0x1FFu;
+    let tex_coords_y = (in.val.y >> 9u) & 0x1FFu;
+    let texture_id   = (in.val.z) & 0xFFFFu;
+
+    let position = vec3(f32(position_x), f32(position_y), f32(position_z)) / 16.;
+
+    out.position = light_matrix * vec4<f32>(chunk_offset + position, 1.0);
+    out.tex_coords = vec2(f32(tex_coords_x), f32(tex_coords_y)) / 16.;
+    out.texture_id = texture_id;
+    return out;
+}
+
+// Only the depth is written, the cutouts of the leaves and the flowers let the light through
+@fragment
+fn fs_main(in: VertexOutput) {
+    let alpha = textureSample(block_textures, block_sampler, in.tex_coords, in.texture_id).a;
+    if (alpha < 0.5) {
+        discard;
+    }
+}
@@ -0,0 +1,307 @@
+use std::rc::Rc;
+
+use cgmath::{EuclideanSpace, Matrix4, Point3, Transform, Vector3, Vector4, Zero};
+
+use crate::{camera::Camera, consts::*, context::Context};
+
+use super::{
+    chunk_buffers::{ChunkBuffers, DrawList},
+    frustrum::Frustrum,
+    texture::TextureArray,
+    uniform::Uniform,
+    world_graphics::WorldGraphics,
+    world_renderer::VERTEX_BUFFER_LAYOUT,
+    Bindable,
+};
+
+/// Maps the depth of the OpenGL projections of cgmath from -1..1 to the 0..1 of wgpu.
+#[rustfmt::skip]
+const OPENGL_TO_WGPU_MATRIX: Matrix4<f32> = Matrix4::new(
+    1., 0., 0.,  0.,
+    0., 1., 0.,  0.,
+    0., 0., 0.5, 0.,
+    0., 0., 0.5, 1.,
+);
+
+/// Read by the world shaders to find the cascade of a point and its depth seen from the sun.
+#[repr(C)]
+pub struct ShadowUniform {
+    matrices: [Matrix4<f32>; SHADOW_CASCADE_COUNT],
+    /// Distances from the camera at which the cascades end
+    cascade_ends: Vector4<f32>,
+    /// From 0 when the sun is below the horizon to 1 when it is high enough
+    strength: f32,
+    _padding: [f32; 3],
+}
+
+/// Depth of the terrain seen from the sun, in cascades covering larger and larger parts
+/// of the view with the same resolution, so that the shadows are sharp close to the camera.
+pub struct ShadowMap {
+    uniform: Uniform<ShadowUniform>,
+    /// Matrix of each cascade alone, for its own render pass
+    cascade_uniforms: Vec<Uniform<Matrix4<f32>>>,
+    cascade_frustrums: Vec<Frustrum>,
+    cascade_draws: Vec<DrawList>,
+    cascade_views: Vec<wgpu::TextureView>,
+    array_view: wgpu::TextureView,
+    sampler: wgpu::Sampler,
+    pipeline: wgpu::RenderPipeline,
+    blocks_texture: Rc<TextureArray>,
+    strength: f32,
+}
+
+impl ShadowMap {
+    pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+    pub fn new(context: Rc<Context>, blocks_texture: Rc<TextureArray>) -> Self {
+        let texture = context.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Shadow Map"),
+            size: wgpu::Extent3d {
+                width: SHADOW_MAP_SIZE,
+                height: SHADOW_MAP_SIZE,
+                depth_or_array_layers: SHADOW_CASCADE_COUNT as u32,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[Self::FORMAT],
+        });
+
+        let cascade_views = (0..SHADOW_CASCADE_COUNT as u32)
+            .map(|layer| {
+                texture.create_view(&wgpu::TextureViewDescriptor {
+                    label: Some(&format!("Shadow Map Cascade {}", layer)),
+                    dimension: Some(wgpu::TextureViewDimension::D2),
+                    base_array_layer: layer,
+                    array_layer_count: Some(1),
+                    ..Default::default()
+                })
+            })
+            .collect();
+        let array_view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("Shadow Map Cascades"),
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+
+        // Compares with the depth of the four closest texels and blends the results
+        let sampler = context.device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Shadow Map Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            compare: Some(wgpu::CompareFunction::LessEqual),
+            ..Default::default()
+        });
+
+        let uniform = Uniform::new(
+            context.clone(),
+            "Shadow Uniform",
+            ShadowUniform {
+                matrices: [Matrix4::zero(); SHADOW_CASCADE_COUNT],
+                cascade_ends: Vector4::zero(),
+                strength: 0.,
+                _padding: [0.; 3],
+            },
+        );
+        let cascade_uniforms = (0..SHADOW_CASCADE_COUNT)
+            .map(|i| {
+                Uniform::new(
+                    context.clone(),
+                    &format!("Shadow Cascade {} Matrix", i),
+                    Matrix4::zero(),
+                )
+            })
+            .collect();
+        let cascade_draws = (0..SHADOW_CASCADE_COUNT)
+            .map(|i| DrawList::new(context.clone(), &format!("Shadow Cascade {} Draws", i)))
+            .collect();
+
+        ShadowMap {
+            uniform,
+            cascade_uniforms,
+            cascade_frustrums: vec![],
+            cascade_draws,
+            cascade_views,
+            array_view,
+            sampler,
+            pipeline: create_shadow_pipeline(&context),
+            blocks_texture,
+            strength: 0.,
+        }
+    }
+
+    /// Fits the cascades around the view of the camera, as seen from the sun.
+    pub fn update(&mut self, camera: &Camera, sun_direction: Vector3<f32>) {
+        puffin::profile_function!();
+
+        // The shadows fade in as the sun rises and out as it sets
+        self.strength = (sun_direction.y / SHADOW_FADE_SUN_HEIGHT).clamp(0., 1.);
+
+        // Rotation only, so that the cascades can be aligned to the texels in light space
+        let light_view =
+            Matrix4::look_to_rh(Point3::new(0., 0., 0.), -sun_direction, Vector3::unit_x());
+
+        let mut matrices = [Matrix4::zero(); SHADOW_CASCADE_COUNT];
+        let mut cascade_ends = Vector4::zero();
+        self.cascade_frustrums.clear();
+        let mut near = 0.;
+        for (i, &far) in SHADOW_CASCADE_ENDS.iter().enumerate() {
+            let (center, radius) = camera.get_slice_bounds(near, far);
+
+            // Moving by whole texels keeps the edges of the shadows from shimmering
+            let texel_size = 2. * radius / SHADOW_MAP_SIZE as f32;
+            let center = light_view.transform_point(Point3::from_vec(center));
+            let x = (center.x / texel_size).floor() * texel_size;
+            let y = (center.y / texel_size).floor() * texel_size;
+
+            // The blocks between the sun and the cascade cast shadows into it
+            let projection = cgmath::ortho(
+                x - radius,
+                x + radius,
+                y - radius,
+                y + radius,
+                -center.z - radius - SHADOW_CASTER_DISTANCE,
+                -center.z + radius,
+            );
+            let matrix = projection * light_view;
+
+            self.cascade_frustrums.push(Frustrum::new(matrix));
+            matrices[i] = OPENGL_TO_WGPU_MATRIX * matrix;
+            cascade_ends[i] = far;
+            self.cascade_uniforms[i].write(matrices[i]);
+            near = far;
+        }
+
+        self.uniform.write(ShadowUniform {
+            matrices,
+            cascade_ends,
+            strength: self.strength,
+            _padding: [0.; 3],
+        });
+    }
+
+    /// Renders the depth of the solid blocks of each cascade. Nothing is drawn at night,
+    /// the shaders ignore the shadow map then.
+    pub fn draw(&mut self, encoder: &mut wgpu::CommandEncoder, world_graphics: &WorldGraphics) {
+        puffin::profile_function!();
+
+        if self.strength == 0. {
+            return;
+        }
+
+        for (draws, frustrum) in self.cascade_draws.iter_mut().zip(&self.cascade_frustrums) {
+            draws.update(
+                world_graphics
+                    .chunks_in_frustrum(frustrum)
+                    .map(|graphics| graphics.allocation.solid_draw()),
+            );
+        }
+
+        let buffers = world_graphics.buffers();
+        for ((view, uniform), draws) in self
+            .cascade_views
+            .iter()
+            .zip(&self.cascade_uniforms)
+            .zip(&self.cascade_draws)
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Shadow Render Pass"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: true,
+                    }),
+                    stencil_ops: None,
+                }),
+            });
+
+            render_pass.set_pipeline(&self.pipeline);
+            render_pass.set_bind_group(0, uniform.get_bind_group(), &[]);
+            render_pass.set_bind_group(1, self.blocks_texture.get_bind_group(), &[]);
+
+            buffers.bind(&mut render_pass, 2);
+            draws.draw(&mut render_pass);
+        }
+    }
+
+    /// For the sky bind group, which the world shaders sample the shadows from.
+    pub fn as_uniform_binding(&self) -> wgpu::BindingResource<'_> {
+        self.uniform.as_binding()
+    }
+
+    pub fn get_view(&self) -> &wgpu::TextureView {
+        &self.array_view
+    }
+
+    pub fn get_sampler(&self) -> &wgpu::Sampler {
+        &self.sampler
+    }
+}
+
+fn create_shadow_pipeline(context: &Context) -> wgpu::RenderPipeline {
+    let bind_group_layouts = &[
+        &Uniform::<Matrix4<f32>>::create_bind_group_layout(context),
+        &TextureArray::create_bind_group_layout(context),
+        &ChunkBuffers::create_bind_group_layout(context),
+    ];
+
+    let layout = context
+        .device
+        .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Shadow Render Pipeline Layout"),
+            bind_group_layouts,
+            push_constant_ranges: &[],
+        });
+
+    let shader = context
+        .device
+        .create_shader_module(wgpu::include_wgsl!("shadow_shader.wgsl"));
+
+    context
+        .device
+        .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Shadow Render Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[VERTEX_BUFFER_LAYOUT],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                // Both sides, the flowers and the torches are flat
+                cull_mode: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: ShadowMap::FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                // Keeps the lit surfaces from shadowing themselves
+                bias: wgpu::DepthBiasState {
+                    constant: 2,
+                    slope_scale: 2.,
+                    clamp: 0.,
+                },
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        })
+}
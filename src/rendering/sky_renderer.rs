@@ -1,15 +1,12 @@
-use std::rc::Rc;
-
 use cgmath::Vector2;
 use wgpu::util::DeviceExt;
 
-use super::{texture::Texture, Bindable};
+use super::{texture::ColorBuffer, Bindable};
 use crate::{camera::Camera, context::Context, sky::Sky, utils::as_bytes_slice};
 
 pub struct SkyRenderer {
     render_pipeline: wgpu::RenderPipeline,
     screen_quad: wgpu::Buffer,
-    sky_texture: Rc<Texture>,
 }
 
 impl SkyRenderer {
@@ -28,11 +25,10 @@ impl SkyRenderer {
         attributes: &wgpu::vertex_attr_array![0 => Float32x2],
     };
 
-    pub fn new(context: &Context, sky_texture: Rc<Texture>) -> Self {
+    pub fn new(context: &Context) -> Self {
         let bind_group_layouts = &[
             &Camera::create_bind_group_layout(context),
             &Sky::create_bind_group_layout(context),
-            &Texture::create_bind_group_layout(context),
         ];
 
         let layout = context
@@ -62,7 +58,7 @@ impl SkyRenderer {
                         module: &shader,
                         entry_point: "fs_main",
                         targets: &[Some(wgpu::ColorTargetState {
-                            format: context.surface_config.borrow().format,
+                            format: ColorBuffer::HDR_FORMAT,
                             blend: None,
                             write_mask: wgpu::ColorWrites::ALL,
                         })],
@@ -92,7 +88,6 @@ impl SkyRenderer {
         SkyRenderer {
             render_pipeline,
             screen_quad,
-            sky_texture,
         }
     }
 
@@ -119,7 +114,6 @@ impl SkyRenderer {
         render_pass.set_pipeline(&self.render_pipeline);
         render_pass.set_bind_group(0, camera.get_bind_group(), &[]);
         render_pass.set_bind_group(1, sky.get_bind_group(), &[]);
-        render_pass.set_bind_group(2, self.sky_texture.get_bind_group(), &[]);
         render_pass.set_vertex_buffer(0, self.screen_quad.slice(..));
         render_pass.draw(0..(Self::SCREEN_QUAD_VERTICES.len() as u32), 0..1);
     }
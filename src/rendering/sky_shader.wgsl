@@ -28,24 +28,34 @@ struct SkyUniform {
     sun_direction: vec3<f32>,
     time: f32,
     sun_light: f32,
+    fog_start: f32,
+    fog_end: f32,
 }
 
 @group(1) @binding(0)
 var<uniform> sky_uniform: SkyUniform;
-
-@group(2) @binding(0)
+@group(1) @binding(1)
 var sky_texture: texture_2d<f32>;
-@group(2) @binding(1)
+@group(1) @binding(2)
 var sky_sampler: sampler;
 
+// Gradient of the sky at the time of day, with the sunlight scattered around the sun
+// when it is low on the horizon. The sun disc is drawn by the sky shader only.
+fn sky_color(direction: vec3<f32>) -> vec3<f32> {
+    let uv = vec2(sky_uniform.time * 2., direction.y / -2. + 0.5);
+    let gradient = textureSampleLevel(sky_texture, sky_sampler, uv, 0.).rgb;
+
+    let sun_dot = max(dot(direction, sky_uniform.sun_direction), 0.);
+    let scattering = pow(sun_dot, 8.) * (1. - abs(sky_uniform.sun_direction.y)) * 0.6;
+    return mix(gradient, vec3(1., 0.75, 0.45), scattering);
+}
+
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     let direction = normalize(in.unnormalized_direction);
-    let uv = vec2(sky_uniform.time * 2., direction.y / -2. + 0.5);
-    let sky_color = textureSample(sky_texture, sky_sampler, uv);
 
     let sun_dot = dot(direction, sky_uniform.sun_direction);
     let sunness = max(0., min(1., 1. / 256. / (1. - sun_dot) - 0.1));
-    let sun_color = vec4(1.0, 1.0, 0.9, 1.);
-    return mix(sky_color, sun_color, sunness);
+    let sun_color = vec3(1.0, 1.0, 0.9);
+    return vec4(mix(sky_color(direction), sun_color, sunness), 1.);
 }
@@ -1,13 +1,16 @@
 struct VertexInput {
-    @location(0) val: vec2<u32>,
+    @location(0) val: vec3<u32>,
 }
 
 struct VertexOutput {
     @builtin(position) position: vec4<f32>,
+    // Repeating past 1, the sampler wraps them
     @location(0) tex_coords: vec2<f32>,
     @location(1) sun_light: f32,
     @location(2) block_light: f32,
     @location(3) diffused_light: f32,
+    @location(4) @interpolate(flat) texture_id: u32,
+    @location(5) world_position: vec3<f32>,
 }
 
 struct Camera {
@@ -19,36 +22,39 @@ struct Camera {
 @group(0) @binding(0)
 var<uniform> camera: Camera;
 
+// Offset of each chunk, the draw of a chunk starts at its instance index
 @group(3) @binding(0)
-var<uniform> chunk_offset: vec3<f32>;
+var<storage, read> chunk_offsets: array<vec4<f32>>;
 
 @vertex
-fn vs_main(in: VertexInput) -> VertexOutput {
+fn vs_main(in: VertexInput, @builtin(instance_index) chunk: u32) -> VertexOutput {
     var out: VertexOutput;
+    let chunk_offset = chunk_offsets[chunk].xyz;
 
     let position_x     = (in.val.x) & 0x1FFu;
     let position_y     = (in.val.x >> 9u) & 0x1FFu;
     let position_z     = (in.val.x >> 18u) & 0x1FFu;
-    let tex_coords_x   = (in.val.x >> 27u) & 0x1Fu;
-    let tex_coords_y   = (in.val.y) & 0x1Fu;
-    let texture_id     = (in.val.y >> 5u) & 0xFFFFu;
-    let sun_light      = (in.val.y >> 21u) & 0xFu;
-    let block_light    = (in.val.y >> 25u) & 0xFu;
-    let diffused_light = (in.val.y >> 29u) & 0x3u;
+    let diffused_light = (in.val.x >> 27u) & 0x3u;
+    let tex_coords_x   = (in.val.y) & 0x1FFu;
+    let tex_coords_y   = (in.val.y >> 9u) & 0x1FFu;
+    let texture_id     = (in.val.z) & 0xFFFFu;
+    let sun_light      = (in.val.z >> 16u) & 0x3Fu;
+    let block_light    = (in.val.z >> 22u) & 0x3Fu;
+    let occlusion      = (in.val.z >> 28u) & 0x3u;
 
     let position = vec3(f32(position_x), f32(position_y), f32(position_z)) / 16.;
     let tex_coords = vec2(f32(tex_coords_x), f32(tex_coords_y)) / 16.;
 
-    let tex_base = vec2(
-        f32(texture_id % 4u),
-        f32(texture_id / 4u),
-    ) / 4.;
-
-    out.position = camera.matrix * vec4<f32>(chunk_offset + position, 1.0);
-    out.tex_coords = tex_base + tex_coords / 4.;
-    out.sun_light = f32(sun_light) / 15.;
-    out.block_light = f32(block_light) / 15.;
+    out.world_position = chunk_offset + position;
+    out.position = camera.matrix * vec4<f32>(out.world_position, 1.0);
+    out.tex_coords = tex_coords;
+    out.texture_id = texture_id;
+    // The light levels are in quarters
+    out.sun_light = f32(sun_light) / 60.;
+    out.block_light = f32(block_light) / 60.;
     out.diffused_light = f32(diffused_light) / 3. * 0.6 + 0.4;
+    // Corners between solid blocks are darker
+    out.diffused_light *= f32(occlusion) / 3. * 0.4 + 0.6;
     return out;
 }
 
@@ -56,25 +62,116 @@ struct SkyUniform {
     sun_direction: vec3<f32>,
     time: f32,
     sun_light: f32,
+    fog_start: f32,
+    fog_end: f32,
 }
 
 @group(1) @binding(0)
 var<uniform> sky_uniform: SkyUniform;
+@group(1) @binding(1)
+var sky_texture: texture_2d<f32>;
+@group(1) @binding(2)
+var sky_sampler: sampler;
+
+// Gradient of the sky at the time of day, with the sunlight scattered around the sun
+// when it is low on the horizon. The sun disc is drawn by the sky shader only.
+fn sky_color(direction: vec3<f32>) -> vec3<f32> {
+    let uv = vec2(sky_uniform.time * 2., direction.y / -2. + 0.5);
+    let gradient = textureSampleLevel(sky_texture, sky_sampler, uv, 0.).rgb;
+
+    let sun_dot = max(dot(direction, sky_uniform.sun_direction), 0.);
+    let scattering = pow(sun_dot, 8.) * (1. - abs(sky_uniform.sun_direction.y)) * 0.6;
+    return mix(gradient, vec3(1., 0.75, 0.45), scattering);
+}
+
+// Fades the color into the sky behind it with the horizontal distance from the camera.
+fn apply_fog(color: vec3<f32>, world_position: vec3<f32>) -> vec3<f32> {
+    let offset = world_position - camera.position;
+    let fog = smoothstep(sky_uniform.fog_start, sky_uniform.fog_end, length(offset.xz));
+    return mix(color, sky_color(normalize(offset)), fog);
+}
+
+struct Shadows {
+    matrices: array<mat4x4<f32>, 3>,
+    cascade_ends: vec4<f32>,
+    strength: f32,
+}
+
+@group(1) @binding(3)
+var<uniform> shadows: Shadows;
+@group(1) @binding(4)
+var shadow_map: texture_depth_2d_array;
+@group(1) @binding(5)
+var shadow_sampler: sampler_comparison;
+
+// How much of the sunlight the terrain between the point and the sun blocks, from 0 to 1.
+// The cascade is the first one covering the point, and 3x3 samples soften the edges.
+fn shadow_factor(world_position: vec3<f32>, normal: vec3<f32>) -> f32 {
+    if (shadows.strength == 0.) {
+        return 0.;
+    }
+    // The faces turned away from the sun are in their own shadow
+    let facing = dot(normal, sky_uniform.sun_direction);
+    if (facing <= 0.) {
+        return shadows.strength;
+    }
+
+    let distance = length(world_position - camera.position);
+    if (distance >= shadows.cascade_ends[2]) {
+        return 0.;
+    }
+    var cascade = 2;
+    if (distance < shadows.cascade_ends[0]) {
+        cascade = 0;
+    } else if (distance < shadows.cascade_ends[1]) {
+        cascade = 1;
+    }
+
+    // Moving the point off the face by more at grazing angles avoids shadow acne
+    let offset = normal * (0.02 + 0.04 * f32(cascade)) * (2. - facing);
+    let light_position = shadows.matrices[cascade] * vec4(world_position + offset, 1.);
+    let uv = light_position.xy * vec2(0.5, -0.5) + 0.5;
+
+    let texel = 1. / f32(textureDimensions(shadow_map).x);
+    var lit = 0.;
+    for (var x = -1; x <= 1; x++) {
+        for (var y = -1; y <= 1; y++) {
+            let sample_uv = uv + vec2(f32(x), f32(y)) * texel;
+            lit += textureSampleCompareLevel(shadow_map, shadow_sampler, sample_uv, cascade, light_position.z);
+        }
+    }
+    let shadow = 1. - lit / 9.;
+
+    // Fades out before the end of the last cascade instead of cutting off
+    let fade = 1. - smoothstep(shadows.cascade_ends[2] * 0.8, shadows.cascade_ends[2], distance);
+    return shadow * fade * shadows.strength;
+}
 
 @group(2) @binding(0)
-var texture_test: texture_2d<f32>;
+var texture_test: texture_2d_array<f32>;
 @group(2) @binding(1)
 var sampler_test: sampler;
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    let texture_color = textureSample(texture_test, sampler_test, in.tex_coords);
-    if (texture_color.a == 0.) {
+    // The derivatives give the normal of the face, they must be taken before any discard
+    var normal = normalize(cross(dpdx(in.world_position), dpdy(in.world_position)));
+    if (dot(normal, camera.position - in.world_position) < 0.) {
+        normal = -normal;
+    }
+
+    let texture_color = textureSample(texture_test, sampler_test, in.tex_coords, in.texture_id);
+    // The mipmaps blend the edges of the cutouts
+    if (texture_color.a < 0.5) {
         discard;
     }
 
-    let world_light_unmapped = in.diffused_light * max(sky_uniform.sun_light * in.sun_light, in.block_light);
+    let shadow = shadow_factor(in.world_position, normal);
+
+    let sun_light = sky_uniform.sun_light * in.sun_light * (1. - 0.45 * shadow);
+    let world_light_unmapped = in.diffused_light * max(sun_light, in.block_light);
     let world_light = world_light_unmapped * world_light_unmapped;
 
-    return world_light * texture_color;
+    let color = apply_fog(world_light * texture_color.rgb, in.world_position);
+    return vec4(color, texture_color.a);
 }
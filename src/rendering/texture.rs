@@ -1,12 +1,15 @@
 use std::rc::Rc;
 
 use cgmath::Vector2;
+use image::RgbaImage;
 
 use crate::context::Context;
 
 use super::Bindable;
 
 pub struct Texture {
+    view: wgpu::TextureView,
+    sampler: wgpu::Sampler,
     bind_group: wgpu::BindGroup,
 }
 
@@ -78,7 +81,20 @@ impl Texture {
                 ],
             });
 
-        Texture { bind_group }
+        Texture {
+            view,
+            sampler,
+            bind_group,
+        }
+    }
+
+    /// For the bind groups that combine the texture with other resources.
+    pub fn get_view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+
+    pub fn get_sampler(&self) -> &wgpu::Sampler {
+        &self.sampler
     }
 }
 
@@ -114,6 +130,236 @@ impl Bindable for Texture {
     }
 }
 
+/// How the block textures are sampled, mipmaps are used by all the modes.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, clap::ValueEnum)]
+pub enum TextureFiltering {
+    /// Sharp pixels, close and far
+    Nearest,
+    /// Sharp pixels up close, blended mipmaps in the distance
+    Trilinear,
+    /// Smooth textures, keeping the detail of the surfaces seen at grazing angles.
+    /// Falls back to trilinear if the adapter does not support it.
+    Anisotropic,
+}
+
+impl TextureFiltering {
+    const MAX_ANISOTROPY: u16 = 16;
+
+    fn sampler_descriptor(self, context: &Context) -> wgpu::SamplerDescriptor<'static> {
+        let anisotropic_supported = context
+            .adapter
+            .get_downlevel_capabilities()
+            .flags
+            .contains(wgpu::DownlevelFlags::ANISOTROPIC_FILTERING);
+        let mode = match self {
+            TextureFiltering::Anisotropic if !anisotropic_supported => {
+                log::warn!("Anisotropic filtering is not supported, using trilinear filtering");
+                TextureFiltering::Trilinear
+            }
+            mode => mode,
+        };
+
+        let (mag_filter, min_filter, anisotropy_clamp) = match mode {
+            TextureFiltering::Nearest => (wgpu::FilterMode::Nearest, wgpu::FilterMode::Nearest, 1),
+            TextureFiltering::Trilinear => (wgpu::FilterMode::Nearest, wgpu::FilterMode::Linear, 1),
+            // Anisotropic filtering requires linear filters everywhere
+            TextureFiltering::Anisotropic => (
+                wgpu::FilterMode::Linear,
+                wgpu::FilterMode::Linear,
+                Self::MAX_ANISOTROPY,
+            ),
+        };
+        let mipmap_filter = match mode {
+            TextureFiltering::Nearest => wgpu::FilterMode::Nearest,
+            _ => wgpu::FilterMode::Linear,
+        };
+
+        wgpu::SamplerDescriptor {
+            label: Some("Texture Array Sampler"),
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            address_mode_w: wgpu::AddressMode::Repeat,
+            mag_filter,
+            min_filter,
+            mipmap_filter,
+            anisotropy_clamp,
+            ..Default::default()
+        }
+    }
+}
+
+/// Halves the size of the image, weighting the colors by their alpha so that the
+/// transparent pixels do not darken the edges of the cutouts.
+fn downsample(image: &RgbaImage) -> RgbaImage {
+    let (width, height) = ((image.width() / 2).max(1), (image.height() / 2).max(1));
+    RgbaImage::from_fn(width, height, |x, y| {
+        let mut color = [0u32; 3];
+        let mut alpha = 0u32;
+        for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+            let x = (x * 2 + dx).min(image.width() - 1);
+            let y = (y * 2 + dy).min(image.height() - 1);
+            let pixel = image.get_pixel(x, y).0;
+            for i in 0..3 {
+                color[i] += pixel[i] as u32 * pixel[3] as u32;
+            }
+            alpha += pixel[3] as u32;
+        }
+
+        if alpha == 0 {
+            return image::Rgba([0, 0, 0, 0]);
+        }
+        image::Rgba([
+            (color[0] / alpha) as u8,
+            (color[1] / alpha) as u8,
+            (color[2] / alpha) as u8,
+            (alpha / 4) as u8,
+        ])
+    })
+}
+
+/// Layers of the same size sampled by index, each with its own mipmaps, so that
+/// repeating textures and distant mipmaps never bleed into the neighboring layers.
+pub struct TextureArray {
+    view: wgpu::TextureView,
+    sampler: wgpu::Sampler,
+    bind_group: wgpu::BindGroup,
+}
+
+impl TextureArray {
+    /// The layers are square, with a size that is a power of two.
+    pub fn new(
+        context: &Context,
+        label: &str,
+        layers: &[RgbaImage],
+        filtering: TextureFiltering,
+    ) -> TextureArray {
+        let size = layers[0].width();
+        let mip_level_count = size.ilog2() + 1;
+        let texture_size = wgpu::Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: layers.len() as u32,
+        };
+
+        let texture = context.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: texture_size,
+            mip_level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[wgpu::TextureFormat::Rgba8UnormSrgb],
+        });
+
+        for (layer_index, layer) in layers.iter().enumerate() {
+            let mut mip = layer.clone();
+            for mip_level in 0..mip_level_count {
+                if mip_level > 0 {
+                    mip = downsample(&mip);
+                }
+
+                context.queue.write_texture(
+                    wgpu::ImageCopyTexture {
+                        texture: &texture,
+                        mip_level,
+                        origin: wgpu::Origin3d {
+                            x: 0,
+                            y: 0,
+                            z: layer_index as u32,
+                        },
+                        aspect: wgpu::TextureAspect::All,
+                    },
+                    &mip,
+                    wgpu::ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: Some(4 * mip.width()),
+                        rows_per_image: Some(mip.height()),
+                    },
+                    wgpu::Extent3d {
+                        width: mip.width(),
+                        height: mip.height(),
+                        depth_or_array_layers: 1,
+                    },
+                );
+            }
+        }
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        let sampler = context
+            .device
+            .create_sampler(&filtering.sampler_descriptor(context));
+
+        let bind_group = context
+            .device
+            .create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some(&format!("{} Bind Group", label)),
+                layout: &TextureArray::create_bind_group_layout(context),
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&sampler),
+                    },
+                ],
+            });
+
+        TextureArray {
+            view,
+            sampler,
+            bind_group,
+        }
+    }
+
+    /// For the bind groups that combine the texture array with other resources.
+    pub fn get_view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+
+    pub fn get_sampler(&self) -> &wgpu::Sampler {
+        &self.sampler
+    }
+}
+
+impl Bindable for TextureArray {
+    fn create_bind_group_layout(context: &Context) -> wgpu::BindGroupLayout {
+        context
+            .device
+            .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Texture Array Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            multisampled: false,
+                            view_dimension: wgpu::TextureViewDimension::D2Array,
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                ],
+            })
+    }
+
+    fn get_bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+}
+
+/// Can be read by the shaders of the passes that do not write to it.
 pub struct DepthBuffer {
     context: Rc<Context>,
     label: String,
@@ -158,10 +404,91 @@ impl DepthBuffer {
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
             format: Self::FORMAT,
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
             view_formats: &[Self::FORMAT],
         });
 
         texture.create_view(&wgpu::TextureViewDescriptor::default())
     }
 }
+
+/// Color target the size of the window, rendered to before it is drawn to the screen,
+/// and copied from to read what is behind the transparent surfaces.
+pub struct ColorBuffer {
+    context: Rc<Context>,
+    label: String,
+    format: wgpu::TextureFormat,
+    texture: wgpu::Texture,
+    texture_view: wgpu::TextureView,
+}
+
+impl ColorBuffer {
+    /// Of the scene, which can be brighter than the screen before the tone mapping.
+    pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+    pub fn new(
+        context: Rc<Context>,
+        label: &str,
+        format: wgpu::TextureFormat,
+        size: Vector2<u32>,
+    ) -> Self {
+        let texture = Self::create_texture(&context, label, format, size);
+        let texture_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        ColorBuffer {
+            context,
+            label: label.to_string(),
+            format,
+            texture,
+            texture_view,
+        }
+    }
+
+    pub fn resize(&mut self, size: Vector2<u32>) {
+        self.texture = Self::create_texture(&self.context, &self.label, self.format, size);
+        self.texture_view = self
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+    }
+
+    pub fn get_texture(&self) -> &wgpu::Texture {
+        &self.texture
+    }
+
+    pub fn get_texture_view(&self) -> &wgpu::TextureView {
+        &self.texture_view
+    }
+
+    pub fn format(&self) -> wgpu::TextureFormat {
+        self.format
+    }
+
+    pub fn size(&self) -> Vector2<u32> {
+        let size = self.texture.size();
+        Vector2::new(size.width, size.height)
+    }
+
+    fn create_texture(
+        context: &Context,
+        label: &str,
+        format: wgpu::TextureFormat,
+        size: Vector2<u32>,
+    ) -> wgpu::Texture {
+        context.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: size.x,
+                height: size.y,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC
+                | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[format],
+        })
+    }
+}
@@ -26,6 +26,12 @@ impl<T> Uniform<T> {
         }
     }
 
+    /// For the bind groups that combine the uniform with other resources.
+    #[inline]
+    pub fn as_binding(&self) -> wgpu::BindingResource<'_> {
+        self.buffer.as_entire_binding()
+    }
+
     #[inline]
     pub fn write(&self, value: T) {
         self.context
@@ -1,15 +1,17 @@
 struct VertexInput {
-    @location(0) val: vec2<u32>,
+    @location(0) val: vec3<u32>,
 }
 
 struct VertexOutput {
     @builtin(position) position: vec4<f32>,
+    // Repeating past 1, the sampler wraps them
     @location(0) tex_coords: vec2<f32>,
     @location(1) sun_light: f32,
     @location(2) block_light: f32,
     @location(3) diffused_light: f32,
 
     @location(4) world_position: vec3<f32>,
+    @location(5) @interpolate(flat) texture_id: u32,
 }
 
 struct Camera {
@@ -21,36 +23,35 @@ struct Camera {
 @group(0) @binding(0)
 var<uniform> camera: Camera;
 
+// Offset of each chunk, the draw of a chunk starts at its instance index
 @group(3) @binding(0)
-var<uniform> chunk_offset: vec3<f32>;
+var<storage, read> chunk_offsets: array<vec4<f32>>;
 
 @vertex
-fn vs_main(in: VertexInput) -> VertexOutput {
+fn vs_main(in: VertexInput, @builtin(instance_index) chunk: u32) -> VertexOutput {
     var out: VertexOutput;
+    let chunk_offset = chunk_offsets[chunk].xyz;
 
     let position_x     = (in.val.x) & 0x1FFu;
     let position_y     = (in.val.x >> 9u) & 0x1FFu;
     let position_z     = (in.val.x >> 18u) & 0x1FFu;
-    let tex_coords_x   = (in.val.x >> 27u) & 0x1Fu;
-    let tex_coords_y   = (in.val.y) & 0x1Fu;
-    let texture_id     = (in.val.y >> 5u) & 0xFFFFu;
-    let sun_light      = (in.val.y >> 21u) & 0xFu;
-    let block_light    = (in.val.y >> 25u) & 0xFu;
-    let diffused_light = (in.val.y >> 29u) & 0x3u;
+    let diffused_light = (in.val.x >> 27u) & 0x3u;
+    let tex_coords_x   = (in.val.y) & 0x1FFu;
+    let tex_coords_y   = (in.val.y >> 9u) & 0x1FFu;
+    let texture_id     = (in.val.z) & 0xFFFFu;
+    let sun_light      = (in.val.z >> 16u) & 0x3Fu;
+    let block_light    = (in.val.z >> 22u) & 0x3Fu;
 
     let position = vec3(f32(position_x), f32(position_y), f32(position_z)) / 16.;
     let tex_coords = vec2(f32(tex_coords_x), f32(tex_coords_y)) / 16.;
 
-    let tex_base = vec2(
-        f32(texture_id % 4u),
-        f32(texture_id / 4u),
-    ) / 4.;
-
     out.world_position = chunk_offset + position;
     out.position = camera.matrix * vec4<f32>(out.world_position, 1.0);
-    out.tex_coords = tex_base + tex_coords / 4.;
-    out.sun_light = f32(sun_light) / 15.;
-    out.block_light = f32(block_light) / 15.;
+    out.tex_coords = tex_coords;
+    out.texture_id = texture_id;
+    // The light levels are in quarters
+    out.sun_light = f32(sun_light) / 60.;
+    out.block_light = f32(block_light) / 60.;
     out.diffused_light = f32(diffused_light) / 3. * 0.6 + 0.4;
     return out;
 }
@@ -99,36 +100,234 @@ fn perlin(seed: vec2<f32>) -> f32 {
     return unmapped;
 }
 
-fn normal_at(pos: vec2<f32>) -> vec3<f32> {
-    let seed = pos * 4.;
-    return normalize(vec3<f32>(perlin(seed), 10., perlin(seed)));
+// Same as DAY_LENGTH_SECS, the time of the sky is in days
+const DAY_LENGTH_SECS: f32 = 600.;
+
+// Two layers of noise drifting in different directions
+fn wave_height(pos: vec2<f32>, seconds: f32) -> f32 {
+    let large = perlin(pos * 0.8 + vec2(0.3, 0.2) * seconds);
+    let small = perlin(pos * 1.9 + vec2(-0.25, 0.35) * seconds);
+    return large + 0.5 * small;
+}
+
+// Normal of the animated surface of the water, facing up.
+fn wave_normal(pos: vec2<f32>) -> vec3<f32> {
+    let seconds = sky_uniform.time * DAY_LENGTH_SECS;
+    let step = 0.05;
+    let height = wave_height(pos, seconds);
+    let slope_x = (wave_height(pos + vec2(step, 0.), seconds) - height) / step;
+    let slope_z = (wave_height(pos + vec2(0., step), seconds) - height) / step;
+    return normalize(vec3(-slope_x * 0.15, 1., -slope_z * 0.15));
 }
 
 struct SkyUniform {
     sun_direction: vec3<f32>,
     time: f32,
     sun_light: f32,
+    fog_start: f32,
+    fog_end: f32,
 }
 
 @group(1) @binding(0)
 var<uniform> sky_uniform: SkyUniform;
+@group(1) @binding(1)
+var sky_texture: texture_2d<f32>;
+@group(1) @binding(2)
+var sky_sampler: sampler;
+
+// Gradient of the sky at the time of day, with the sunlight scattered around the sun
+// when it is low on the horizon. The sun disc is drawn by the sky shader only.
+fn sky_color(direction: vec3<f32>) -> vec3<f32> {
+    let uv = vec2(sky_uniform.time * 2., direction.y / -2. + 0.5);
+    let gradient = textureSampleLevel(sky_texture, sky_sampler, uv, 0.).rgb;
+
+    let sun_dot = max(dot(direction, sky_uniform.sun_direction), 0.);
+    let scattering = pow(sun_dot, 8.) * (1. - abs(sky_uniform.sun_direction.y)) * 0.6;
+    return mix(gradient, vec3(1., 0.75, 0.45), scattering);
+}
+
+// Fades the color into the sky behind it with the horizontal distance from the camera.
+fn apply_fog(color: vec3<f32>, world_position: vec3<f32>) -> vec3<f32> {
+    let offset = world_position - camera.position;
+    let fog = smoothstep(sky_uniform.fog_start, sky_uniform.fog_end, length(offset.xz));
+    return mix(color, sky_color(normalize(offset)), fog);
+}
+
+struct Shadows {
+    matrices: array<mat4x4<f32>, 3>,
+    cascade_ends: vec4<f32>,
+    strength: f32,
+}
+
+@group(1) @binding(3)
+var<uniform> shadows: Shadows;
+@group(1) @binding(4)
+var shadow_map: texture_depth_2d_array;
+@group(1) @binding(5)
+var shadow_sampler: sampler_comparison;
+
+// How much of the sunlight the terrain between the point and the sun blocks, from 0 to 1.
+// The cascade is the first one covering the point, and 3x3 samples soften the edges.
+fn shadow_factor(world_position: vec3<f32>, normal: vec3<f32>) -> f32 {
+    if (shadows.strength == 0.) {
+        return 0.;
+    }
+    // The faces turned away from the sun are in their own shadow
+    let facing = dot(normal, sky_uniform.sun_direction);
+    if (facing <= 0.) {
+        return shadows.strength;
+    }
+
+    let distance = length(world_position - camera.position);
+    if (distance >= shadows.cascade_ends[2]) {
+        return 0.;
+    }
+    var cascade = 2;
+    if (distance < shadows.cascade_ends[0]) {
+        cascade = 0;
+    } else if (distance < shadows.cascade_ends[1]) {
+        cascade = 1;
+    }
+
+    // Moving the point off the face by more at grazing angles avoids shadow acne
+    let offset = normal * (0.02 + 0.04 * f32(cascade)) * (2. - facing);
+    let light_position = shadows.matrices[cascade] * vec4(world_position + offset, 1.);
+    let uv = light_position.xy * vec2(0.5, -0.5) + 0.5;
+
+    let texel = 1. / f32(textureDimensions(shadow_map).x);
+    var lit = 0.;
+    for (var x = -1; x <= 1; x++) {
+        for (var y = -1; y <= 1; y++) {
+            let sample_uv = uv + vec2(f32(x), f32(y)) * texel;
+            lit += textureSampleCompareLevel(shadow_map, shadow_sampler, sample_uv, cascade, light_position.z);
+        }
+    }
+    let shadow = 1. - lit / 9.;
+
+    // Fades out before the end of the last cascade instead of cutting off
+    let fade = 1. - smoothstep(shadows.cascade_ends[2] * 0.8, shadows.cascade_ends[2], distance);
+    return shadow * fade * shadows.strength;
+}
 
 @group(2) @binding(0)
-var texture_test: texture_2d<f32>;
+var block_textures: texture_2d_array<f32>;
 @group(2) @binding(1)
-var sampler_test: sampler;
+var block_sampler: sampler;
+// The terrain and the sky drawn before the water
+@group(2) @binding(2)
+var behind_texture: texture_2d<f32>;
+@group(2) @binding(3)
+var behind_sampler: sampler;
+@group(2) @binding(4)
+var depth_texture: texture_depth_2d;
+
+// How fast each color fades through a block of water, the red first
+const WATER_ABSORPTION: vec3<f32> = vec3<f32>(0.4, 0.12, 0.08);
+
+// Position on the screen from 0 to 1 and depth, off the screen behind the camera.
+fn to_screen(world_position: vec3<f32>) -> vec3<f32> {
+    let clip = camera.matrix * vec4(world_position, 1.);
+    if (clip.w <= 0.) {
+        return vec3(-1.);
+    }
+    let ndc = clip.xyz / clip.w;
+    return vec3(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5, ndc.z);
+}
+
+fn scene_depth(uv: vec2<f32>) -> f32 {
+    let size = vec2<f32>(textureDimensions(depth_texture));
+    let coords = clamp(vec2<i32>(uv * size), vec2(0), vec2<i32>(size) - 1);
+    return textureLoad(depth_texture, coords, 0);
+}
+
+fn scene_position(uv: vec2<f32>, depth: f32) -> vec3<f32> {
+    let position = camera.inverse_matrix * vec4(uv.x * 2. - 1., 1. - uv.y * 2., depth, 1.);
+    return position.xyz / position.w + camera.position;
+}
+
+// Marches along the reflected ray over the depth buffer. Returns the color of the terrain
+// it hits, weighted by how far from the edges of the screen, or a weight of 0 when it
+// leaves the screen or passes behind the terrain.
+fn trace_reflection(origin: vec3<f32>, direction: vec3<f32>) -> vec4<f32> {
+    var distance = 0.;
+    var step = 0.25;
+    for (var i = 0; i < 32; i++) {
+        distance += step;
+        step *= 1.15;
+
+        let position = origin + direction * distance;
+        let screen = to_screen(position);
+        if (any(screen.xy < vec2(0.)) || any(screen.xy > vec2(1.)) || screen.z > 1.) {
+            return vec4(0.);
+        }
+
+        let depth = scene_depth(screen.xy);
+        if (screen.z > depth) {
+            if (length(scene_position(screen.xy, depth) - position) > step * 2.) {
+                return vec4(0.);
+            }
+            let color = textureSampleLevel(behind_texture, behind_sampler, screen.xy, 0.).rgb;
+            let edge = min(min(screen.x, 1. - screen.x), min(screen.y, 1. - screen.y));
+            return vec4(color, smoothstep(0., 0.1, edge));
+        }
+    }
+    return vec4(0.);
+}
 
 @fragment
-fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    let normal = normal_at(in.world_position.xz);
+fn fs_main(in: VertexOutput, @builtin(front_facing) outside: bool) -> @location(0) vec4<f32> {
+    // The derivatives give the normal of the face, turned towards the camera
+    var normal = normalize(cross(dpdx(in.world_position), dpdy(in.world_position)));
+    if (dot(normal, camera.position - in.world_position) < 0.) {
+        normal = -normal;
+    }
+    // The waves are on the surface only
+    if (abs(normal.y) > 0.5) {
+        normal = wave_normal(in.world_position.xz) * sign(normal.y);
+    }
+
+    let texture_color = textureSample(block_textures, block_sampler, in.tex_coords, in.texture_id).rgb;
+
+    let shadow = shadow_factor(in.world_position, vec3(0., 1., 0.));
+    let sun_light = sky_uniform.sun_light * in.sun_light * (1. - 0.45 * shadow);
+    let world_light_unmapped = in.diffused_light * max(sun_light, in.block_light);
+    let water_color = world_light_unmapped * world_light_unmapped * texture_color;
+
+    // What is behind the water, bent by the waves. The terrain in front of the water
+    // is not bent into it.
+    let screen_uv = in.position.xy / vec2<f32>(textureDimensions(behind_texture));
+    var refracted_uv = screen_uv + normal.xz * 0.03;
+    if (scene_depth(refracted_uv) < in.position.z) {
+        refracted_uv = screen_uv;
+    }
+    let behind = textureSampleLevel(behind_texture, behind_sampler, refracted_uv, 0.).rgb;
 
     let look_dir = normalize(in.world_position - camera.position);
-    let reflected = reflect(look_dir, normal);
-    let specular_light = 1. + pow(max(dot(sky_uniform.sun_direction, reflected), 0.), 128.);
+    let facing = max(dot(-look_dir, normal), 0.);
+
+    var color: vec3<f32>;
+    if (outside) {
+        // The deeper the water behind the surface, the less of the terrain goes through
+        let behind_position = scene_position(refracted_uv, scene_depth(refracted_uv));
+        let thickness = distance(behind_position, in.world_position);
+        let transmittance = exp(-thickness * WATER_ABSORPTION);
+        let refracted = behind * transmittance + water_color * (1. - transmittance);
+
+        let reflected_dir = reflect(look_dir, normal);
+        let hit = trace_reflection(in.world_position, reflected_dir);
+        let reflected = mix(sky_color(reflected_dir), hit.rgb, hit.a);
+
+        let sun_visible = sky_uniform.sun_light * (1. - shadow) * step(0., sky_uniform.sun_direction.y);
+        let specular = pow(max(dot(sky_uniform.sun_direction, reflected_dir), 0.), 128.) * sun_visible;
 
-    let world_light_unmapped = in.diffused_light * max(sky_uniform.sun_light * in.sun_light, in.block_light);
-    let world_light = world_light_unmapped * world_light_unmapped * specular_light;
+        // Schlick's approximation, the water reflects more at grazing angles
+        let fresnel = 0.02 + 0.98 * pow(1. - facing, 5.);
+        color = mix(refracted, reflected, fresnel) + specular;
+    } else {
+        // Seen from below, the surface reflects everything past the critical angle
+        let internal_reflection = smoothstep(0.25, 0.4, 1. - facing);
+        color = mix(behind, water_color, 0.25 + 0.75 * internal_reflection);
+    }
 
-    let texture_color = textureSample(texture_test, sampler_test, in.tex_coords).xyz;
-    return vec4<f32>(world_light * texture_color, 0.8);
+    return vec4(apply_fog(color, in.world_position), 1.);
 }
@@ -0,0 +1,211 @@
+use std::{
+    cell::{Ref, RefCell},
+    collections::HashMap,
+    rc::Rc,
+    time::Instant,
+};
+
+use cgmath::{MetricSpace, Vector3, Zero};
+use mycraft_world::{
+    aabb::Aabb,
+    consts::MAX_UPDATE_TIME,
+    get_chunk_and_block_coords,
+    mesh::{ChunkMeshes, Face},
+    to_chunk_offset, BlockCoords, Chunk, ChunkCoords, World,
+};
+
+use crate::{camera::Camera, context::Context};
+
+use super::{
+    chunk_buffers::{ChunkBuffers, DrawList},
+    frustrum::Frustrum,
+    world_renderer::{ChunkGraphics, ChunkGraphicsData},
+};
+
+/// GPU side of the loaded chunks, built from the meshes produced by the world.
+pub struct WorldGraphics {
+    buffers: Rc<RefCell<ChunkBuffers>>,
+
+    chunks: HashMap<ChunkCoords, Rc<ChunkGraphics>>,
+    /// Chunks with graphics from the closest to the farthest from the camera
+    sorted_chunks: Vec<(ChunkCoords, Rc<ChunkGraphics>)>,
+    needs_sort: bool,
+    /// Chunks in the frustrum from the farthest to the closest
+    render_queue: Vec<Rc<ChunkGraphics>>,
+    /// Chunks of the render queue, the closest first to skip the hidden fragments early
+    solid_draws: DrawList,
+    /// Chunks of the render queue, the farthest first to blend the closest water last
+    water_draws: DrawList,
+
+    prev_cam_chunk_coords: ChunkCoords,
+    prev_cam_block_coords: BlockCoords,
+}
+
+fn chunk_aabb(coords: ChunkCoords) -> Aabb {
+    Aabb {
+        start: to_chunk_offset(coords),
+        size: [Chunk::SIZE as f32; 3].into(),
+    }
+}
+
+impl WorldGraphics {
+    pub fn new(context: Rc<Context>) -> Self {
+        WorldGraphics {
+            buffers: Rc::new(RefCell::new(ChunkBuffers::new(context.clone()))),
+
+            chunks: HashMap::new(),
+            sorted_chunks: Vec::new(),
+            needs_sort: false,
+            render_queue: Vec::new(),
+            solid_draws: DrawList::new(context.clone(), "Solid Chunk Draws"),
+            water_draws: DrawList::new(context, "Water Chunk Draws"),
+
+            prev_cam_chunk_coords: Vector3::zero(),
+            prev_cam_block_coords: Vector3::zero(),
+        }
+    }
+
+    pub fn update(&mut self, world: &mut World, camera: &Camera) {
+        puffin::profile_function!();
+
+        let update_start = Instant::now();
+
+        // The unloaded chunks come first, the new meshes may belong to reloaded ones
+        for coords in world.take_unloaded_chunks() {
+            if self.chunks.remove(&coords).is_some() {
+                self.needs_sort = true;
+            }
+        }
+
+        for (coords, meshes) in world.take_new_meshes() {
+            match self.create_chunk_graphics(coords, meshes) {
+                Some(graphics) => self.chunks.insert(coords, graphics),
+                None => self.chunks.remove(&coords),
+            };
+            self.needs_sort = true;
+        }
+
+        let (cam_chunk_coords, cam_block_coords) = get_chunk_and_block_coords(camera.position);
+        if cam_chunk_coords != self.prev_cam_chunk_coords {
+            self.needs_sort = true;
+            self.prev_cam_chunk_coords = cam_chunk_coords;
+        }
+
+        if cam_block_coords != self.prev_cam_block_coords {
+            for graphics in self.chunks.values() {
+                graphics.graphics_data.borrow_mut().water_faces_unsorted = true;
+            }
+            self.prev_cam_block_coords = cam_block_coords;
+        }
+
+        if self.needs_sort {
+            puffin::profile_scope!("Chunk graphics sort");
+
+            self.sorted_chunks = self
+                .chunks
+                .iter()
+                .map(|(&coords, graphics)| (coords, graphics.clone()))
+                .collect();
+            self.sorted_chunks
+                .sort_unstable_by_key(|x| cam_chunk_coords.distance2(x.0));
+            self.needs_sort = false;
+        }
+
+        puffin::profile_scope!("Render queue update");
+
+        let frustrum = camera.get_frustrum();
+        self.render_queue.clear();
+        for (coords, graphics) in self.sorted_chunks.iter() {
+            if !frustrum.intersects_with_aabb(&chunk_aabb(*coords)) {
+                continue;
+            }
+
+            // The closest chunks are sorted first, the others wait for the next frames
+            if graphics.needs_water_faces_sorting()
+                && Instant::now() - update_start < MAX_UPDATE_TIME
+            {
+                let relative_cam_pos = camera.position - to_chunk_offset(*coords);
+                graphics.sort_water_faces(relative_cam_pos);
+            }
+
+            self.render_queue.push(graphics.clone());
+        }
+        self.render_queue.reverse();
+
+        self.solid_draws.update(
+            self.render_queue
+                .iter()
+                .rev()
+                .map(|graphics| graphics.allocation.solid_draw()),
+        );
+        self.water_draws.update(
+            self.render_queue
+                .iter()
+                .map(|graphics| graphics.allocation.water_draw()),
+        );
+    }
+
+    fn create_chunk_graphics(
+        &self,
+        coords: ChunkCoords,
+        meshes: ChunkMeshes,
+    ) -> Option<Rc<ChunkGraphics>> {
+        puffin::profile_function!();
+
+        if meshes.water_vertices.is_empty() && meshes.solid_vertices.is_empty() {
+            return None;
+        }
+
+        let Some(allocation) = self.buffers.borrow_mut().allocate(
+            &meshes.solid_vertices,
+            &Face::generate_default_indices(meshes.solid_vertices.len() / 4),
+            &meshes.water_vertices,
+            &Face::generate_indices(&meshes.water_faces),
+            to_chunk_offset(coords).extend(0.),
+        ) else {
+            log::warn!(
+                "The meshes of chunk {:?} do not fit in the chunk buffers",
+                coords
+            );
+            return None;
+        };
+
+        Some(Rc::new(ChunkGraphics {
+            buffers: self.buffers.clone(),
+            allocation,
+
+            graphics_data: RefCell::new(ChunkGraphicsData {
+                water_faces: meshes.water_faces,
+                water_faces_unsorted: true,
+            }),
+        }))
+    }
+
+    /// Returns the chunks seen through the frustrum, which can be another than the camera's.
+    pub fn chunks_in_frustrum<'a>(
+        &'a self,
+        frustrum: &'a Frustrum,
+    ) -> impl Iterator<Item = &'a ChunkGraphics> {
+        self.sorted_chunks
+            .iter()
+            .filter(|(coords, _)| frustrum.intersects_with_aabb(&chunk_aabb(*coords)))
+            .map(|(_, graphics)| graphics.as_ref())
+    }
+
+    /// The vertices, the indices and the offsets of all the chunks, for the draw lists.
+    pub fn buffers(&self) -> Ref<'_, ChunkBuffers> {
+        self.buffers.borrow()
+    }
+
+    pub fn solid_draws(&self) -> &DrawList {
+        &self.solid_draws
+    }
+
+    pub fn water_draws(&self) -> &DrawList {
+        &self.water_draws
+    }
+
+    pub fn num_chunks_rendered(&self) -> usize {
+        self.render_queue.len()
+    }
+}
@@ -1,129 +1,33 @@
 use std::{cell::RefCell, rc::Rc};
 
-use cgmath::{MetricSpace, Vector2, Vector3};
-use wgpu::util::DeviceExt;
+use cgmath::{MetricSpace, Vector3};
+use mycraft_world::mesh::{Face, Vertex};
 
-use crate::{camera::Camera, context::Context, sky::Sky, utils::as_bytes_slice};
+use crate::{camera::Camera, context::Context, sky::Sky};
 
 use super::{
-    texture::{DepthBuffer, Texture},
-    uniform::Uniform,
-    Bindable, RenderTargetWithDepth,
+    chunk_buffers::{ChunkAllocation, ChunkBuffers},
+    texture::{ColorBuffer, DepthBuffer, TextureArray},
+    world_graphics::WorldGraphics,
+    Bindable,
 };
 
-#[derive(Clone, Copy)]
-#[repr(C)]
-pub struct Vertex(u32, u32);
-
-pub struct VertexDesc {
-    pub pos: Vector3<u16>,
-    pub texture_id: u16,
-    pub texture_coords: Vector2<u8>,
-    pub diffused_light: u8,
-    pub sun_light: u8,
-    pub block_light: u8,
-}
-
-impl Vertex {
-    const BUFFER_LAYOUT: wgpu::VertexBufferLayout<'static> = wgpu::VertexBufferLayout {
-        array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
-        step_mode: wgpu::VertexStepMode::Vertex,
-        attributes: &wgpu::vertex_attr_array![0 => Uint32x2],
-    };
-
-    #[inline]
-    pub fn new(desc: VertexDesc) -> Self {
-        Vertex(
-            ((desc.pos.x as u32) & 0x1FF)
-                | (((desc.pos.y as u32) & 0x1FF) << 9)
-                | (((desc.pos.z as u32) & 0x1FF) << 18)
-                | (((desc.texture_coords.x as u32) & 0x1F) << 27),
-            ((desc.texture_coords.y as u32) & 0x1F)
-                | ((desc.texture_id as u32) << 5)
-                | (((desc.sun_light as u32) & 0xF) << 21)
-                | (((desc.block_light as u32) & 0xF) << 25)
-                | (((desc.diffused_light as u32) & 0x3) << 29),
-        )
-    }
-}
-
-pub struct ChunkMesh {
-    context: Rc<Context>,
-    pub vertices: wgpu::Buffer,
-    pub indices: wgpu::Buffer,
-    pub index_count: u32,
-}
-
-impl ChunkMesh {
-    pub fn new(context: Rc<Context>, label: &str, vertices: &[Vertex], indices: &[u32]) -> Self {
-        let index_count = indices.len() as u32;
-        let vertices = context
-            .device
-            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some(&format!("{} Vertices", label)),
-                usage: wgpu::BufferUsages::VERTEX,
-                contents: as_bytes_slice(vertices),
-            });
-        let indices = context
-            .device
-            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some(&format!("{} Indices", label)),
-                usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_DST,
-                contents: as_bytes_slice(indices),
-            });
-
-        ChunkMesh {
-            context,
-            vertices,
-            indices,
-            index_count,
-        }
-    }
-
-    pub fn write_indices(&self, indices: &[u32]) {
-        assert_eq!(indices.len() as u32, self.index_count);
-        self.context
-            .queue
-            .write_buffer(&self.indices, 0, as_bytes_slice(indices));
-    }
-}
-
-pub struct Face {
-    pub base_index: u32,
-    pub center: Vector3<f32>,
-    pub distance: f32,
-}
-
-impl Face {
-    const VERTEX_INDICES: [u32; 6] = [0, 1, 2, 2, 1, 3];
-
-    pub fn generate_default_indices(face_count: usize) -> Vec<u32> {
-        Self::VERTEX_INDICES
-            .iter()
-            .cycle()
-            .enumerate()
-            .map(|(i, x)| x + (i as u32 / 6) * 4)
-            .take(face_count * 6)
-            .collect()
-    }
-
-    pub fn generate_indices(faces: &[Face]) -> Vec<u32> {
-        faces
-            .iter()
-            .flat_map(|face| Self::VERTEX_INDICES.iter().map(|x| x + face.base_index))
-            .collect()
-    }
-}
+pub const VERTEX_BUFFER_LAYOUT: wgpu::VertexBufferLayout<'static> = wgpu::VertexBufferLayout {
+    array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
+    step_mode: wgpu::VertexStepMode::Vertex,
+    attributes: &wgpu::vertex_attr_array![0 => Uint32x3],
+};
 
 pub struct ChunkGraphicsData {
     pub water_faces: Vec<Face>,
     pub water_faces_unsorted: bool,
 }
 
+/// Meshes of a chunk, in the buffers shared by all the chunks. Frees its part of the
+/// buffers when dropped.
 pub struct ChunkGraphics {
-    pub solid_mesh: ChunkMesh,
-    pub water_mesh: ChunkMesh,
-    pub offset: Uniform<Vector3<f32>>,
+    pub buffers: Rc<RefCell<ChunkBuffers>>,
+    pub allocation: ChunkAllocation,
 
     pub graphics_data: RefCell<ChunkGraphicsData>,
 }
@@ -146,60 +50,161 @@ impl ChunkGraphics {
 
         data.water_faces
             .sort_by(|x, y| y.distance.total_cmp(&x.distance));
-        self.water_mesh
-            .write_indices(&Face::generate_indices(&data.water_faces));
+        self.buffers.borrow().write_indices(
+            self.allocation.water_indices.clone(),
+            &Face::generate_indices(&data.water_faces),
+        );
+    }
+}
+
+impl Drop for ChunkGraphics {
+    fn drop(&mut self) {
+        self.buffers.borrow_mut().free(self.allocation.clone());
     }
 }
 
 pub struct WorldRenderer {
+    context: Rc<Context>,
     solid_block_pipeline: wgpu::RenderPipeline,
     water_pipeline: wgpu::RenderPipeline,
-    blocks_texture: Rc<Texture>,
+    blocks_texture: Rc<TextureArray>,
+    /// Copy of the terrain and the sky behind the water, refracted and reflected by it
+    behind_water: ColorBuffer,
+    behind_water_sampler: wgpu::Sampler,
+    water_bind_group: wgpu::BindGroup,
 }
 
 impl WorldRenderer {
-    pub fn new(context: &Context, blocks_texture: Rc<Texture>) -> Self {
+    pub fn new(
+        context: Rc<Context>,
+        blocks_texture: Rc<TextureArray>,
+        target: &ColorBuffer,
+        depth_buffer: &DepthBuffer,
+    ) -> Self {
         let solid_block_pipeline = create_world_pipeline(
-            context,
+            &context,
             WorldPipelineDesc {
                 label: "Solid Block Render Pipeline",
                 blend: wgpu::BlendState::REPLACE,
                 shader: wgpu::include_wgsl!("solid_block_shader.wgsl"),
+                textures_layout: TextureArray::create_bind_group_layout(&context),
                 cull_mode: Some(wgpu::Face::Back),
                 depth_write_enabled: true,
             },
         );
 
+        // The water is opaque, it blends what is behind it by itself. The faces are sorted
+        // from back to front, so the closest one is kept.
         let water_pipeline = create_world_pipeline(
-            context,
+            &context,
             WorldPipelineDesc {
                 label: "Water Block Render Pipeline",
-                blend: wgpu::BlendState::ALPHA_BLENDING,
+                blend: wgpu::BlendState::REPLACE,
                 shader: wgpu::include_wgsl!("water_shader.wgsl"),
+                textures_layout: create_water_bind_group_layout(&context),
                 cull_mode: None,
                 depth_write_enabled: false,
             },
         );
 
+        let behind_water = ColorBuffer::new(
+            context.clone(),
+            "Behind Water Buffer",
+            target.format(),
+            target.size(),
+        );
+        let behind_water_sampler = context.device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Behind Water Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let water_bind_group = create_water_bind_group(
+            &context,
+            &blocks_texture,
+            &behind_water,
+            &behind_water_sampler,
+            depth_buffer,
+        );
+
         WorldRenderer {
+            context,
             solid_block_pipeline,
             water_pipeline,
             blocks_texture,
+            behind_water,
+            behind_water_sampler,
+            water_bind_group,
         }
     }
 
+    /// Follows the size of the target, after it and the depth buffer are resized.
+    pub fn resize(&mut self, target: &ColorBuffer, depth_buffer: &DepthBuffer) {
+        self.behind_water.resize(target.size());
+        self.water_bind_group = create_water_bind_group(
+            &self.context,
+            &self.blocks_texture,
+            &self.behind_water,
+            &self.behind_water_sampler,
+            depth_buffer,
+        );
+    }
+
+    /// Draws the solid blocks, then the water over a copy of them.
     pub fn draw<'a>(
         &'a self,
         encoder: &'a mut wgpu::CommandEncoder,
-        target: RenderTargetWithDepth<'a>,
+        target: &'a ColorBuffer,
+        depth_buffer: &'a DepthBuffer,
         camera: &'a Camera,
-        chunks: impl Iterator<Item = &'a ChunkGraphics> + Clone,
+        world_graphics: &'a WorldGraphics,
         sky: &'a Sky,
     ) {
+        let buffers = world_graphics.buffers();
+
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Solid Block Render Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: target.get_texture_view(),
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: depth_buffer.get_texture_view(),
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: true,
+                    }),
+                    stencil_ops: None,
+                }),
+            });
+
+            render_pass.set_pipeline(&self.solid_block_pipeline);
+            render_pass.set_bind_group(0, camera.get_bind_group(), &[]);
+            render_pass.set_bind_group(1, sky.get_bind_group(), &[]);
+            render_pass.set_bind_group(2, self.blocks_texture.get_bind_group(), &[]);
+
+            buffers.bind(&mut render_pass, 3);
+            world_graphics.solid_draws().draw(&mut render_pass);
+        }
+
+        encoder.copy_texture_to_texture(
+            target.get_texture().as_image_copy(),
+            self.behind_water.get_texture().as_image_copy(),
+            target.get_texture().size(),
+        );
+
+        // The depth buffer is read-only, the water shader samples it
         let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-            label: Some("World Render Pass"),
+            label: Some("Water Render Pass"),
             color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: target.color,
+                view: target.get_texture_view(),
                 resolve_target: None,
                 ops: wgpu::Operations {
                     load: wgpu::LoadOp::Load,
@@ -207,44 +212,110 @@ impl WorldRenderer {
                 },
             })],
             depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                view: target.depth,
-                depth_ops: Some(wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(1.0),
-                    store: true,
-                }),
+                view: depth_buffer.get_texture_view(),
+                depth_ops: None,
                 stencil_ops: None,
             }),
         });
 
-        render_pass.set_pipeline(&self.solid_block_pipeline);
+        render_pass.set_pipeline(&self.water_pipeline);
         render_pass.set_bind_group(0, camera.get_bind_group(), &[]);
         render_pass.set_bind_group(1, sky.get_bind_group(), &[]);
-        render_pass.set_bind_group(2, self.blocks_texture.get_bind_group(), &[]);
-
-        for chunk in chunks.clone() {
-            render_pass.set_bind_group(3, chunk.offset.get_bind_group(), &[]);
+        render_pass.set_bind_group(2, &self.water_bind_group, &[]);
 
-            render_pass.set_vertex_buffer(0, chunk.solid_mesh.vertices.slice(..));
-            render_pass.set_index_buffer(
-                chunk.solid_mesh.indices.slice(..),
-                wgpu::IndexFormat::Uint32,
-            );
-            render_pass.draw_indexed(0..chunk.solid_mesh.index_count, 0, 0..1);
-        }
-
-        render_pass.set_pipeline(&self.water_pipeline);
+        buffers.bind(&mut render_pass, 3);
+        world_graphics.water_draws().draw(&mut render_pass);
+    }
+}
 
-        for chunk in chunks {
-            render_pass.set_bind_group(3, chunk.offset.get_bind_group(), &[]);
+/// The block textures, with the color and the depth of what is behind the water.
+fn create_water_bind_group_layout(context: &Context) -> wgpu::BindGroupLayout {
+    context
+        .device
+        .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Water Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2Array,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Depth,
+                    },
+                    count: None,
+                },
+            ],
+        })
+}
 
-            render_pass.set_vertex_buffer(0, chunk.water_mesh.vertices.slice(..));
-            render_pass.set_index_buffer(
-                chunk.water_mesh.indices.slice(..),
-                wgpu::IndexFormat::Uint32,
-            );
-            render_pass.draw_indexed(0..chunk.water_mesh.index_count, 0, 0..1);
-        }
-    }
+fn create_water_bind_group(
+    context: &Context,
+    blocks_texture: &TextureArray,
+    behind_water: &ColorBuffer,
+    behind_water_sampler: &wgpu::Sampler,
+    depth_buffer: &DepthBuffer,
+) -> wgpu::BindGroup {
+    context
+        .device
+        .create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Water Bind Group"),
+            layout: &create_water_bind_group_layout(context),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(blocks_texture.get_view()),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(blocks_texture.get_sampler()),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(behind_water.get_texture_view()),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Sampler(behind_water_sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::TextureView(depth_buffer.get_texture_view()),
+                },
+            ],
+        })
 }
 
 struct WorldPipelineDesc<'a> {
@@ -253,14 +324,16 @@ struct WorldPipelineDesc<'a> {
     cull_mode: Option<wgpu::Face>,
     depth_write_enabled: bool,
     shader: wgpu::ShaderModuleDescriptor<'a>,
+    /// Layout of the textures at the group 2
+    textures_layout: wgpu::BindGroupLayout,
 }
 
 fn create_world_pipeline(context: &Context, desc: WorldPipelineDesc) -> wgpu::RenderPipeline {
     let bind_group_layouts = &[
         &Camera::create_bind_group_layout(context),
         &Sky::create_bind_group_layout(context),
-        &Texture::create_bind_group_layout(context),
-        &Uniform::<Vector3<f32>>::create_bind_group_layout(context),
+        &desc.textures_layout,
+        &ChunkBuffers::create_bind_group_layout(context),
     ];
 
     let layout = context
@@ -281,13 +354,13 @@ fn create_world_pipeline(context: &Context, desc: WorldPipelineDesc) -> wgpu::Re
             vertex: wgpu::VertexState {
                 module: &shader,
                 entry_point: "vs_main",
-                buffers: &[Vertex::BUFFER_LAYOUT],
+                buffers: &[VERTEX_BUFFER_LAYOUT],
             },
             fragment: Some(wgpu::FragmentState {
                 module: &shader,
                 entry_point: "fs_main",
                 targets: &[Some(wgpu::ColorTargetState {
-                    format: context.surface_config.borrow().format,
+                    format: ColorBuffer::HDR_FORMAT,
                     blend: Some(desc.blend),
                     write_mask: wgpu::ColorWrites::ALL,
                 })],
@@ -1,12 +1,19 @@
 use std::{path::Path, rc::Rc};
 
-use anyhow::Result;
-use image::DynamicImage;
+use anyhow::{bail, Result};
+use image::{DynamicImage, RgbaImage};
+use mycraft_world::{
+    blocks::{load_texture_names, BlockRegistry},
+    generation::StructureRegistry,
+};
 
-use crate::{context::Context, rendering::texture::Texture};
+use crate::{
+    context::Context,
+    rendering::texture::{Texture, TextureArray, TextureFiltering},
+};
 
 pub struct Resources {
-    pub blocks_texture: Rc<Texture>,
+    pub blocks_texture: Rc<TextureArray>,
     pub sky_texture: Rc<Texture>,
 }
 
@@ -21,15 +28,64 @@ fn load_image(path: &Path, name: &str) -> Result<DynamicImage> {
     Ok(image)
 }
 
+/// Loads the block textures in the order of their ids. They are square, all of the same
+/// size, and that size is a power of two for the mipmaps.
+fn load_block_textures(
+    context: &Context,
+    path: &Path,
+    texture_names: &[String],
+) -> Result<Vec<RgbaImage>> {
+    let max_layers = context.device.limits().max_texture_array_layers as usize;
+    if texture_names.is_empty() || texture_names.len() > max_layers {
+        bail!(
+            "Expected between 1 and {} block textures, found {}",
+            max_layers,
+            texture_names.len()
+        );
+    }
+
+    let mut textures = vec![];
+    for name in texture_names {
+        textures.push(load_image(path, &format!("blocks/{}.png", name))?.to_rgba8());
+    }
+
+    let size = textures[0].width();
+    if !size.is_power_of_two() {
+        bail!(
+            "The size of the block textures {} is not a power of two",
+            size
+        );
+    }
+    for (texture, name) in textures.iter().zip(texture_names) {
+        if texture.dimensions() != (size, size) {
+            bail!(
+                "Block texture {} is not {}x{} like the others",
+                name,
+                size,
+                size
+            );
+        }
+    }
+    Ok(textures)
+}
+
 fn load_texture(context: &Context, path: &Path, name: &str) -> Result<Texture> {
     let image = load_image(path, name)?;
     Ok(Texture::new(context, name, image))
 }
 
 impl Resources {
-    pub fn try_load(context: &Context, path: impl AsRef<Path>) -> Result<Self> {
+    pub fn try_load(
+        context: &Context,
+        path: impl AsRef<Path>,
+        filtering: TextureFiltering,
+    ) -> Result<Self> {
         let path = path.as_ref();
 
+        let texture_names = load_texture_names(&path.join("textures").join("blocks"))?;
+        BlockRegistry::load(&path.join("blocks.ron"), &texture_names)?.install()?;
+        StructureRegistry::load(&path.join("structures.ron"))?.install()?;
+
         macro_rules! load_texture {
             ($name:literal) => {
                 Rc::new(load_texture(context, path, $name)?)
@@ -37,7 +93,12 @@ impl Resources {
         }
 
         Ok(Resources {
-            blocks_texture: load_texture!("blocks.png"),
+            blocks_texture: Rc::new(TextureArray::new(
+                context,
+                "blocks",
+                &load_block_textures(context, path, &texture_names)?,
+                filtering,
+            )),
             sky_texture: load_texture!("sky.png"),
         })
     }
@@ -5,12 +5,16 @@ use cgmath::{Vector3, Zero};
 use crate::{
     consts::*,
     context::Context,
-    rendering::{uniform::Uniform, Bindable},
+    rendering::{shadows::ShadowMap, texture::Texture, uniform::Uniform, Bindable},
 };
 
+/// State of the sky shared by the sky and the world shaders, with the sky gradient
+/// texture so that the fog matches the sky behind the terrain, and the shadows of the sun.
 pub struct Sky {
     uniform: Uniform<SkyUniform>,
+    bind_group: wgpu::BindGroup,
     time: f32,
+    view_distance: f32,
 }
 
 #[repr(C, align(16))]
@@ -18,25 +22,72 @@ pub struct SkyUniform {
     pub sun_direction: Vector3<f32>,
     pub time: f32,
     pub sun_light: f32,
+    /// Horizontal distance from the camera at which the fog starts
+    pub fog_start: f32,
+    /// Horizontal distance from the camera past which only the fog is visible
+    pub fog_end: f32,
 }
 
 impl Sky {
-    pub fn new(context: Rc<Context>) -> Self {
+    pub fn new(context: Rc<Context>, texture: &Texture, shadow_map: &ShadowMap) -> Self {
         let uniform = SkyUniform {
             sun_direction: Vector3::zero(),
             time: 0.,
             sun_light: 1.,
+            fog_start: 0.,
+            fog_end: 0.,
         };
+        let uniform = Uniform::new(context.clone(), "Sky Uniform", uniform);
+
+        let bind_group = context
+            .device
+            .create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Sky Bind Group"),
+                layout: &Sky::create_bind_group_layout(&context),
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: uniform.as_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(texture.get_view()),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::Sampler(texture.get_sampler()),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: shadow_map.as_uniform_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 4,
+                        resource: wgpu::BindingResource::TextureView(shadow_map.get_view()),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 5,
+                        resource: wgpu::BindingResource::Sampler(shadow_map.get_sampler()),
+                    },
+                ],
+            });
 
         Sky {
-            uniform: Uniform::new(context, "Sky Uniform", uniform),
+            uniform,
+            bind_group,
             time: 0.,
+            view_distance: 0.,
         }
     }
 
-    fn get_uniform_data(&self) -> SkyUniform {
+    /// Direction from the ground towards the sun, below the horizon at night.
+    pub fn sun_direction(&self) -> Vector3<f32> {
         let angle = self.time * 2. * PI;
-        let sun_direction = Vector3::new(0., angle.cos(), angle.sin());
+        Vector3::new(0., angle.cos(), angle.sin())
+    }
+
+    fn get_uniform_data(&self) -> SkyUniform {
+        let sun_direction = self.sun_direction();
 
         let dayness = ((0.5 * PI * sun_direction.y).sin() + 1.) / 2.;
         let sun_light = dayness * dayness * (1. - MIDNIGHT_SUNLIGHT) + MIDNIGHT_SUNLIGHT;
@@ -45,9 +96,16 @@ impl Sky {
             sun_direction,
             time: self.time,
             sun_light,
+            fog_start: self.view_distance * FOG_START,
+            fog_end: self.view_distance,
         }
     }
 
+    /// Sets the horizontal distance in blocks at which the world fades into the sky.
+    pub fn set_view_distance(&mut self, view_distance: f32) {
+        self.view_distance = view_distance;
+    }
+
     pub fn update(&mut self, delta: std::time::Duration) {
         self.time += delta.as_secs_f32() / DAY_LENGTH_SECS;
         self.uniform.write(self.get_uniform_data());
@@ -56,11 +114,69 @@ impl Sky {
 
 impl Bindable for Sky {
     fn create_bind_group_layout(context: &Context) -> wgpu::BindGroupLayout {
-        Uniform::<SkyUniform>::create_bind_group_layout(context)
+        context
+            .device
+            .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Sky Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            multisampled: false,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 4,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            multisampled: false,
+                            view_dimension: wgpu::TextureViewDimension::D2Array,
+                            sample_type: wgpu::TextureSampleType::Depth,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 5,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+                        count: None,
+                    },
+                ],
+            })
     }
 
     #[inline]
     fn get_bind_group(&self) -> &wgpu::BindGroup {
-        self.uniform.get_bind_group()
+        &self.bind_group
     }
 }
@@ -1,6 +1,4 @@
-pub mod aabb;
 pub mod input;
-pub mod raycasting;
 
 #[inline]
 pub(crate) fn as_bytes<T>(value: &T) -> &[u8] {